@@ -0,0 +1,96 @@
+use crate::gamelog::{GameLog, LogCategory};
+use crate::monster_ai_system::HUNT_TURNS;
+use crate::spawner::SpawnQueue;
+use crate::{AiMode, AiState, Boss, CombatStats, Map, MyTurn, Name, Point, Position, RunState};
+use specs::prelude::*;
+
+/// Turns a boss waits between calls for help.
+const SUMMON_COOLDOWN: i32 = 5;
+
+/// What sets bosses apart from the rank and file. Once awake a boss always
+/// knows where the player is, and below half its hit points it spends its
+/// turn calling a minion onto a free tile next to it whenever it can. Runs
+/// before `MonsterAI`, which takes care of the chasing and fighting.
+pub struct BossAI {}
+
+impl<'a> System<'a> for BossAI {
+    type SystemData = (
+        WriteExpect<'a, Map>,
+        ReadExpect<'a, Point>,
+        ReadExpect<'a, RunState>,
+        Entities<'a>,
+        WriteStorage<'a, Boss>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, AiState>,
+        WriteStorage<'a, MyTurn>,
+        ReadStorage<'a, Name>,
+        WriteExpect<'a, SpawnQueue>,
+        WriteExpect<'a, GameLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut map,
+            player_pos,
+            runstate,
+            entities,
+            mut bosses,
+            combat_stats,
+            positions,
+            mut ai_states,
+            mut my_turn,
+            names,
+            mut spawns,
+            mut log,
+        ) = data;
+
+        if *runstate != RunState::MonsterTurn {
+            return;
+        }
+
+        let mut summoned = Vec::new();
+        for (entity, boss, stats, pos, _my_turn) in
+            (&entities, &mut bosses, &combat_stats, &positions, &my_turn).join()
+        {
+            boss.cooldown = i32::max(0, boss.cooldown - 1);
+
+            let free_tile = if stats.hp * 2 < stats.max_hp && boss.cooldown == 0 {
+                map.open_neighbor(pos.x, pos.y)
+            } else {
+                None
+            };
+            if let Some(tile) = free_tile {
+                spawns.request(&boss.minion, tile.x, tile.y);
+                // Keep the tile for the minion until the map is indexed again
+                let idx = map.xy_idx(tile.x, tile.y);
+                map.blocked[idx] = true;
+                boss.cooldown = SUMMON_COOLDOWN;
+                if map.visible_tiles[map.xy_idx(pos.x, pos.y)] {
+                    if let Some(name) = names.get(entity) {
+                        log.add(
+                            LogCategory::Combat,
+                            format!("{} calls for help!", name.name),
+                        );
+                    }
+                }
+                summoned.push(entity);
+                continue;
+            }
+
+            if let Some(ai) = ai_states.get_mut(entity) {
+                if ai.mode != AiMode::Sleeping {
+                    ai.mode = AiMode::Hunting {
+                        last_known: *player_pos,
+                        turns: HUNT_TURNS,
+                    };
+                }
+            }
+        }
+
+        // Calling for help takes the whole turn
+        for entity in summoned {
+            my_turn.remove(entity);
+        }
+    }
+}
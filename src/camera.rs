@@ -0,0 +1,197 @@
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+
+use crate::colors::Theme;
+use crate::gui::UiState;
+use crate::lighting_system::LightMap;
+use crate::map::{Map, TileType};
+use crate::{FieldOfView, Player};
+
+/// Size of the part of the screen the map is drawn into. The UI panel takes
+/// up the rows below it.
+pub const VIEW_WIDTH: i32 = 80;
+pub const VIEW_HEIGHT: i32 = 43;
+
+/// World coordinates of the tile in the top left corner of the screen. The
+/// view is centered on the player, but stops at the edges of the map so no
+/// more of the screen than necessary is left empty.
+pub fn origin(ecs: &World) -> Point {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+
+    Point::new(
+        view_start(player_pos.x, map.width, VIEW_WIDTH),
+        view_start(player_pos.y, map.height, VIEW_HEIGHT),
+    )
+}
+
+fn view_start(center: i32, map_size: i32, view_size: i32) -> i32 {
+    i32::clamp(center - view_size / 2, 0, i32::max(map_size - view_size, 0))
+}
+
+/// Whether a screen position falls inside the map view.
+pub fn in_view(screen_x: i32, screen_y: i32) -> bool {
+    (0..VIEW_WIDTH).contains(&screen_x) && (0..VIEW_HEIGHT).contains(&screen_y)
+}
+
+/// Whether the tile at `idx` gets drawn: once the player has seen it, or
+/// always in wizard mode.
+pub fn tile_drawn(map: &Map, idx: usize, wizard_mode: bool) -> bool {
+    wizard_mode || map.revealed_tiles[idx]
+}
+
+/// Whether what stands on the tile at `idx` gets drawn: while the player can
+/// see it, or always in wizard mode.
+pub fn contents_drawn(map: &Map, idx: usize, wizard_mode: bool) -> bool {
+    wizard_mode || map.visible_tiles[idx]
+}
+
+/// Draws the part of the map that's in view.
+pub fn render_camera(ecs: &World, ctx: &mut BTerm) {
+    let map = ecs.fetch::<Map>();
+    let light_map = ecs.fetch::<LightMap>();
+    let theme = ecs.fetch::<Theme>();
+    let wizard_mode = ecs.fetch::<UiState>().wizard_mode;
+    let origin = origin(ecs);
+
+    // Nothing to see without someone to see it
+    let players = ecs.read_storage::<Player>();
+    let fovs = ecs.read_storage::<FieldOfView>();
+    if (&players, &fovs).join().next().is_none() {
+        return;
+    }
+
+    for screen_y in 0..VIEW_HEIGHT {
+        for screen_x in 0..VIEW_WIDTH {
+            let (x, y) = (origin.x + screen_x, origin.y + screen_y);
+            if x >= map.width || y >= map.height {
+                continue;
+            }
+
+            let idx = map.xy_idx(x, y);
+            if !tile_drawn(&map, idx, wizard_mode) {
+                continue;
+            }
+
+            let glyph;
+            let mut fg;
+            let mut bg = RGBA::from_u8(0, 0, 0, 255);
+            match map.tiles[idx] {
+                TileType::Floor => {
+                    glyph = to_cp437('█');
+                    fg = if map.bloodstains.contains(&idx) {
+                        theme.bloodstain
+                    } else {
+                        theme.floor
+                    };
+                }
+                TileType::Wall => {
+                    glyph = wall_glyph(&map, x, y, wizard_mode);
+                    fg = theme.wall;
+                }
+                // Only wizards see through secret doors
+                TileType::SecretDoor if wizard_mode => {
+                    glyph = to_cp437('+');
+                    fg = theme.wall;
+                }
+                TileType::SecretDoor => {
+                    glyph = wall_glyph(&map, x, y, wizard_mode);
+                    fg = theme.wall;
+                }
+                TileType::DownStairs => {
+                    glyph = to_cp437('>');
+                    fg = theme.stairs;
+                    bg = theme.floor;
+                }
+                TileType::UpStairs => {
+                    glyph = to_cp437('<');
+                    fg = theme.stairs;
+                    bg = theme.floor;
+                }
+                TileType::Door => {
+                    glyph = if map.open_doors[idx] {
+                        to_cp437('\'')
+                    } else {
+                        to_cp437('+')
+                    };
+                    fg = theme.door;
+                    bg = theme.floor;
+                }
+                TileType::ShallowWater => {
+                    glyph = to_cp437('~');
+                    fg = theme.shallow_water;
+                    bg = theme.shallow_water_bg;
+                }
+                TileType::DeepWater => {
+                    glyph = to_cp437('≈');
+                    fg = theme.deep_water;
+                    bg = theme.deep_water_bg;
+                }
+                TileType::Lava => {
+                    glyph = to_cp437('≈');
+                    fg = theme.lava;
+                    bg = theme.lava_bg;
+                }
+            }
+            if map.visible_tiles[idx] {
+                fg = lit(fg, light_map.brightness(idx));
+            } else {
+                fg = fg.lerp(BLACK.into(), 0.5);
+                bg = bg.lerp(BLACK.into(), 0.5);
+            }
+            ctx.set(screen_x, screen_y, fg, bg, glyph);
+        }
+    }
+}
+
+fn lit(color: RGBA, brightness: RGB) -> RGBA {
+    RGBA::from_f32(
+        f32::min(1.0, color.r * brightness.r),
+        f32::min(1.0, color.g * brightness.g),
+        f32::min(1.0, color.b * brightness.b),
+        color.a,
+    )
+}
+
+/// Picks the line drawing character that joins a wall up with the walls
+/// around it. Only walls that get drawn count, so unexplored rock doesn't give
+/// away its shape outside wizard mode. Beyond the map edge counts as wall.
+fn wall_glyph(map: &Map, x: i32, y: i32, wizard_mode: bool) -> FontCharType {
+    let mut mask = 0;
+    if is_drawn_wall(map, x, y - 1, wizard_mode) {
+        mask += 1;
+    }
+    if is_drawn_wall(map, x, y + 1, wizard_mode) {
+        mask += 2;
+    }
+    if is_drawn_wall(map, x - 1, y, wizard_mode) {
+        mask += 4;
+    }
+    if is_drawn_wall(map, x + 1, y, wizard_mode) {
+        mask += 8;
+    }
+
+    match mask {
+        1..=3 => to_cp437('║'),
+        4 | 8 | 12 => to_cp437('═'),
+        5 => to_cp437('╝'),
+        6 => to_cp437('╗'),
+        7 => to_cp437('╣'),
+        9 => to_cp437('╚'),
+        10 => to_cp437('╔'),
+        11 => to_cp437('╠'),
+        13 => to_cp437('╩'),
+        14 => to_cp437('╦'),
+        15 => to_cp437('╬'),
+        _ => to_cp437('○'),
+    }
+}
+
+fn is_drawn_wall(map: &Map, x: i32, y: i32, wizard_mode: bool) -> bool {
+    if x < 0 || x >= map.width || y < 0 || y >= map.height {
+        return true;
+    }
+
+    let idx = map.xy_idx(x, y);
+    map.is_wall(idx) && tile_drawn(map, idx, wizard_mode)
+}
@@ -1,5 +1,15 @@
+use bracket_lib::prelude::*;
+use serde::{Deserialize, Serialize};
+
 use crate::RGBA;
 
+/// Where players can pick a theme, in RON: either the name of a built-in one,
+/// like `HighContrast`, or a whole theme of their own, written the way `Theme`
+/// is. Without the file, or if it can't be made sense of, the game looks the
+/// classic way.
+const THEME_FILE: &str = "theme.ron";
+
+/// What things on the floor stand on, whatever the theme draws the floor with.
 pub const FLOOR_COLOR: RGBA = RGBA {
     r: 0.3,
     g: 0.3,
@@ -13,3 +23,102 @@ pub const TRANSPARENT_COLOR: RGBA = RGBA {
     b: 0.3,
     a: 0.0,
 };
+
+/// The colors the map and the interface are drawn with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Theme {
+    pub floor: RGBA,
+    pub bloodstain: RGBA,
+    pub wall: RGBA,
+    pub stairs: RGBA,
+    pub door: RGBA,
+    pub shallow_water: RGBA,
+    pub shallow_water_bg: RGBA,
+    pub deep_water: RGBA,
+    pub deep_water_bg: RGBA,
+    pub lava: RGBA,
+    pub lava_bg: RGBA,
+    /// Ordinary interface text and box borders
+    pub text: RGB,
+    /// Titles, key hints and other things to pick out
+    pub highlight: RGB,
+    /// The menu entry currently chosen
+    pub selected: RGB,
+    /// Whatever can't be chosen or doesn't matter right now
+    pub dimmed: RGB,
+    pub background: RGB,
+}
+
+/// The themes that come with the game.
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub enum BuiltinTheme {
+    Classic,
+    HighContrast,
+}
+
+impl Theme {
+    pub fn builtin(theme: BuiltinTheme) -> Theme {
+        match theme {
+            BuiltinTheme::Classic => Theme::classic(),
+            BuiltinTheme::HighContrast => Theme::high_contrast(),
+        }
+    }
+
+    /// Grey floors between blue walls.
+    pub fn classic() -> Theme {
+        Theme {
+            floor: FLOOR_COLOR,
+            bloodstain: RGBA::from_f32(0.4, 0.05, 0.05, 1.0),
+            wall: RGBA::from_u8(70, 110, 180, 255),
+            stairs: RGBA::named(CYAN),
+            door: RGBA::named(CHOCOLATE),
+            shallow_water: RGBA::named(LIGHT_BLUE),
+            shallow_water_bg: RGBA::from_f32(0.2, 0.4, 0.7, 1.0),
+            deep_water: RGBA::named(BLUE),
+            deep_water_bg: RGBA::from_f32(0.05, 0.15, 0.5, 1.0),
+            lava: RGBA::named(ORANGE),
+            lava_bg: RGBA::from_f32(0.7, 0.15, 0.0, 1.0),
+            text: RGB::named(WHITE),
+            highlight: RGB::named(YELLOW),
+            selected: RGB::named(MAGENTA),
+            dimmed: RGB::named(GRAY),
+            background: RGB::named(BLACK),
+        }
+    }
+
+    /// Dark floors, bright walls and features that don't rely on telling
+    /// red from green or blue from purple.
+    pub fn high_contrast() -> Theme {
+        Theme {
+            floor: RGBA::from_f32(0.12, 0.12, 0.12, 1.0),
+            bloodstain: RGBA::from_f32(0.35, 0.2, 0.0, 1.0),
+            wall: RGBA::named(WHITE),
+            stairs: RGBA::named(YELLOW),
+            door: RGBA::named(ORANGE),
+            shallow_water: RGBA::named(WHITE),
+            shallow_water_bg: RGBA::from_f32(0.0, 0.35, 0.8, 1.0),
+            deep_water: RGBA::named(WHITE),
+            deep_water_bg: RGBA::from_f32(0.0, 0.1, 0.45, 1.0),
+            lava: RGBA::named(BLACK),
+            lava_bg: RGBA::named(ORANGE),
+            text: RGB::named(WHITE),
+            highlight: RGB::named(YELLOW),
+            selected: RGB::named(CYAN),
+            dimmed: RGB::named(LIGHT_GRAY),
+            background: RGB::named(BLACK),
+        }
+    }
+
+    /// The theme picked in the theme file, or the classic one.
+    pub fn load() -> Theme {
+        std::fs::read_to_string(THEME_FILE)
+            .ok()
+            .and_then(|text| {
+                ron::from_str::<BuiltinTheme>(&text)
+                    .map(Theme::builtin)
+                    .or_else(|_| ron::from_str::<Theme>(&text))
+                    .ok()
+            })
+            .unwrap_or_else(Theme::classic)
+    }
+}
@@ -1,32 +1,142 @@
 use bracket_lib::prelude::*;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
+use specs::saveload::{ConvertSaveload, Marker};
 use specs_derive::*;
+use std::collections::HashMap;
+use std::convert::Infallible as NoError;
 
-#[derive(Component, Debug)]
+use crate::difficulty::DifficultyLevel;
+use crate::gamelog::GameLog;
+use crate::inventory_system::KnownItems;
+use crate::map::{LevelStore, Map};
+use crate::quest_system::Quests;
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Player {}
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Monster {}
 
-#[derive(Component, Debug)]
+/// Builds up by `speed` every time the player takes a turn, and is spent to
+/// act. Anything faster than the player gets extra turns, anything slower
+/// misses some.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Initiative {
+    pub current: i32,
+    pub speed: i32,
+}
+
+/// Gets to act in this pass of the monster turn
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct MyTurn {}
+
+/// Who it sides with. How factions feel about each other is in the raws.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Faction {
+    pub name: String,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum AiMode {
+    /// Stays put until it sees or hears the player
+    Sleeping,
+    /// Ambles about at random
+    Wandering,
+    /// Heads for where the player was last seen or heard, giving up after a
+    /// while without a sign of them
+    Hunting { last_known: Point, turns: i32 },
+}
+
+/// What a monster is up to when no enemy is in sight
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct AiState {
+    pub mode: AiMode,
+}
+
+/// Hunts without letup, calls `minion`s to its side once badly hurt and
+/// always drops `loot` when killed
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Boss {
+    pub minion: String,
+    pub loot: String,
+    pub cooldown: i32,
+}
+
+/// Vanishes once `turns` more player turns have passed
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Temporary {
+    pub turns: i32,
+}
+
+/// What's left of something that died
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Corpse {}
+
+/// Heals `amount` hp every `interval` player turns while hurt. `counter`
+/// is how many have passed since it last did.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Regeneration {
+    pub amount: i32,
+    pub interval: i32,
+    pub counter: i32,
+}
+
+/// Stepped into shallow water and loses its next turn
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Wading {}
+
+/// Moving quietly, so monsters only notice the player from close by. Each
+/// step takes twice as long, `stepped` marking one that still needs its
+/// extra turn
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Sneaking {
+    pub stepped: bool,
+}
+
+/// Runs from the player once below `threshold` of its max hp
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct CanFlee {
+    pub threshold: f32,
+    pub fleeing: bool,
+}
+
+/// Knows how to open doors
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct DoorUser {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Name {
     pub name: String,
 }
 
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
 }
 
-#[derive(Component)]
+/// Where something is on a level the player isn't on, kept in place of its
+/// `Position` until they come back
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct OtherLevelPosition {
+    pub x: i32,
+    pub y: i32,
+    pub depth: i32,
+}
+
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Renderable {
     pub glyph: FontCharType,
     pub fg: RGB,
     pub bg: RGBA,
+    /// What shows on a shared tile: lower goes on top, so 0 is for the
+    /// player and particles, 1 for monsters, 2 for items and 3 for traps and
+    /// corpses.
+    pub render_order: i32,
 }
 
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct FieldOfView {
     pub visible_tiles: Vec<Point>,
     pub range: i32,
@@ -34,36 +144,450 @@ pub struct FieldOfView {
 }
 
 /// Blocks Movement
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct BlocksTile {}
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct CombatStats {
     pub max_hp: i32,
     pub hp: i32,
     pub defense: i32,
     pub power: i32,
+    /// How good it is at landing blows, set against the target's `dodge`
+    pub attack: i32,
+    pub dodge: i32,
 }
 
-#[derive(Component, Debug, Clone)]
+#[derive(Component, Debug, Clone, ConvertSaveload)]
 pub struct WantsToMelee {
     pub target: Entity,
 }
 
-#[derive(Component, Debug)]
+/// Shoots at things up to `range` tiles away instead of closing in
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct RangedAttacker {
+    pub range: i32,
+    pub damage: i32,
+    /// Turns a hit leaves the target confused for, for casters that addle
+    /// rather than wound.
+    pub confusion: Option<i32>,
+}
+
+#[derive(Component, Debug, Clone, ConvertSaveload)]
+pub struct WantsToShoot {
+    pub target: Entity,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum DamageType {
+    Physical,
+    Fire,
+    Cold,
+    Poison,
+}
+
+/// Takes half damage of the types it resists and double of the types it's
+/// vulnerable to
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Resistances {
+    pub resists: Vec<DamageType>,
+    pub vulnerable: Vec<DamageType>,
+}
+
+/// Damage waiting to be dealt this turn, and whether any of it came from the
+/// player, who's owed the experience if it kills.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct SufferDamage {
-    pub amounts: Vec<i32>,
+    pub amounts: Vec<(i32, DamageType)>,
+    pub by_player: bool,
 }
 
 impl SufferDamage {
-    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32) {
+    pub fn new_damage(
+        store: &mut WriteStorage<SufferDamage>,
+        victim: Entity,
+        amount: i32,
+        kind: DamageType,
+        by_player: bool,
+    ) {
         if let Some(suffering) = store.get_mut(victim) {
-            suffering.amounts.push(amount);
+            suffering.amounts.push((amount, kind));
+            suffering.by_player |= by_player;
         } else {
             let dmg = SufferDamage {
-                amounts: vec![amount],
+                amounts: vec![(amount, kind)],
+                by_player,
             };
             store.insert(victim, dmg).expect("Unable to insert damage");
         }
     }
 }
+
+/// Given the killing blow by the player, who gets the experience for it
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct KilledByPlayer {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Item {}
+
+/// A pile of coins; picking it up empties it into the wallet
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Gold {
+    pub amount: i32,
+}
+
+/// The gold someone has collected so far
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Wallet {
+    pub gold: i32,
+}
+
+/// Opens the locked doors with the same id
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Key {
+    pub id: u32,
+}
+
+/// What an item costs in a shop. Shopkeepers buy things back for half.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Price {
+    pub gold: i32,
+}
+
+/// Someone who trades rather than fights. Their wares are whatever is in
+/// their inventory.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Vendor {}
+
+/// What a friendly sort has to say when bumped into, a line at a time
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Dialogue {
+    pub lines: Vec<String>,
+}
+
+#[derive(Component, Debug, Clone, ConvertSaveload)]
+pub struct InInventory {
+    pub owner: Entity,
+}
+
+#[derive(Component, Debug, Clone, ConvertSaveload)]
+pub struct WantsToPickupItem {
+    pub collected_by: Entity,
+    pub item: Entity,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ProvidesHealing {
+    pub heal_amount: i32,
+}
+
+/// Reveals the layout of the whole level to whoever uses it
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ProvidesMapping {}
+
+/// Moves the user somewhere else on the level: anywhere at all if `random`,
+/// otherwise to the tile they aim it at
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ProvidesTeleport {
+    pub random: bool,
+}
+
+/// Calls up a `creature` to fight at the user's side for `turns` turns
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ProvidesSummon {
+    pub creature: String,
+    pub turns: i32,
+}
+
+/// Reveals what everything the user carries really is
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ProvidesIdentify {}
+
+#[derive(Component, Debug, Clone, ConvertSaveload)]
+pub struct WantsToUseItem {
+    pub item: Entity,
+    pub target: Option<Point>,
+}
+
+#[derive(Component, Debug, Clone, ConvertSaveload)]
+pub struct WantsToThrowItem {
+    pub item: Entity,
+    pub target: Point,
+}
+
+#[derive(Component, Debug, Clone, ConvertSaveload)]
+pub struct WantsToDropItem {
+    pub item: Entity,
+}
+
+/// How heavy something is to carry or throw
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Weight {
+    pub value: f32,
+}
+
+/// What spells are cast with. It comes back a point every player turn.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Mana {
+    pub current: i32,
+    pub max: i32,
+}
+
+/// A spell `owner` knows. Its effects are the same components an item's
+/// would be, and casting it costs `mana_cost`. It can be cast at most once
+/// every `cooldown` turns.
+#[derive(Component, Debug, Clone, ConvertSaveload)]
+pub struct Spell {
+    pub owner: Entity,
+    pub mana_cost: i32,
+    pub cooldown: i32,
+}
+
+/// Turns left until each ability used recently, by name, can be used again
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Cooldowns {
+    pub remaining: HashMap<String, i32>,
+}
+
+/// Used up when used.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Consumable {}
+
+/// Shatters when thrown, splashing everyone around where it lands and
+/// leaving `puddle` behind there, if it names one
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Potion {
+    pub puddle: Option<String>,
+}
+
+/// Carried as one pile of `count` with others of its kind
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Stackable {
+    pub count: i32,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum EquipmentSlot {
+    Melee,
+    Shield,
+    Ranged,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Equippable {
+    pub slot: EquipmentSlot,
+}
+
+#[derive(Component, Debug, Clone, ConvertSaveload)]
+pub struct Equipped {
+    pub owner: Entity,
+    pub slot: EquipmentSlot,
+}
+
+#[derive(Component, Debug, Clone, ConvertSaveload)]
+pub struct WantsToRemoveItem {
+    pub item: Entity,
+}
+
+/// Won't come off once it's been put on
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Cursed {}
+
+/// Goes by `name` until its kind has been identified
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Unidentified {
+    pub name: String,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct MeleePowerBonus {
+    pub power: i32,
+}
+
+/// Hits hard enough to push the target back up to `distance` tiles. Works
+/// on weapons and on monsters that hit hard on their own.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Knockback {
+    pub distance: i32,
+}
+
+/// Takes up more than the one tile, with its `Position` at the top left
+/// corner
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Size {
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Runs with a pack, keeping close to its leader while there's nothing to
+/// chase
+#[derive(Component, Debug, Clone, ConvertSaveload)]
+pub struct PackMember {
+    pub leader: Entity,
+}
+
+/// Raises the wearer's chance of landing a critical hit, in percent
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct CritBonus {
+    pub chance: i32,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct DefenseBonus {
+    pub defense: i32,
+}
+
+/// A bow or the like: shoots up to `range` tiles, using up one of whatever
+/// `Ammo` of the `ammo` kind its wielder carries each time
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct RangedWeapon {
+    pub range: i32,
+    pub damage: i32,
+    pub ammo: String,
+}
+
+/// A bundle of `count` shots, arrows for instance, for ranged weapons that
+/// take `kind`
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Ammo {
+    pub kind: String,
+    pub count: i32,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Ranged {
+    pub range: i32,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct InflictsDamage {
+    pub damage: i32,
+    pub kind: DamageType,
+}
+
+/// Lights up the tiles it can see, brightest close by
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct LightSource {
+    pub range: i32,
+    pub color: RGB,
+}
+
+/// Not drawn or named until the player has spotted it
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Hidden {}
+
+/// Goes off when something steps onto its tile
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct EntryTrigger {}
+
+/// Used up, and deleted, the first time it goes off
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct SingleActivation {}
+
+/// Moved to a new tile this turn
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct EntityMoved {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct AreaOfEffect {
+    pub radius: i32,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Confusion {
+    pub turns: i32,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum EffectKind {
+    Poison,
+    Burning,
+}
+
+impl EffectKind {
+    /// How the log describes someone suffering from it.
+    pub fn adjective(self) -> &'static str {
+        match self {
+            EffectKind::Poison => "poisoned",
+            EffectKind::Burning => "burning",
+        }
+    }
+
+    /// What kind of harm it does every turn.
+    pub fn damage_type(self) -> DamageType {
+        match self {
+            EffectKind::Poison => DamageType::Poison,
+            EffectKind::Burning => DamageType::Fire,
+        }
+    }
+}
+
+/// Takes `magnitude` damage every turn for `turns` turns
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct StatusEffect {
+    pub kind: EffectKind,
+    pub turns: i32,
+    pub magnitude: i32,
+}
+
+/// Weapons that leave a status effect on whoever they hurt
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct InflictsStatus {
+    pub effect: StatusEffect,
+}
+
+/// Stays out of sight for `delay_ms`, then shows for `lifetime_ms`
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ParticleLifetime {
+    pub lifetime_ms: f32,
+    pub delay_ms: f32,
+}
+
+/// What the shots of a ranged weapon or attacker look like in flight
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Projectile {
+    pub glyph: FontCharType,
+    pub fg: RGB,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Experience {
+    pub level: i32,
+    pub xp: i32,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum HungerState {
+    WellFed,
+    Normal,
+    Hungry,
+    Starving,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct HungerClock {
+    pub state: HungerState,
+    pub duration: i32,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ProvidesFood {}
+
+/// Marks the entities that get written to the save file.
+pub struct SerializeMe;
+
+/// Carries the resources that belong in a save file as a temporary entity.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct SerializationHelper {
+    pub map: Map,
+    pub depth: i32,
+    pub log: GameLog,
+    #[serde(default)]
+    pub levels: LevelStore,
+    #[serde(default)]
+    pub known_items: KnownItems,
+    #[serde(default = "Quests::new")]
+    pub quests: Quests,
+    #[serde(default)]
+    pub difficulty: DifficultyLevel,
+}
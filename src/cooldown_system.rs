@@ -0,0 +1,25 @@
+use crate::{Cooldowns, RunState};
+use specs::prelude::*;
+
+/// Brings abilities closer to being ready again, a turn per player turn, and
+/// forgets them once they are.
+pub struct CooldownSystem {}
+
+impl<'a> System<'a> for CooldownSystem {
+    type SystemData = (WriteStorage<'a, Cooldowns>, ReadExpect<'a, RunState>);
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut cooldowns, runstate) = data;
+
+        if *runstate != RunState::PlayerTurn {
+            return;
+        }
+
+        for cooldowns in (&mut cooldowns).join() {
+            for turns in cooldowns.remaining.values_mut() {
+                *turns -= 1;
+            }
+            cooldowns.remaining.retain(|_ability, turns| *turns > 0);
+        }
+    }
+}
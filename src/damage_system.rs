@@ -1,7 +1,33 @@
-use crate::gamelog::GameLog;
-use crate::{CombatStats, Name, Player, SufferDamage};
+use crate::event_log_system::{EventQueue, GameEvent};
+use crate::monster_ai_system::is_hostile;
+use crate::particle_system::ParticleBuilder;
+use crate::raws::raws;
+use crate::screen_effects::ScreenEffects;
+use crate::spawner;
+use crate::{
+    Boss, CombatStats, DamageType, Experience, Faction, KilledByPlayer, Map, Name, PackMember,
+    Player, Position, Renderable, Resistances, RunState, SufferDamage,
+};
+use bracket_lib::prelude::{to_cp437, Point, RandomNumberGenerator, BLACK, GOLD, RGB, RGBA};
 use specs::prelude::*;
-use std::process::exit;
+
+/// Experience a kill is worth per level of the victim.
+const XP_PER_LEVEL: i32 = 100;
+
+/// How much of `amount` damage of `kind` gets through to someone with
+/// `resistances`: half if they resist it, double if they're vulnerable.
+pub fn after_resistance(amount: i32, kind: DamageType, resistances: Option<&Resistances>) -> i32 {
+    match resistances {
+        Some(r) if r.resists.contains(&kind) => amount / 2,
+        Some(r) if r.vulnerable.contains(&kind) => amount * 2,
+        _ => amount,
+    }
+}
+
+/// Total experience the player needs to get past `level`.
+pub fn xp_for_next_level(level: i32) -> i32 {
+    level * 1000
+}
 
 pub struct DamageSystem {}
 
@@ -9,13 +35,55 @@ impl<'a> System<'a> for DamageSystem {
     type SystemData = (
         WriteStorage<'a, CombatStats>,
         WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Position>,
+        WriteExpect<'a, Map>,
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, ScreenEffects>,
+        ReadStorage<'a, Resistances>,
+        WriteStorage<'a, KilledByPlayer>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut stats, mut damage) = data;
+        let (
+            mut stats,
+            mut damage,
+            positions,
+            mut map,
+            entities,
+            player_entity,
+            mut effects,
+            resistances,
+            mut killed_by_player,
+        ) = data;
 
-        for (mut stats, damage) in (&mut stats, &damage).join() {
-            stats.hp -= damage.amounts.iter().sum::<i32>();
+        for (entity, stats, damage, pos, resistances) in (
+            &entities,
+            &mut stats,
+            &damage,
+            (&positions).maybe(),
+            (&resistances).maybe(),
+        )
+            .join()
+        {
+            let total = damage
+                .amounts
+                .iter()
+                .map(|(amount, kind)| after_resistance(*amount, *kind, resistances))
+                .sum::<i32>();
+            stats.hp -= total;
+            if entity == *player_entity && total > 0 {
+                effects.player_hurt(total);
+            }
+            if damage.by_player && stats.hp < 1 {
+                killed_by_player
+                    .insert(entity, KilledByPlayer {})
+                    .expect("Unable to insert kill");
+            }
+            if let Some(pos) = pos.filter(|_| total > 0) {
+                let idx = map.xy_idx(pos.x, pos.y);
+                map.bloodstains.insert(idx);
+            }
         }
 
         damage.clear();
@@ -25,32 +93,144 @@ impl<'a> System<'a> for DamageSystem {
 impl DamageSystem {
     pub fn delete_the_dead(ecs: &mut World) {
         let mut dead: Vec<Entity> = Vec::new();
+        let mut player_died = false;
         {
             let combat_stats = ecs.read_storage::<CombatStats>();
             let players = ecs.read_storage::<Player>();
             let names = ecs.read_storage::<Name>();
             let entities = ecs.entities();
-            let mut log = ecs.write_resource::<GameLog>();
+            let mut events = ecs.write_resource::<EventQueue>();
             for (entity, stats) in (&entities, &combat_stats).join() {
                 if stats.hp < 1 {
-                    let player = players.get(entity);
-                    match player {
-                        None => {
-                            let victim_name = names.get(entity);
-                            if let Some(name) = victim_name {
-                                log.entries.push(format!("{} is dead", name.name));
-                            }
-                            dead.push(entity);
-                        }
-                        Some(_) => {
-                            log.entries.push("You are dead!".to_string());
-                            exit(0);
-                        }
+                    let is_player = players.contains(entity);
+                    if let Some(name) = names.get(entity) {
+                        events.push(GameEvent::Death {
+                            victim: name.clone(),
+                            is_player,
+                        });
                     }
+                    dead.push(entity);
+                    player_died |= is_player;
                 }
             }
         }
 
+        // Only the player's own kills of those hostile to them are worth anything
+        let xp_gained: i32 = {
+            let killed_by_player = ecs.read_storage::<KilledByPlayer>();
+            let factions = ecs.read_storage::<Faction>();
+            let experience = ecs.read_storage::<Experience>();
+            dead.iter()
+                .filter(|victim| killed_by_player.contains(**victim))
+                .filter(|victim| {
+                    let faction = factions.get(**victim).map(|faction| faction.name.as_str());
+                    is_hostile(raws(), faction, "Player")
+                })
+                .map(|victim| experience.get(*victim).map_or(1, |exp| exp.level) * XP_PER_LEVEL)
+                .sum()
+        };
+        if xp_gained > 0 {
+            DamageSystem::grant_xp(ecs, xp_gained);
+        }
+
+        // Bosses always leave something behind
+        let loot: Vec<(String, i32, i32)> = {
+            let bosses = ecs.read_storage::<Boss>();
+            let positions = ecs.read_storage::<Position>();
+            dead.iter()
+                .filter_map(|victim| Some((bosses.get(*victim)?, positions.get(*victim)?)))
+                .map(|(boss, pos)| (boss.loot.clone(), pos.x, pos.y))
+                .collect()
+        };
+
+        // Everyone but the player leaves a body behind
+        let corpses: Vec<(i32, i32, String, RGB)> = {
+            let players = ecs.read_storage::<Player>();
+            let positions = ecs.read_storage::<Position>();
+            let renderables = ecs.read_storage::<Renderable>();
+            let names = ecs.read_storage::<Name>();
+            dead.iter()
+                .filter(|victim| !players.contains(**victim))
+                .filter_map(|victim| {
+                    let pos = positions.get(*victim)?;
+                    let render = renderables.get(*victim)?;
+                    let name = names.get(*victim)?;
+                    Some((pos.x, pos.y, name.name.clone(), render.fg))
+                })
+                .collect()
+        };
+
+        // A pack that's lost its leader breaks up
+        {
+            let entities = ecs.entities();
+            let mut pack_members = ecs.write_storage::<PackMember>();
+            let leaderless: Vec<Entity> = (&entities, &pack_members)
+                .join()
+                .filter(|(_entity, member)| dead.contains(&member.leader))
+                .map(|(entity, _member)| entity)
+                .collect();
+            for member in leaderless {
+                pack_members.remove(member);
+            }
+        }
+
         ecs.delete_entities(&dead).expect("Unable to delete");
+
+        for (x, y, name, fg) in corpses {
+            spawner::corpse(ecs, x, y, &name, fg);
+        }
+        for (name, x, y) in loot {
+            spawner::spawn_entity(ecs, &name, x, y);
+        }
+
+        if player_died {
+            let mut runstate = ecs.write_resource::<RunState>();
+            *runstate = RunState::GameOver;
+        }
+    }
+
+    /// Gives the player experience, levelling them up once they pass
+    /// `level * 1000`.
+    fn grant_xp(ecs: &mut World, amount: i32) {
+        let player_entity = *ecs.fetch::<Entity>();
+        let mut experience = ecs.write_storage::<Experience>();
+        let mut combat_stats = ecs.write_storage::<CombatStats>();
+        let (Some(exp), Some(stats)) = (
+            experience.get_mut(player_entity),
+            combat_stats.get_mut(player_entity),
+        ) else {
+            return;
+        };
+
+        exp.xp += amount;
+        if exp.xp < xp_for_next_level(exp.level) {
+            return;
+        }
+
+        exp.level += 1;
+        let hp_gain = ecs
+            .write_resource::<RandomNumberGenerator>()
+            .roll_dice(1, 8)
+            + 2;
+        stats.max_hp += hp_gain;
+        stats.hp = stats.max_hp;
+
+        ecs.write_resource::<EventQueue>()
+            .push(GameEvent::LevelUp { level: exp.level });
+
+        let player_pos = ecs.fetch::<Point>();
+        let mut particle_builder = ecs.write_resource::<ParticleBuilder>();
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                particle_builder.request(
+                    player_pos.x + dx,
+                    player_pos.y + dy,
+                    RGB::named(GOLD),
+                    RGBA::named(BLACK),
+                    to_cp437('░'),
+                    400.0,
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+/// How much tougher monsters get with each level down, on top of the chosen
+/// difficulty.
+const SCALING_PER_DEPTH: f32 = 0.1;
+
+/// The difficulty picked on the title screen.
+#[derive(PartialEq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum DifficultyLevel {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl DifficultyLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            DifficultyLevel::Easy => "Easy",
+            DifficultyLevel::Normal => "Normal",
+            DifficultyLevel::Hard => "Hard",
+        }
+    }
+
+    /// The next one along, wrapping around after the hardest.
+    pub fn next(self) -> DifficultyLevel {
+        match self {
+            DifficultyLevel::Easy => DifficultyLevel::Normal,
+            DifficultyLevel::Normal => DifficultyLevel::Hard,
+            DifficultyLevel::Hard => DifficultyLevel::Easy,
+        }
+    }
+
+    /// The one before, wrapping around before the easiest.
+    pub fn previous(self) -> DifficultyLevel {
+        match self {
+            DifficultyLevel::Easy => DifficultyLevel::Hard,
+            DifficultyLevel::Normal => DifficultyLevel::Easy,
+            DifficultyLevel::Hard => DifficultyLevel::Normal,
+        }
+    }
+
+    fn multiplier(self) -> f32 {
+        match self {
+            DifficultyLevel::Easy => 0.75,
+            DifficultyLevel::Normal => 1.0,
+            DifficultyLevel::Hard => 1.5,
+        }
+    }
+}
+
+/// How strong monsters are made when they spawn, before depth is taken into
+/// account, and the level that was picked to make them so.
+pub struct Difficulty {
+    pub level: DifficultyLevel,
+    pub multiplier: f32,
+}
+
+impl Difficulty {
+    pub fn new(level: DifficultyLevel) -> Difficulty {
+        Difficulty {
+            level,
+            multiplier: level.multiplier(),
+        }
+    }
+
+    /// What a monster's hit points and power are multiplied by at `depth`.
+    pub fn scale(&self, depth: i32) -> f32 {
+        self.multiplier * (1.0 + SCALING_PER_DEPTH * (depth - 1) as f32)
+    }
+
+    /// `stat` scaled for `depth`, rounded, and never brought down to nothing
+    /// if it was something to begin with.
+    pub fn scaled(&self, stat: i32, depth: i32) -> i32 {
+        let scaled = (stat as f32 * self.scale(depth)).round() as i32;
+        if stat > 0 {
+            i32::max(1, scaled)
+        } else {
+            scaled
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use crate::gamelog::{refer_to, GameLog, LogCategory};
+use crate::{EffectKind, Name};
+use specs::prelude::*;
+
+/// Something that happened in the game worth telling the player about.
+/// Names are taken when the event happens, since whoever it happened to may
+/// be gone by the time it's reported.
+#[derive(Clone, Debug)]
+pub enum GameEvent {
+    /// A blow that landed, even if it did no harm.
+    Damage {
+        attacker: Name,
+        attacker_is_player: bool,
+        target: Name,
+        target_is_player: bool,
+        amount: i32,
+        critical: bool,
+        backstab: bool,
+    },
+    /// A blow that didn't land.
+    Miss {
+        attacker: Name,
+        attacker_is_player: bool,
+        target: Name,
+        target_is_player: bool,
+    },
+    /// Someone knocked back by a blow.
+    KnockedBack {
+        target: Name,
+        target_is_player: bool,
+    },
+    /// Someone knocked back into a wall, and hurt by it.
+    HitWall {
+        target: Name,
+        target_is_player: bool,
+        amount: i32,
+    },
+    /// A weapon leaving something behind, like poison.
+    Afflicted {
+        target: Name,
+        target_is_player: bool,
+        kind: EffectKind,
+    },
+    Death {
+        victim: Name,
+        is_player: bool,
+    },
+    /// The player picking something up.
+    Pickup {
+        item: String,
+    },
+    /// The player picking up coins.
+    GoldPickup {
+        amount: i32,
+    },
+    /// The player reaching a new experience level.
+    LevelUp {
+        level: i32,
+    },
+}
+
+/// Events waiting to be reported. Systems push to it as things happen; any
+/// that want to react to events read them before `EventLogSystem`, which
+/// empties it.
+#[derive(Default)]
+pub struct EventQueue {
+    pub events: Vec<GameEvent>,
+}
+
+impl EventQueue {
+    pub fn new() -> EventQueue {
+        EventQueue::default()
+    }
+
+    pub fn push(&mut self, event: GameEvent) {
+        self.events.push(event);
+    }
+}
+
+/// How the log tells of `event`.
+pub fn describe(event: &GameEvent) -> (LogCategory, String) {
+    match event {
+        GameEvent::Damage {
+            attacker,
+            attacker_is_player,
+            target,
+            target_is_player,
+            amount,
+            critical,
+            backstab,
+        } => {
+            let attacker = refer_to(attacker, *attacker_is_player, true);
+            let target = refer_to(target, *target_is_player, false);
+            let text = if *amount == 0 {
+                format!(
+                    "{} {} unable to hurt {}.",
+                    attacker,
+                    if *attacker_is_player { "are" } else { "is" },
+                    target
+                )
+            } else {
+                format!(
+                    "{}{}{} {} {}, for {} hp.",
+                    if *backstab { "Backstab! " } else { "" },
+                    if *critical { "Critical hit! " } else { "" },
+                    attacker,
+                    if *attacker_is_player { "hit" } else { "hits" },
+                    target,
+                    amount
+                )
+            };
+            (LogCategory::Combat, text)
+        }
+        GameEvent::Miss {
+            attacker,
+            attacker_is_player,
+            target,
+            target_is_player,
+        } => (
+            LogCategory::Combat,
+            format!(
+                "{} {} {}.",
+                refer_to(attacker, *attacker_is_player, true),
+                if *attacker_is_player {
+                    "miss"
+                } else {
+                    "misses"
+                },
+                refer_to(target, *target_is_player, false)
+            ),
+        ),
+        GameEvent::KnockedBack {
+            target,
+            target_is_player,
+        } => (
+            LogCategory::Combat,
+            format!(
+                "{} {} knocked back.",
+                refer_to(target, *target_is_player, true),
+                if *target_is_player { "are" } else { "is" }
+            ),
+        ),
+        GameEvent::HitWall {
+            target,
+            target_is_player,
+            amount,
+        } => (
+            LogCategory::Combat,
+            format!(
+                "{} {} into the wall, for {} hp.",
+                refer_to(target, *target_is_player, true),
+                if *target_is_player { "slam" } else { "slams" },
+                amount
+            ),
+        ),
+        GameEvent::Afflicted {
+            target,
+            target_is_player,
+            kind,
+        } => (
+            LogCategory::Combat,
+            format!(
+                "{} {} {}!",
+                refer_to(target, *target_is_player, true),
+                if *target_is_player { "are" } else { "is" },
+                kind.adjective()
+            ),
+        ),
+        GameEvent::Death {
+            is_player: true, ..
+        } => (LogCategory::Combat, "You are dead!".to_string()),
+        GameEvent::Death { victim, .. } => {
+            (LogCategory::Combat, format!("{} is dead", victim.name))
+        }
+        GameEvent::Pickup { item } => (LogCategory::Item, format!("You pick up the {}.", item)),
+        GameEvent::GoldPickup { amount } => {
+            (LogCategory::Item, format!("You pick up {} gold.", amount))
+        }
+        GameEvent::LevelUp { level } => {
+            (LogCategory::General, format!("Welcome to level {}!", level))
+        }
+    }
+}
+
+/// Turns whatever happened since it last ran into log messages.
+pub struct EventLogSystem {}
+
+impl<'a> System<'a> for EventLogSystem {
+    type SystemData = (WriteExpect<'a, EventQueue>, WriteExpect<'a, GameLog>);
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut queue, mut log) = data;
+
+        for event in queue.events.drain(..) {
+            let (category, text) = describe(&event);
+            log.add(category, text);
+        }
+    }
+}
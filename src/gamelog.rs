@@ -1,3 +1,65 @@
+use crate::Name;
+use bracket_lib::prelude::{CYAN, RED, RGB, WHITE};
+use serde::{Deserialize, Serialize};
+
+/// How many messages the log keeps before it starts forgetting the oldest.
+const MAX_ENTRIES: usize = 200;
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum LogCategory {
+    General,
+    Combat,
+    Item,
+}
+
+impl LogCategory {
+    pub fn color(self) -> RGB {
+        match self {
+            LogCategory::General => RGB::named(WHITE),
+            LogCategory::Combat => RGB::named(RED),
+            LogCategory::Item => RGB::named(CYAN),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub text: String,
+    pub category: LogCategory,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GameLog {
-    pub entries: Vec<String>,
+    pub entries: Vec<LogEntry>,
+}
+
+impl GameLog {
+    pub fn new<S: ToString>(first_entry: S) -> GameLog {
+        let mut log = GameLog { entries: vec![] };
+        log.add(LogCategory::General, first_entry);
+        log
+    }
+
+    /// Appends a message, dropping the oldest ones once there are more than
+    /// `MAX_ENTRIES`.
+    pub fn add<S: ToString>(&mut self, category: LogCategory, text: S) {
+        self.entries.push(LogEntry {
+            text: text.to_string(),
+            category,
+        });
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+}
+
+/// How the log refers to someone: the player is "you", everyone else goes by
+/// their name. `sentence_start` capitalizes the "you".
+pub fn refer_to(name: &Name, is_player: bool, sentence_start: bool) -> String {
+    match (is_player, sentence_start) {
+        (true, true) => "You".to_string(),
+        (true, false) => "you".to_string(),
+        (false, _) => name.name.clone(),
+    }
 }
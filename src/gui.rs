@@ -1,42 +1,179 @@
+use crate::camera::{self, VIEW_HEIGHT, VIEW_WIDTH};
+use crate::colors::Theme;
+use crate::damage_system::xp_for_next_level;
+use crate::difficulty::DifficultyLevel;
 use crate::gamelog::GameLog;
-use crate::map::{HEIGHT, WIDTH};
-use crate::{CombatStats, Map, Name, Player, Position};
+use crate::inventory_system::{carried_weight, carry_capacity, item_name, KnownItems};
+use crate::map::{footprint, Depth, TileType};
+use crate::player::Autopilot;
+use crate::quest_system::{Quest, Quests};
+use crate::ranged_combat_system::{ammo_left, equipped_ranged_weapon};
+use crate::saveload_system;
+use crate::vendor;
+use crate::{
+    AiMode, AiState, CombatStats, Confusion, DefenseBonus, Dialogue, EquipmentSlot, Equipped,
+    Experience, FieldOfView, Hidden, HungerClock, HungerState, InInventory, Mana, Map,
+    MeleePowerBonus, Monster, Name, Player, Position, Price, Size, Sneaking, Spell, Stackable,
+    StatusEffect, Unidentified, Wallet, Weight,
+};
 use bracket_lib::prelude::*;
 use specs::prelude::*;
 
+/// Length of the health bar next to the HP readout.
+const HP_BAR_WIDTH: i32 = 51;
+
+/// Size of the minimap, border included, on a map big enough to fill it.
+const MINIMAP_WIDTH: i32 = 22;
+const MINIMAP_HEIGHT: i32 = 17;
+
+/// Display settings the player can change while playing.
+pub struct UiState {
+    pub show_minimap: bool,
+    /// Draws the whole map and everything on it, seen or not, for debugging.
+    /// Only what's shown changes; the game plays on as before.
+    pub wizard_mode: bool,
+}
+
+impl UiState {
+    pub fn new() -> UiState {
+        UiState {
+            show_minimap: false,
+            wizard_mode: false,
+        }
+    }
+}
+
+/// Green while healthy, yellow once hurt, red when close to death.
+fn hp_color(fraction: f32) -> RGB {
+    if fraction > 0.6 {
+        RGB::named(GREEN)
+    } else if fraction > 0.3 {
+        RGB::named(YELLOW)
+    } else {
+        RGB::named(RED)
+    }
+}
+
 pub fn draw_ui(ecs: &World, ctx: &mut BTerm) {
+    let theme = ecs.fetch::<Theme>();
     ctx.draw_box(
         0,
-        HEIGHT,
-        WIDTH - 1,
+        VIEW_HEIGHT,
+        VIEW_WIDTH - 1,
         6,
-        RGB::named(WHITE),
-        RGB::named(BLACK),
+        theme.text,
+        theme.background,
     );
 
     let combat_stats = ecs.read_storage::<CombatStats>();
     let players = ecs.read_storage::<Player>();
     for (_player, stats) in (&players, &combat_stats).join() {
         let health = format!("HP: {} / {}", stats.hp, stats.max_hp);
-        ctx.print_color(17, HEIGHT, RGB::named(YELLOW), RGB::named(BLACK), &health);
+        ctx.print_color(17, VIEW_HEIGHT, theme.highlight, theme.background, &health);
         ctx.draw_bar_horizontal(
             28,
-            HEIGHT,
-            51,
+            VIEW_HEIGHT,
+            HP_BAR_WIDTH,
             stats.hp,
             stats.max_hp,
-            RGB::named(RED),
-            RGB::named(BLACK),
+            hp_color(stats.hp as f32 / stats.max_hp as f32),
+            theme.background,
         );
     }
 
-    let log = ecs.fetch::<GameLog>();
-    let mut y = HEIGHT + 1;
-    for s in log.entries.iter().rev() {
-        if y < 49 {
-            ctx.print(2, y, s);
+    let manas = ecs.read_storage::<Mana>();
+    for (_player, mana) in (&players, &manas).join() {
+        ctx.print_color(
+            2,
+            VIEW_HEIGHT + 6,
+            RGB::named(CYAN),
+            theme.background,
+            format!("Mana: {} / {}", mana.current, mana.max),
+        );
+    }
+
+    let player_entity = *ecs.fetch::<Entity>();
+    if let Some((_weapon, ranged)) = equipped_ranged_weapon(ecs, player_entity) {
+        let mut kind = ranged.ammo.clone();
+        if let Some(first) = kind.get_mut(0..1) {
+            first.make_ascii_uppercase();
         }
-        y += 1;
+        ctx.print_color(
+            18,
+            VIEW_HEIGHT + 6,
+            RGB::named(BURLYWOOD),
+            theme.background,
+            format!("{}s: {}", kind, ammo_left(ecs, player_entity, &ranged.ammo)),
+        );
+    }
+
+    let wallets = ecs.read_storage::<Wallet>();
+    for (_player, wallet) in (&players, &wallets).join() {
+        ctx.print_color(
+            2,
+            VIEW_HEIGHT,
+            RGB::named(GOLD),
+            theme.background,
+            format!("Gold: {}", wallet.gold),
+        );
+    }
+
+    if *ecs.fetch::<Autopilot>() == Autopilot::Rest {
+        ctx.print_color(
+            46,
+            VIEW_HEIGHT + 6,
+            theme.dimmed,
+            theme.background,
+            "Resting...",
+        );
+    }
+
+    let sneaking = ecs.read_storage::<Sneaking>();
+    if (&players, &sneaking).join().next().is_some() {
+        ctx.print_color(
+            60,
+            VIEW_HEIGHT + 6,
+            theme.dimmed,
+            theme.background,
+            "Sneaking",
+        );
+    }
+
+    let hunger_clocks = ecs.read_storage::<HungerClock>();
+    for (_player, clock) in (&players, &hunger_clocks).join() {
+        match clock.state {
+            HungerState::WellFed => ctx.print_color(
+                71,
+                VIEW_HEIGHT + 6,
+                RGB::named(GREEN),
+                theme.background,
+                "Well Fed",
+            ),
+            HungerState::Normal => {}
+            HungerState::Hungry => ctx.print_color(
+                71,
+                VIEW_HEIGHT + 6,
+                RGB::named(ORANGE),
+                theme.background,
+                "Hungry",
+            ),
+            HungerState::Starving => ctx.print_color(
+                71,
+                VIEW_HEIGHT + 6,
+                RGB::named(RED),
+                theme.background,
+                "Starving",
+            ),
+        }
+    }
+
+    let log = ecs.fetch::<GameLog>();
+    for (y, entry) in (VIEW_HEIGHT + 1..49).zip(log.entries.iter().rev()) {
+        ctx.print_color(2, y, entry.category.color(), theme.background, &entry.text);
+    }
+
+    if ecs.fetch::<UiState>().show_minimap {
+        draw_minimap(ecs, ctx);
     }
 
     let (mouse_x, mouse_y) = ctx.mouse_pos();
@@ -44,23 +181,129 @@ pub fn draw_ui(ecs: &World, ctx: &mut BTerm) {
     draw_tooltips(ecs, ctx);
 }
 
+/// Draws the explored part of the level shrunk into a box in the top right
+/// corner, with the player as a yellow dot and the monsters in sight as red
+/// ones. Each cell of the box stands for a block of tiles; maps that would
+/// fit the box as they are aren't blown up, the box shrinks to their size
+/// instead.
+pub fn draw_minimap(ecs: &World, ctx: &mut BTerm) {
+    let theme = ecs.fetch::<Theme>();
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Point>();
+    let positions = ecs.read_storage::<Position>();
+    let monsters = ecs.read_storage::<Monster>();
+
+    let (inner_width, inner_height) = (MINIMAP_WIDTH - 2, MINIMAP_HEIGHT - 2);
+    let scale_x = i32::max(1, (map.width + inner_width - 1) / inner_width);
+    let scale_y = i32::max(1, (map.height + inner_height - 1) / inner_height);
+    let cells_x = (map.width + scale_x - 1) / scale_x;
+    let cells_y = (map.height + scale_y - 1) / scale_y;
+    let (left, top) = (VIEW_WIDTH - cells_x - 2, 0);
+
+    ctx.draw_box(
+        left,
+        top,
+        cells_x + 1,
+        cells_y + 1,
+        theme.text,
+        theme.background,
+    );
+
+    for cell_y in 0..cells_y {
+        for cell_x in 0..cells_x {
+            // A block shows up as floor if any of it is known to be open,
+            // and as wall if all that's known of it is wall
+            let mut seen_open = false;
+            let mut seen_wall = false;
+            for y in cell_y * scale_y..i32::min(map.height, (cell_y + 1) * scale_y) {
+                for x in cell_x * scale_x..i32::min(map.width, (cell_x + 1) * scale_x) {
+                    let idx = map.xy_idx(x, y);
+                    if !map.revealed_tiles[idx] {
+                        continue;
+                    }
+                    match map.tiles[idx] {
+                        TileType::Wall | TileType::SecretDoor => seen_wall = true,
+                        _ => seen_open = true,
+                    }
+                }
+            }
+
+            let bg = if seen_open {
+                theme.floor.to_rgb()
+            } else if seen_wall {
+                theme.wall.lerp(RGBA::named(BLACK), 0.5).to_rgb()
+            } else {
+                theme.background
+            };
+            ctx.set_bg(left + 1 + cell_x, top + 1 + cell_y, bg);
+        }
+    }
+
+    let dot = |ctx: &mut BTerm, x: i32, y: i32, color: (u8, u8, u8)| {
+        ctx.set(
+            left + 1 + x / scale_x,
+            top + 1 + y / scale_y,
+            RGB::named(color),
+            RGB::from_u8(60, 60, 60),
+            to_cp437('•'),
+        );
+    };
+    for (_monster, pos) in (&monsters, &positions).join() {
+        if map.visible_tiles[map.xy_idx(pos.x, pos.y)] {
+            dot(ctx, pos.x, pos.y, RED);
+        }
+    }
+    dot(ctx, player_pos.x, player_pos.y, YELLOW);
+}
+
+/// Lists the names of whatever visible entities are under the mouse in a
+/// small box beside the cursor. In wizard mode that's everything there, under
+/// its real name and with its hit points. The box flips to the other side of
+/// the cursor (and up) rather than run off the edge of the screen.
 fn draw_tooltips(ecs: &World, ctx: &mut BTerm) {
+    let theme = ecs.fetch::<Theme>();
     let map = ecs.fetch::<Map>();
     let names = ecs.read_storage::<Name>();
     let positions = ecs.read_storage::<Position>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let unidentified = ecs.read_storage::<Unidentified>();
+    let known = ecs.fetch::<KnownItems>();
+
+    let origin = camera::origin(ecs);
 
     let (mouse_x, mouse_y) = ctx.mouse_pos();
-    if mouse_x >= map.width || mouse_y >= map.height {
+    if !camera::in_view(mouse_x, mouse_y) {
+        return;
+    }
+    let (world_x, world_y) = (mouse_x + origin.x, mouse_y + origin.y);
+    if world_x >= map.width || world_y >= map.height {
         return;
     }
 
+    let wizard_mode = ecs.fetch::<UiState>().wizard_mode;
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let sizes = ecs.read_storage::<Size>();
     let mut tooltip: Vec<String> = vec![];
-    for (name, position) in (&names, &positions).join() {
-        if position.x == mouse_x && position.y == mouse_y {
-            let idx = map.xy_idx(position.x, position.y);
-            if map.visible_tiles[idx] {
-                tooltip.push(name.name.clone());
-            }
+    for (name, unknown, position, hidden, stats, size) in (
+        &names,
+        (&unidentified).maybe(),
+        &positions,
+        (&hidden).maybe(),
+        (&combat_stats).maybe(),
+        (&sizes).maybe(),
+    )
+        .join()
+    {
+        if !footprint(position.x, position.y, size).contains(&Point::new(world_x, world_y)) {
+            continue;
+        }
+        if wizard_mode {
+            tooltip.push(match stats {
+                Some(stats) => format!("{} ({}/{} hp)", name.name, stats.hp, stats.max_hp),
+                None => name.name.clone(),
+            });
+        } else if hidden.is_none() && map.visible_tiles[map.xy_idx(position.x, position.y)] {
+            tooltip.push(known.name_of(name, unknown));
         }
     }
 
@@ -68,42 +311,901 @@ fn draw_tooltips(ecs: &World, ctx: &mut BTerm) {
         return;
     }
 
-    let mut width: i32 = 0;
-    for s in tooltip.iter() {
-        if width < s.len() as i32 {
-            width = s.len() as i32
+    let inner_width = tooltip.iter().map(|s| s.len() as i32).max().unwrap_or(0);
+    let box_width = inner_width + 2;
+    let box_height = tooltip.len() as i32 + 2;
+    let (screen_width, screen_height) = ctx.get_char_size();
+
+    let box_x = if mouse_x + 1 + box_width <= screen_width as i32 {
+        mouse_x + 1
+    } else {
+        mouse_x - box_width
+    };
+    let box_y = i32::min(mouse_y, screen_height as i32 - box_height);
+
+    let fg = theme.text;
+    let bg = RGB::from_u8(100, 100, 100);
+    ctx.draw_box(box_x, box_y, box_width - 1, box_height - 1, fg, bg);
+    for (y, s) in (box_y + 1..).zip(tooltip.iter()) {
+        ctx.print_color(box_x + 1, y, fg, bg, s);
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum ItemMenuResult {
+    Cancel,
+    NoResponse,
+    Selected,
+}
+
+pub fn show_inventory(ecs: &World, ctx: &mut BTerm) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let carried = carried_weight(
+        player_entity,
+        &ecs.entities(),
+        &ecs.read_storage::<InInventory>(),
+        &ecs.read_storage::<Equipped>(),
+        &ecs.read_storage::<Weight>(),
+        &ecs.read_storage::<Stackable>(),
+    );
+    let capacity = ecs
+        .read_storage::<CombatStats>()
+        .get(player_entity)
+        .map_or(0.0, carry_capacity);
+    let title = format!("Inventory ({:.1} / {:.1})", carried, capacity);
+    carried_item_menu(ecs, ctx, &title)
+}
+
+/// Lists what the player carries to pick something to throw.
+pub fn show_throw_menu(ecs: &World, ctx: &mut BTerm) -> (ItemMenuResult, Option<Entity>) {
+    carried_item_menu(ecs, ctx, "Throw which item?")
+}
+
+/// Lists what the player carries to pick something to leave behind.
+pub fn show_drop_item_menu(ecs: &World, ctx: &mut BTerm) -> (ItemMenuResult, Option<Entity>) {
+    carried_item_menu(ecs, ctx, "Drop which item?")
+}
+
+/// Lists what the player has equipped to pick something to take off.
+pub fn show_remove_item_menu(ecs: &World, ctx: &mut BTerm) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let equipped = ecs.read_storage::<Equipped>();
+    let entities = ecs.entities();
+
+    let gear: Vec<(Entity, String)> = (&entities, &equipped, &names)
+        .join()
+        .filter(|(_entity, worn, _name)| worn.owner == *player_entity)
+        .map(|(entity, _worn, name)| (entity, name.name.clone()))
+        .collect();
+
+    item_menu(
+        ctx,
+        &ecs.fetch::<Theme>(),
+        "Remove which item?",
+        "Nothing equipped",
+        &gear,
+    )
+}
+
+fn carried_item_menu(
+    ecs: &World,
+    ctx: &mut BTerm,
+    title: &str,
+) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let unidentified = ecs.read_storage::<Unidentified>();
+    let known = ecs.fetch::<KnownItems>();
+    let backpack = ecs.read_storage::<InInventory>();
+    let entities = ecs.entities();
+
+    let stacks = ecs.read_storage::<Stackable>();
+
+    let inventory: Vec<(Entity, String)> = (&entities, &backpack)
+        .join()
+        .filter(|(_entity, pack)| pack.owner == *player_entity)
+        .map(|(entity, _pack)| {
+            let name = item_name(&names, &unidentified, &known, entity);
+            match stacks.get(entity) {
+                Some(stack) if stack.count > 1 => (entity, format!("{} (x{})", name, stack.count)),
+                _ => (entity, name),
+            }
+        })
+        .collect();
+
+    item_menu(
+        ctx,
+        &ecs.fetch::<Theme>(),
+        title,
+        "Inventory is empty",
+        &inventory,
+    )
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum DropStackResult {
+    NoResponse,
+    Cancel,
+    One,
+    All,
+}
+
+/// Asks whether to drop one of a stack or the whole thing.
+pub fn drop_stack_prompt(ecs: &World, ctx: &mut BTerm, item: Entity) -> DropStackResult {
+    let theme = ecs.fetch::<Theme>();
+    let names = ecs.read_storage::<Name>();
+    let unidentified = ecs.read_storage::<Unidentified>();
+    let known = ecs.fetch::<KnownItems>();
+    let count = ecs
+        .read_storage::<Stackable>()
+        .get(item)
+        .map_or(1, |stack| stack.count);
+
+    ctx.draw_box(15, 22, 31, 5, theme.text, theme.background);
+    ctx.print_color(
+        18,
+        22,
+        theme.highlight,
+        theme.background,
+        format!(
+            "Drop {} (x{})",
+            item_name(&names, &unidentified, &known, item),
+            count
+        ),
+    );
+    ctx.print(17, 24, "(O)ne or (A)ll?");
+    ctx.print_color(
+        18,
+        27,
+        theme.highlight,
+        theme.background,
+        "ESCAPE to cancel",
+    );
+
+    match ctx.key {
+        Some(VirtualKeyCode::O) => DropStackResult::One,
+        Some(VirtualKeyCode::A) => DropStackResult::All,
+        Some(VirtualKeyCode::Escape) => DropStackResult::Cancel,
+        _ => DropStackResult::NoResponse,
+    }
+}
+
+/// Lists the spells the player knows, with what each costs, to pick one to
+/// cast.
+pub fn show_spells(ecs: &World, ctx: &mut BTerm) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let spells = ecs.read_storage::<Spell>();
+    let entities = ecs.entities();
+
+    let known: Vec<(Entity, String)> = (&entities, &spells, &names)
+        .join()
+        .filter(|(_entity, spell, _name)| spell.owner == *player_entity)
+        .map(|(entity, spell, name)| (entity, format!("{} ({} mana)", name.name, spell.mana_cost)))
+        .collect();
+
+    item_menu(
+        ctx,
+        &ecs.fetch::<Theme>(),
+        "Cast which spell?",
+        "You know no spells",
+        &known,
+    )
+}
+
+/// Which side of the counter a shop screen is showing.
+#[derive(PartialEq, Copy, Clone)]
+pub enum VendorMode {
+    Buy,
+    Sell,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum VendorResult {
+    NoResponse,
+    Cancel,
+    ToggleMode,
+    Buy { item: Entity },
+    Sell { item: Entity },
+}
+
+/// Lists the shopkeeper's wares, or the player's own sellable items, with
+/// what each would cost or fetch. Tab switches between the two.
+pub fn show_vendor(ecs: &World, ctx: &mut BTerm, vendor: Entity, mode: VendorMode) -> VendorResult {
+    let player_entity = ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let unidentified = ecs.read_storage::<Unidentified>();
+    let known = ecs.fetch::<KnownItems>();
+    let backpack = ecs.read_storage::<InInventory>();
+    let prices = ecs.read_storage::<Price>();
+    let entities = ecs.entities();
+
+    let owner = match mode {
+        VendorMode::Buy => vendor,
+        VendorMode::Sell => *player_entity,
+    };
+    let items: Vec<(Entity, String)> = (&entities, &backpack, &prices)
+        .join()
+        .filter(|(_entity, pack, _price)| pack.owner == owner)
+        .map(|(entity, _pack, price)| {
+            let gold = match mode {
+                VendorMode::Buy => price.gold,
+                VendorMode::Sell => vendor::sell_price(price.gold),
+            };
+            let name = item_name(&names, &unidentified, &known, entity);
+            (entity, format!("{} ({})", name, gold))
+        })
+        .collect();
+
+    let (title, empty_text) = match mode {
+        VendorMode::Buy => ("Buy - TAB to sell", "Sold out"),
+        VendorMode::Sell => ("Sell - TAB to buy", "Nothing to sell"),
+    };
+    let (result, item) = item_menu(ctx, &ecs.fetch::<Theme>(), title, empty_text, &items);
+    match (result, item) {
+        (ItemMenuResult::Cancel, _) => VendorResult::Cancel,
+        (ItemMenuResult::Selected, Some(item)) => match mode {
+            VendorMode::Buy => VendorResult::Buy { item },
+            VendorMode::Sell => VendorResult::Sell { item },
+        },
+        _ if ctx.key == Some(VirtualKeyCode::Tab) => VendorResult::ToggleMode,
+        _ => VendorResult::NoResponse,
+    }
+}
+
+/// Draws a boxed, lettered list of items and returns the one picked, if any.
+/// An empty list shows `empty_text` and only accepts Escape.
+fn item_menu(
+    ctx: &mut BTerm,
+    theme: &Theme,
+    title: &str,
+    empty_text: &str,
+    items: &[(Entity, String)],
+) -> (ItemMenuResult, Option<Entity>) {
+    let count = i32::max(1, items.len() as i32);
+    let y = 25 - (count / 2);
+    ctx.draw_box(15, y - 2, 31, count + 3, theme.text, theme.background);
+    ctx.print_color(18, y - 2, theme.highlight, theme.background, title);
+    ctx.print_color(
+        18,
+        y + count + 1,
+        theme.highlight,
+        theme.background,
+        "ESCAPE to cancel",
+    );
+
+    if items.is_empty() {
+        ctx.print(17, y, empty_text);
+    }
+
+    for ((_entity, name), (y, letter)) in items.iter().zip((y..).zip('a'..='z')) {
+        ctx.set(17, y, theme.text, theme.background, to_cp437('('));
+        ctx.set(18, y, theme.highlight, theme.background, to_cp437(letter));
+        ctx.set(19, y, theme.text, theme.background, to_cp437(')'));
+        ctx.print(21, y, name);
+    }
+
+    match ctx.key {
+        None => (ItemMenuResult::NoResponse, None),
+        Some(VirtualKeyCode::Escape) => (ItemMenuResult::Cancel, None),
+        Some(key) => {
+            let selection = letter_to_option(key);
+            if selection > -1 && (selection as usize) < items.len() {
+                return (ItemMenuResult::Selected, Some(items[selection as usize].0));
+            }
+            (ItemMenuResult::NoResponse, None)
         }
     }
-    width += 3;
+}
 
-    let fg = RGB::named(WHITE);
-    let bg = RGB::from_u8(100, 100, 100);
+/// Highlights the visible tiles within `range` of the player and waits for a
+/// click on one of them. Clicks outside the highlighted area are refused.
+pub fn ranged_target(ecs: &World, ctx: &mut BTerm, range: i32) -> (ItemMenuResult, Option<Point>) {
+    let theme = ecs.fetch::<Theme>();
+    let player_entity = ecs.fetch::<Entity>();
+    let player_pos = ecs.fetch::<Point>();
+    let fovs = ecs.read_storage::<FieldOfView>();
+    let origin = camera::origin(ecs);
+
+    ctx.print_color(5, 0, theme.highlight, theme.background, "Select Target:");
 
-    if mouse_x > 40 {
-        let arrow_pos = Point::new(mouse_x - 2, mouse_y);
-        let left_x = mouse_x - width;
-        let mut y = mouse_y;
-        for s in tooltip.iter() {
-            ctx.print_color(left_x, y, fg, bg, s);
-            let padding = (width - s.len() as i32) - 1;
-            for i in 0..padding {
-                ctx.print_color(arrow_pos.x - i, y, fg, bg, " ");
+    let mut available_cells = Vec::new();
+    if let Some(fov) = fovs.get(*player_entity) {
+        for idx in fov.visible_tiles.iter() {
+            let distance = DistanceAlg::Pythagoras.distance2d(*player_pos, *idx);
+            if distance <= range as f32 {
+                ctx.set_bg(idx.x - origin.x, idx.y - origin.y, RGB::named(BLUE));
+                available_cells.push(*idx);
             }
-            y += 1;
-            ctx.print_color(arrow_pos.x, arrow_pos.y, fg, bg, "->");
         }
     } else {
-        let arrow_pos = Point::new(mouse_x + 1, mouse_y);
-        let left_x = mouse_x + 3;
-        let mut y = mouse_y;
-        for s in tooltip.iter() {
-            ctx.print_color(left_x + 1, y, fg, bg, s);
-            let padding = (width - s.len() as i32) - 1;
-            for i in 0..padding {
-                ctx.print_color(arrow_pos.x + 1 + i, y, fg, bg, " ");
+        return (ItemMenuResult::Cancel, None);
+    }
+
+    let (mouse_x, mouse_y) = ctx.mouse_pos();
+    let mouse_pos = Point::new(mouse_x + origin.x, mouse_y + origin.y);
+    let valid_target = available_cells.contains(&mouse_pos);
+    if valid_target {
+        ctx.set_bg(mouse_x, mouse_y, RGB::named(CYAN));
+    } else {
+        ctx.set_bg(mouse_x, mouse_y, RGB::named(RED));
+    }
+
+    if ctx.key == Some(VirtualKeyCode::Escape) {
+        return (ItemMenuResult::Cancel, None);
+    }
+    if ctx.left_click && valid_target {
+        return (ItemMenuResult::Selected, Some(mouse_pos));
+    }
+
+    (ItemMenuResult::NoResponse, None)
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum ExamineResult {
+    Open { cursor: Point },
+    Close,
+}
+
+/// Keyboard look mode: highlights the tile under `cursor` and describes it in
+/// the bottom panel, in place of the log. The cursor moves with the arrow keys
+/// and stays on the part of the map that's on screen.
+pub fn examine(ecs: &World, ctx: &mut BTerm, cursor: Point) -> ExamineResult {
+    let theme = ecs.fetch::<Theme>();
+    let map = ecs.fetch::<Map>();
+    let names = ecs.read_storage::<Name>();
+    let positions = ecs.read_storage::<Position>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let ai_states = ecs.read_storage::<AiState>();
+    let unidentified = ecs.read_storage::<Unidentified>();
+    let known = ecs.fetch::<KnownItems>();
+    let origin = camera::origin(ecs);
+
+    ctx.set_bg(cursor.x - origin.x, cursor.y - origin.y, theme.highlight);
+
+    let idx = map.xy_idx(cursor.x, cursor.y);
+    let mut description: Vec<String> = vec![];
+    if map.revealed_tiles[idx] {
+        description.push(tile_description(&map, idx).to_string());
+        if map.visible_tiles[idx] {
+            for (name, unknown, pos, stats, ai, _hidden) in (
+                &names,
+                (&unidentified).maybe(),
+                &positions,
+                (&combat_stats).maybe(),
+                (&ai_states).maybe(),
+                !&hidden,
+            )
+                .join()
+            {
+                if pos.x != cursor.x || pos.y != cursor.y {
+                    continue;
+                }
+                let asleep = if ai.is_some_and(|ai| ai.mode == AiMode::Sleeping) {
+                    ", asleep"
+                } else {
+                    ""
+                };
+                match stats {
+                    Some(stats) => description.push(format!(
+                        "{} ({} / {} hp{})",
+                        name.name, stats.hp, stats.max_hp, asleep
+                    )),
+                    None => description.push(known.name_of(name, unknown)),
+                }
+            }
+        }
+    } else {
+        description.push("You haven't seen what's there.".to_string());
+    }
+
+    ctx.draw_box(
+        0,
+        VIEW_HEIGHT,
+        VIEW_WIDTH - 1,
+        6,
+        theme.text,
+        theme.background,
+    );
+    ctx.print_color(
+        2,
+        VIEW_HEIGHT,
+        theme.highlight,
+        theme.background,
+        "Examine (ESCAPE to stop)",
+    );
+    for (y, line) in (VIEW_HEIGHT + 1..VIEW_HEIGHT + 6).zip(description.iter()) {
+        ctx.print(2, y, line);
+    }
+
+    let (dx, dy) = match ctx.key {
+        None => return ExamineResult::Open { cursor },
+        Some(key) => match key {
+            VirtualKeyCode::Escape => return ExamineResult::Close,
+            VirtualKeyCode::Left | VirtualKeyCode::Numpad4 => (-1, 0),
+            VirtualKeyCode::Right | VirtualKeyCode::Numpad6 => (1, 0),
+            VirtualKeyCode::Up | VirtualKeyCode::Numpad8 => (0, -1),
+            VirtualKeyCode::Down | VirtualKeyCode::Numpad2 => (0, 1),
+            VirtualKeyCode::Numpad7 => (-1, -1),
+            VirtualKeyCode::Numpad9 => (1, -1),
+            VirtualKeyCode::Numpad1 => (-1, 1),
+            VirtualKeyCode::Numpad3 => (1, 1),
+            _ => (0, 0),
+        },
+    };
+
+    let max_x = i32::min(map.width, origin.x + VIEW_WIDTH) - 1;
+    let max_y = i32::min(map.height, origin.y + VIEW_HEIGHT) - 1;
+    ExamineResult::Open {
+        cursor: Point::new(
+            i32::clamp(cursor.x + dx, origin.x, max_x),
+            i32::clamp(cursor.y + dy, origin.y, max_y),
+        ),
+    }
+}
+
+fn tile_description(map: &Map, idx: usize) -> &'static str {
+    match map.tiles[idx] {
+        TileType::Floor => "Floor",
+        TileType::Wall | TileType::SecretDoor => "Wall",
+        TileType::DownStairs => "Stairs leading down",
+        TileType::UpStairs => "Stairs leading up",
+        TileType::Door if map.open_doors[idx] => "An open door",
+        TileType::Door if map.is_locked(idx) => "A locked door",
+        TileType::Door => "A closed door",
+        TileType::ShallowWater => "Shallow water",
+        TileType::DeepWater => "Deep water",
+        TileType::Lava => "Lava",
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum MainMenuSelection {
+    NewGame,
+    Difficulty,
+    Continue,
+    Quit,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum MainMenuResult {
+    NoSelection {
+        selected: MainMenuSelection,
+    },
+    Selected {
+        selected: MainMenuSelection,
+    },
+    /// Left on an option that steps through choices, to step back.
+    Previous {
+        selected: MainMenuSelection,
+    },
+}
+
+/// Draws the title screen with `selection` highlighted. Continue is greyed
+/// out and skipped over while there's no savegame to continue from. Picking
+/// the difficulty, or pressing right on it, moves on to the next one; left
+/// goes back to the one before.
+pub fn main_menu(
+    ecs: &World,
+    ctx: &mut BTerm,
+    selection: MainMenuSelection,
+    difficulty: DifficultyLevel,
+) -> MainMenuResult {
+    let theme = ecs.fetch::<Theme>();
+    let save_exists = saveload_system::does_save_exist();
+
+    ctx.print_color_centered(15, theme.highlight, theme.background, "vortex");
+
+    let difficulty_label = format!("Difficulty: {}", difficulty.name());
+    let options = [
+        (MainMenuSelection::NewGame, "Begin New Game", true),
+        (
+            MainMenuSelection::Difficulty,
+            difficulty_label.as_str(),
+            true,
+        ),
+        (MainMenuSelection::Continue, "Continue", save_exists),
+        (MainMenuSelection::Quit, "Quit", true),
+    ];
+    for (y, (option, label, enabled)) in (24..).zip(options.iter()) {
+        let fg = if !enabled {
+            theme.dimmed
+        } else if *option == selection {
+            theme.selected
+        } else {
+            theme.text
+        };
+        ctx.print_color_centered(y, fg, theme.background, label);
+    }
+
+    match ctx.key {
+        None => MainMenuResult::NoSelection {
+            selected: selection,
+        },
+        Some(key) => match key {
+            VirtualKeyCode::Escape => MainMenuResult::NoSelection {
+                selected: MainMenuSelection::Quit,
+            },
+            VirtualKeyCode::Up => {
+                let mut selected = match selection {
+                    MainMenuSelection::NewGame => MainMenuSelection::Quit,
+                    MainMenuSelection::Difficulty => MainMenuSelection::NewGame,
+                    MainMenuSelection::Continue => MainMenuSelection::Difficulty,
+                    MainMenuSelection::Quit => MainMenuSelection::Continue,
+                };
+                if selected == MainMenuSelection::Continue && !save_exists {
+                    selected = MainMenuSelection::Difficulty;
+                }
+                MainMenuResult::NoSelection { selected }
+            }
+            VirtualKeyCode::Down => {
+                let mut selected = match selection {
+                    MainMenuSelection::NewGame => MainMenuSelection::Difficulty,
+                    MainMenuSelection::Difficulty => MainMenuSelection::Continue,
+                    MainMenuSelection::Continue => MainMenuSelection::Quit,
+                    MainMenuSelection::Quit => MainMenuSelection::NewGame,
+                };
+                if selected == MainMenuSelection::Continue && !save_exists {
+                    selected = MainMenuSelection::Quit;
+                }
+                MainMenuResult::NoSelection { selected }
+            }
+            VirtualKeyCode::Left if selection == MainMenuSelection::Difficulty => {
+                MainMenuResult::Previous {
+                    selected: selection,
+                }
             }
-            y += 1;
+            VirtualKeyCode::Right if selection == MainMenuSelection::Difficulty => {
+                MainMenuResult::Selected {
+                    selected: selection,
+                }
+            }
+            VirtualKeyCode::Return => MainMenuResult::Selected {
+                selected: selection,
+            },
+            _ => MainMenuResult::NoSelection {
+                selected: selection,
+            },
+        },
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum GameOverResult {
+    NoSelection,
+    QuitToMenu,
+}
+
+pub fn game_over(ecs: &World, ctx: &mut BTerm) -> GameOverResult {
+    let theme = ecs.fetch::<Theme>();
+    let depth = ecs.fetch::<Depth>();
+
+    ctx.print_color_centered(15, RGB::named(RED), theme.background, "You died");
+    ctx.print_color_centered(
+        18,
+        theme.text,
+        theme.background,
+        format!("You made it to depth {}.", depth.0),
+    );
+    ctx.print_color_centered(
+        21,
+        theme.selected,
+        theme.background,
+        "Press any key to return to the main menu.",
+    );
+
+    match ctx.key {
+        None => GameOverResult::NoSelection,
+        Some(_) => GameOverResult::QuitToMenu,
+    }
+}
+
+/// Everything the character sheet shows, gathered up front.
+pub struct CharacterInfo {
+    pub level: i32,
+    pub xp: i32,
+    pub xp_for_next_level: i32,
+    pub hp: i32,
+    pub max_hp: i32,
+    /// Power and defense with what the equipment adds
+    pub power: i32,
+    pub defense: i32,
+    pub attack: i32,
+    pub dodge: i32,
+    /// Each equipped item with the slot it's in
+    pub equipment: Vec<(EquipmentSlot, String)>,
+    /// What's ailing the player, with the turns it has left
+    pub effects: Vec<String>,
+}
+
+pub fn gather_character_info(ecs: &World) -> CharacterInfo {
+    let player_entity = ecs.fetch::<Entity>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let experience = ecs.read_storage::<Experience>();
+    let equipped = ecs.read_storage::<Equipped>();
+    let names = ecs.read_storage::<Name>();
+    let power_bonuses = ecs.read_storage::<MeleePowerBonus>();
+    let defense_bonuses = ecs.read_storage::<DefenseBonus>();
+    let status_effects = ecs.read_storage::<StatusEffect>();
+    let confusion = ecs.read_storage::<Confusion>();
+    let entities = ecs.entities();
+
+    let stats = combat_stats.get(*player_entity).unwrap();
+    let (level, xp) = experience
+        .get(*player_entity)
+        .map_or((1, 0), |exp| (exp.level, exp.xp));
+
+    let mut power = stats.power;
+    let mut defense = stats.defense;
+    let mut equipment = Vec::new();
+    for (item, worn, name) in (&entities, &equipped, &names).join() {
+        if worn.owner != *player_entity {
+            continue;
         }
-        ctx.print_color(arrow_pos.x, arrow_pos.y, fg, bg, "<-");
+        power += power_bonuses.get(item).map_or(0, |bonus| bonus.power);
+        defense += defense_bonuses.get(item).map_or(0, |bonus| bonus.defense);
+        equipment.push((worn.slot, name.name.clone()));
+    }
+
+    let mut effects = Vec::new();
+    if let Some(effect) = status_effects.get(*player_entity) {
+        effects.push(format!(
+            "{} ({} turns)",
+            effect.kind.adjective(),
+            effect.turns
+        ));
+    }
+    if let Some(confused) = confusion.get(*player_entity) {
+        effects.push(format!("confused ({} turns)", confused.turns));
+    }
+    if ecs.read_storage::<Sneaking>().contains(*player_entity) {
+        effects.push("sneaking".to_string());
+    }
+
+    CharacterInfo {
+        level,
+        xp,
+        xp_for_next_level: xp_for_next_level(level),
+        hp: stats.hp,
+        max_hp: stats.max_hp,
+        power,
+        defense,
+        attack: stats.attack,
+        dodge: stats.dodge,
+        equipment,
+        effects,
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum QuestLogResult {
+    Open,
+    Close,
+}
+
+/// Lists the player's quests in a box in the middle of the screen, those
+/// still to do first, until Escape is pressed.
+pub fn show_quests(ecs: &World, ctx: &mut BTerm) -> QuestLogResult {
+    let theme = ecs.fetch::<Theme>();
+    let quests = ecs.fetch::<Quests>();
+    const WIDTH: i32 = 40;
+
+    let mut lines: Vec<(RGB, String)> = vec![(theme.highlight, "Active".to_string())];
+    let active: Vec<&Quest> = quests.quests.iter().filter(|quest| !quest.done).collect();
+    if active.is_empty() {
+        lines.push((theme.dimmed, "nothing left to do".to_string()));
+    }
+    for quest in active {
+        lines.push((theme.text, quest.title.clone()));
+    }
+    lines.push((theme.background, String::new()));
+    lines.push((theme.highlight, "Completed".to_string()));
+    let completed: Vec<&Quest> = quests.quests.iter().filter(|quest| quest.done).collect();
+    if completed.is_empty() {
+        lines.push((theme.dimmed, "none yet".to_string()));
+    }
+    for quest in completed {
+        lines.push((theme.dimmed, quest.title.clone()));
+    }
+
+    let (screen_width, screen_height) = ctx.get_char_size();
+    let height = lines.len() as i32 + 1;
+    let x = (screen_width as i32 - WIDTH) / 2;
+    let y = (screen_height as i32 - height) / 2;
+
+    ctx.draw_box(x, y, WIDTH, height, theme.text, theme.background);
+    ctx.print_color(x + 3, y, theme.highlight, theme.background, "Quests");
+    ctx.print_color(
+        x + 3,
+        y + height,
+        theme.highlight,
+        theme.background,
+        "ESCAPE to close",
+    );
+    for (line_y, (color, text)) in (y + 1..).zip(lines.iter()) {
+        ctx.print_color(x + 2, line_y, *color, theme.background, text);
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => QuestLogResult::Close,
+        _ => QuestLogResult::Open,
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum DialogueResult {
+    Open,
+    Next { line: usize },
+    Close,
+}
+
+/// Shows what `npc` has to say, one line at a time. Any key moves on to the
+/// next line; Escape, or running out of them, ends the conversation.
+pub fn show_dialogue(ecs: &World, ctx: &mut BTerm, npc: Entity, line: usize) -> DialogueResult {
+    let theme = ecs.fetch::<Theme>();
+    let names = ecs.read_storage::<Name>();
+    let dialogues = ecs.read_storage::<Dialogue>();
+    const WIDTH: i32 = 66;
+
+    let Some(text) = dialogues
+        .get(npc)
+        .and_then(|dialogue| dialogue.lines.get(line))
+    else {
+        return DialogueResult::Close;
+    };
+    let speaker = names.get(npc).map_or("Someone", |name| name.name.as_str());
+
+    let (screen_width, screen_height) = ctx.get_char_size();
+    let x = (screen_width as i32 - WIDTH) / 2;
+    let y = (screen_height as i32 - 2) / 2;
+
+    ctx.draw_box(x, y, WIDTH, 2, theme.text, theme.background);
+    ctx.print_color(x + 3, y, theme.highlight, theme.background, speaker);
+    ctx.print_color(
+        x + 3,
+        y + 2,
+        theme.highlight,
+        theme.background,
+        "Any key to go on, ESCAPE to leave",
+    );
+    ctx.print_color(x + 2, y + 1, theme.text, theme.background, text);
+
+    match ctx.key {
+        None => DialogueResult::Open,
+        Some(VirtualKeyCode::Escape) => DialogueResult::Close,
+        Some(_) => DialogueResult::Next { line: line + 1 },
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum CharacterSheetResult {
+    Open,
+    Close,
+}
+
+/// Shows the player's stats, gear and ailments in a box in the middle of the
+/// screen until Escape is pressed.
+pub fn show_character_sheet(ecs: &World, ctx: &mut BTerm) -> CharacterSheetResult {
+    let theme = ecs.fetch::<Theme>();
+    const WIDTH: i32 = 40;
+
+    let info = gather_character_info(ecs);
+    let mut lines: Vec<(RGB, String)> = vec![
+        (
+            theme.text,
+            format!(
+                "Level {}   XP {} / {}",
+                info.level, info.xp, info.xp_for_next_level
+            ),
+        ),
+        (theme.text, format!("HP {} / {}", info.hp, info.max_hp)),
+        (
+            theme.text,
+            format!("Power {}   Defense {}", info.power, info.defense),
+        ),
+        (
+            theme.text,
+            format!("Attack {}   Dodge {}", info.attack, info.dodge),
+        ),
+        (theme.background, String::new()),
+        (theme.highlight, "Equipment".to_string()),
+    ];
+    if info.equipment.is_empty() {
+        lines.push((theme.dimmed, "nothing".to_string()));
+    }
+    for (slot, name) in info.equipment.iter() {
+        lines.push((theme.text, format!("{:?}: {}", slot, name)));
+    }
+    lines.push((theme.background, String::new()));
+    lines.push((theme.highlight, "Status".to_string()));
+    if info.effects.is_empty() {
+        lines.push((theme.dimmed, "fine".to_string()));
+    }
+    for effect in info.effects.iter() {
+        lines.push((RGB::named(ORANGE), effect.clone()));
+    }
+
+    let (screen_width, screen_height) = ctx.get_char_size();
+    let height = lines.len() as i32 + 1;
+    let x = (screen_width as i32 - WIDTH) / 2;
+    let y = (screen_height as i32 - height) / 2;
+
+    ctx.draw_box(x, y, WIDTH, height, theme.text, theme.background);
+    ctx.print_color(x + 3, y, theme.highlight, theme.background, "Character");
+    ctx.print_color(
+        x + 3,
+        y + height,
+        theme.highlight,
+        theme.background,
+        "ESCAPE to close",
+    );
+    for (line_y, (color, text)) in (y + 1..).zip(lines.iter()) {
+        ctx.print_color(x + 2, line_y, *color, theme.background, text);
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => CharacterSheetResult::Close,
+        _ => CharacterSheetResult::Open,
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LogViewResult {
+    Open { offset: usize },
+    Close,
+}
+
+/// Shows the whole message log over the screen. `offset` is how many lines
+/// back from the newest message the view is scrolled.
+pub fn show_log(ecs: &World, ctx: &mut BTerm, offset: usize) -> LogViewResult {
+    let theme = ecs.fetch::<Theme>();
+    let (screen_width, screen_height) = ctx.get_char_size();
+    let page = screen_height as usize - 2;
+    let log = ecs.fetch::<GameLog>();
+
+    ctx.draw_box(
+        0,
+        0,
+        screen_width as i32 - 1,
+        screen_height as i32 - 1,
+        theme.text,
+        theme.background,
+    );
+    ctx.print_color(3, 0, theme.highlight, theme.background, "Message Log");
+    ctx.print_color(
+        3,
+        screen_height as i32 - 1,
+        theme.highlight,
+        theme.background,
+        "PAGE UP/DOWN to scroll, ESCAPE to close",
+    );
+
+    let end = log.entries.len() - usize::min(offset, log.entries.len());
+    let start = end.saturating_sub(page);
+    for (y, entry) in (1..).zip(log.entries[start..end].iter()) {
+        ctx.print_color(2, y, entry.category.color(), theme.background, &entry.text);
+    }
+
+    let max_offset = log.entries.len().saturating_sub(page);
+    match ctx.key {
+        None => LogViewResult::Open { offset },
+        Some(key) => match key {
+            VirtualKeyCode::Escape => LogViewResult::Close,
+            VirtualKeyCode::PageUp => LogViewResult::Open {
+                offset: usize::min(offset + page, max_offset),
+            },
+            VirtualKeyCode::PageDown => LogViewResult::Open {
+                offset: offset.saturating_sub(page),
+            },
+            VirtualKeyCode::Up => LogViewResult::Open {
+                offset: usize::min(offset + 1, max_offset),
+            },
+            VirtualKeyCode::Down => LogViewResult::Open {
+                offset: offset.saturating_sub(1),
+            },
+            _ => LogViewResult::Open { offset },
+        },
     }
 }
@@ -0,0 +1,97 @@
+use crate::gamelog::{GameLog, LogCategory};
+use crate::map::TileType;
+use crate::{
+    CombatStats, DamageType, EffectKind, Map, MyTurn, Name, Player, Position, RunState,
+    StatusEffect, SufferDamage,
+};
+use specs::prelude::*;
+
+/// Damage taken for ending a turn in lava.
+const LAVA_DAMAGE: i32 = 5;
+
+/// How long, and how badly, things keep burning after leaving the lava.
+const LAVA_BURN_TURNS: i32 = 3;
+const LAVA_BURN_DAMAGE: i32 = 1;
+
+/// Burns whoever ends their turn standing in lava, and sets them alight. The
+/// player's turn ends on `PlayerTurn`, everyone else's on `MonsterTurn`.
+pub struct HazardSystem {}
+
+impl<'a> System<'a> for HazardSystem {
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, RunState>,
+        ReadStorage<'a, MyTurn>,
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, StatusEffect>,
+        WriteExpect<'a, GameLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            map,
+            runstate,
+            my_turn,
+            entities,
+            positions,
+            combat_stats,
+            players,
+            names,
+            mut inflict_damage,
+            mut effects,
+            mut log,
+        ) = data;
+
+        let players_turn_ended = match *runstate {
+            RunState::PlayerTurn => true,
+            RunState::MonsterTurn => false,
+            _ => return,
+        };
+
+        for (entity, pos, _stats) in (&entities, &positions, &combat_stats).join() {
+            // Monsters only end a turn if they got to take one
+            let is_player = players.contains(entity);
+            if is_player != players_turn_ended || !(is_player || my_turn.contains(entity)) {
+                continue;
+            }
+
+            let idx = map.xy_idx(pos.x, pos.y);
+            if map.tiles[idx] != TileType::Lava {
+                continue;
+            }
+
+            SufferDamage::new_damage(
+                &mut inflict_damage,
+                entity,
+                LAVA_DAMAGE,
+                DamageType::Fire,
+                false,
+            );
+            effects
+                .insert(
+                    entity,
+                    StatusEffect {
+                        kind: EffectKind::Burning,
+                        turns: LAVA_BURN_TURNS,
+                        magnitude: LAVA_BURN_DAMAGE,
+                    },
+                )
+                .expect("Unable to insert status effect");
+            if is_player {
+                log.add(LogCategory::Combat, "The lava burns you!");
+            } else if map.visible_tiles[idx] {
+                if let Some(name) = names.get(entity) {
+                    log.add(
+                        LogCategory::Combat,
+                        format!("{} is burned by the lava.", name.name),
+                    );
+                }
+            }
+        }
+    }
+}
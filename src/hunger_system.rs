@@ -0,0 +1,78 @@
+use crate::gamelog::{GameLog, LogCategory};
+use crate::{DamageType, HungerClock, HungerState, RunState, SufferDamage};
+use specs::prelude::*;
+
+/// How many player turns each hunger state lasts before the next one.
+pub const HUNGER_STATE_DURATION: i32 = 200;
+
+pub struct HungerSystem {}
+
+impl<'a> System<'a> for HungerSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, HungerClock>,
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, RunState>,
+        WriteStorage<'a, SufferDamage>,
+        WriteExpect<'a, GameLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut hunger_clock, player_entity, runstate, mut inflict_damage, mut log) =
+            data;
+
+        if *runstate != RunState::PlayerTurn {
+            return;
+        }
+
+        for (entity, clock) in (&entities, &mut hunger_clock).join() {
+            let is_player = entity == *player_entity;
+
+            clock.duration -= 1;
+            if clock.duration >= 1 {
+                continue;
+            }
+
+            match clock.state {
+                HungerState::WellFed => {
+                    clock.state = HungerState::Normal;
+                    clock.duration = HUNGER_STATE_DURATION;
+                    if is_player {
+                        log.add(LogCategory::General, "You are no longer well fed.");
+                    }
+                }
+                HungerState::Normal => {
+                    clock.state = HungerState::Hungry;
+                    clock.duration = HUNGER_STATE_DURATION;
+                    if is_player {
+                        log.add(LogCategory::General, "You are hungry.");
+                    }
+                }
+                HungerState::Hungry => {
+                    clock.state = HungerState::Starving;
+                    clock.duration = HUNGER_STATE_DURATION;
+                    if is_player {
+                        log.add(LogCategory::General, "You are starving!");
+                    }
+                }
+                HungerState::Starving => {
+                    // Keeps hurting every turn until something is eaten
+                    clock.duration = 0;
+                    if is_player {
+                        log.add(
+                            LogCategory::General,
+                            "Your hunger pangs are getting painful!",
+                        );
+                    }
+                    SufferDamage::new_damage(
+                        &mut inflict_damage,
+                        entity,
+                        1,
+                        DamageType::Physical,
+                        false,
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{Initiative, MyTurn, Player, Position, RunState};
+use specs::prelude::*;
+
+/// Initiative an action costs. Something with this speed acts exactly once
+/// for every turn the player takes.
+pub const TURN_COST: i32 = 2;
+
+/// Decides who gets to act. Each player turn tops up everyone's initiative by
+/// their speed; each pass of the monster turn then hands `MyTurn` to whoever
+/// can afford an action. The player isn't scheduled here and still acts
+/// whenever input arrives, and nothing on a level the player has left is
+/// scheduled at all.
+pub struct InitiativeSystem {}
+
+impl<'a> System<'a> for InitiativeSystem {
+    type SystemData = (
+        ReadExpect<'a, RunState>,
+        Entities<'a>,
+        WriteStorage<'a, Initiative>,
+        WriteStorage<'a, MyTurn>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Position>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (runstate, entities, mut initiatives, mut my_turn, players, positions) = data;
+
+        my_turn.clear();
+        match *runstate {
+            RunState::PlayerTurn => {
+                for (initiative, _player, _pos) in (&mut initiatives, !&players, &positions).join()
+                {
+                    initiative.current += initiative.speed;
+                }
+            }
+            RunState::MonsterTurn => {
+                for (entity, initiative, _player, _pos) in
+                    (&entities, &mut initiatives, !&players, &positions).join()
+                {
+                    if initiative.current >= TURN_COST {
+                        initiative.current -= TURN_COST;
+                        my_turn
+                            .insert(entity, MyTurn {})
+                            .expect("Unable to insert turn");
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether anyone still has initiative left for another action before the
+/// player's next turn.
+pub fn anyone_ready(ecs: &World) -> bool {
+    let initiatives = ecs.read_storage::<Initiative>();
+    let positions = ecs.read_storage::<Position>();
+    (&initiatives, &positions)
+        .join()
+        .any(|(initiative, _pos)| initiative.current >= TURN_COST)
+}
@@ -0,0 +1,1168 @@
+use crate::damage_system::after_resistance;
+use crate::event_log_system::{EventQueue, GameEvent};
+use crate::gamelog::{GameLog, LogCategory};
+use crate::hunger_system::HUNGER_STATE_DURATION;
+use crate::particle_system::{line_path, ParticleBuilder};
+use crate::spawner::SpawnQueue;
+use crate::{
+    field_of_view, Ammo, AreaOfEffect, CombatStats, Confusion, Consumable, CritBonus, Cursed,
+    DefenseBonus, EntityMoved, EntryTrigger, Equippable, Equipped, FieldOfView, Gold, HungerClock,
+    HungerState, InInventory, InflictsDamage, InflictsStatus, Item, Knockback, LightSource, Map,
+    MeleePowerBonus, Name, Position, Potion, Price, Projectile, ProvidesFood, ProvidesHealing,
+    ProvidesIdentify, ProvidesMapping, ProvidesSummon, ProvidesTeleport, Ranged, RangedWeapon,
+    Renderable, Resistances, SerializeMe, Stackable, StatusEffect, SufferDamage, Temporary,
+    Unidentified, Wallet, WantsToDropItem, WantsToPickupItem, WantsToRemoveItem, WantsToThrowItem,
+    WantsToUseItem, Weight,
+};
+use bracket_lib::prelude::{
+    to_cp437, BaseMap, Point, RandomNumberGenerator, BLACK, ORANGE, RED, RGB, RGBA,
+};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use specs::saveload::{MarkedBuilder, SimpleMarker};
+use specs::storage::MaskedStorage;
+use std::collections::HashSet;
+use std::ops::Deref;
+
+/// Weight anyone can carry before counting their strength, and how much
+/// each point of power adds to it.
+const BASE_CARRY_CAPACITY: f32 = 15.0;
+const CARRY_CAPACITY_PER_POWER: f32 = 3.0;
+
+/// How much weight someone with `stats` can carry.
+pub fn carry_capacity(stats: &CombatStats) -> f32 {
+    BASE_CARRY_CAPACITY + CARRY_CAPACITY_PER_POWER * stats.power as f32
+}
+
+/// How much an item weighs, the whole stack of it if it's stacked.
+pub fn item_weight<W, S>(
+    weights: &Storage<Weight, W>,
+    stacks: &Storage<Stackable, S>,
+    item: Entity,
+) -> f32
+where
+    W: Deref<Target = MaskedStorage<Weight>>,
+    S: Deref<Target = MaskedStorage<Stackable>>,
+{
+    let count = stacks.get(item).map_or(1, |stack| stack.count);
+    weights.get(item).map_or(0.0, |weight| weight.value) * count as f32
+}
+
+/// The weight of everything `owner` has in their pack or equipped.
+pub fn carried_weight<B, E, W, S>(
+    owner: Entity,
+    entities: &Entities,
+    backpack: &Storage<InInventory, B>,
+    equipped: &Storage<Equipped, E>,
+    weights: &Storage<Weight, W>,
+    stacks: &Storage<Stackable, S>,
+) -> f32
+where
+    B: Deref<Target = MaskedStorage<InInventory>>,
+    E: Deref<Target = MaskedStorage<Equipped>>,
+    W: Deref<Target = MaskedStorage<Weight>>,
+    S: Deref<Target = MaskedStorage<Stackable>>,
+{
+    entities
+        .join()
+        .filter(|item| {
+            backpack.get(*item).is_some_and(|pack| pack.owner == owner)
+                || equipped.get(*item).is_some_and(|worn| worn.owner == owner)
+        })
+        .map(|item| item_weight(weights, stacks, item))
+        .sum()
+}
+
+/// Whether two items go on the same pile: they share a name and either both
+/// or neither are known for what they are.
+pub fn same_kind(
+    known: &KnownItems,
+    first: (&Name, Option<&Unidentified>),
+    second: (&Name, Option<&Unidentified>),
+) -> bool {
+    first.0.name == second.0.name
+        && known.recognises(first.0, first.1) == known.recognises(second.0, second.1)
+}
+
+/// Puts an item into `owner`'s pack, onto a pile of the same kind if they
+/// have one.
+pub fn put_in_pack(ecs: &mut World, owner: Entity, item: Entity) {
+    let entities = ecs.entities();
+    let known = ecs.fetch::<KnownItems>();
+    let names = ecs.read_storage::<Name>();
+    let unidentified = ecs.read_storage::<Unidentified>();
+    let mut backpack = ecs.write_storage::<InInventory>();
+    let mut stacks = ecs.write_storage::<Stackable>();
+    stow(
+        &entities,
+        &known,
+        (&names, &unidentified),
+        &mut backpack,
+        &mut stacks,
+        owner,
+        item,
+    );
+}
+
+/// `put_in_pack` for systems, which have the storages rather than the world.
+/// An item that goes onto a pile is deleted.
+fn stow(
+    entities: &Entities,
+    known: &KnownItems,
+    (names, unidentified): (&ReadStorage<Name>, &ReadStorage<Unidentified>),
+    backpack: &mut WriteStorage<InInventory>,
+    stacks: &mut WriteStorage<Stackable>,
+    owner: Entity,
+    item: Entity,
+) {
+    let pile = stacks.get(item).and(names.get(item)).and_then(|name| {
+        (entities, &*backpack, &*stacks, names)
+            .join()
+            .find(|(other, pack, _stack, other_name)| {
+                pack.owner == owner
+                    && *other != item
+                    && same_kind(
+                        known,
+                        (name, unidentified.get(item)),
+                        (other_name, unidentified.get(*other)),
+                    )
+            })
+            .map(|(other, _pack, _stack, _name)| other)
+    });
+
+    if let Some(pile) = pile {
+        let count = stacks.get(item).map_or(1, |stack| stack.count);
+        if let Some(stack) = stacks.get_mut(pile) {
+            stack.count += count;
+        }
+        // Out of every pack now, even before it's gone
+        backpack.remove(item);
+        entities.delete(item).expect("Delete failed");
+        return;
+    }
+    backpack
+        .insert(item, InInventory { owner })
+        .expect("Unable to insert backpack entry");
+}
+
+/// Gives `to` a copy of each of the listed components that `from` has.
+macro_rules! copy_components {
+    ($ecs:expr, $from:expr, $to:expr, $( $type:ty ),*) => {
+        $(
+        let component = $ecs.read_storage::<$type>().get($from).cloned();
+        if let Some(component) = component {
+            $ecs.write_storage::<$type>()
+                .insert($to, component)
+                .expect("Unable to copy component");
+        }
+        )*
+    };
+}
+
+/// Splits one item off a stack, into the same pack, and gives it back. Items
+/// that aren't stacked are given back as they are.
+pub fn split_stack(ecs: &mut World, item: Entity) -> Entity {
+    let count = ecs
+        .read_storage::<Stackable>()
+        .get(item)
+        .map_or(1, |stack| stack.count);
+    let owner = ecs
+        .read_storage::<InInventory>()
+        .get(item)
+        .map(|pack| pack.owner);
+    let Some(owner) = owner else {
+        return item;
+    };
+    if count <= 1 {
+        return item;
+    }
+
+    let single = ecs
+        .create_entity()
+        .with(InInventory { owner })
+        .with(Stackable { count: 1 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build();
+    copy_components!(
+        ecs,
+        item,
+        single,
+        Item,
+        Name,
+        Renderable,
+        Unidentified,
+        Cursed,
+        Consumable,
+        Potion,
+        Price,
+        Weight,
+        Ammo,
+        AreaOfEffect,
+        Confusion,
+        CritBonus,
+        DefenseBonus,
+        EntryTrigger,
+        Equippable,
+        InflictsDamage,
+        InflictsStatus,
+        Knockback,
+        LightSource,
+        MeleePowerBonus,
+        Projectile,
+        ProvidesFood,
+        ProvidesHealing,
+        ProvidesIdentify,
+        ProvidesMapping,
+        ProvidesSummon,
+        ProvidesTeleport,
+        Ranged,
+        RangedWeapon,
+        Temporary
+    );
+    if let Some(stack) = ecs.write_storage::<Stackable>().get_mut(item) {
+        stack.count -= 1;
+    }
+    single
+}
+
+pub struct ItemCollectionSystem {}
+
+impl<'a> System<'a> for ItemCollectionSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToPickupItem>,
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, InInventory>,
+        ReadStorage<'a, Gold>,
+        WriteStorage<'a, Wallet>,
+        ReadStorage<'a, Unidentified>,
+        WriteStorage<'a, Stackable>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, Weight>,
+        ReadStorage<'a, CombatStats>,
+        WriteExpect<'a, EventQueue>,
+        ReadExpect<'a, KnownItems>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player_entity,
+            mut log,
+            entities,
+            mut wants_pickup,
+            mut positions,
+            names,
+            mut backpack,
+            gold,
+            mut wallets,
+            unidentified,
+            mut stacks,
+            equipped,
+            weights,
+            combat_stats,
+            mut events,
+            known,
+        ) = data;
+
+        for pickup in wants_pickup.join() {
+            // Coins go straight into the wallet rather than the backpack
+            if let (Some(gold), Some(wallet)) =
+                (gold.get(pickup.item), wallets.get_mut(pickup.collected_by))
+            {
+                wallet.gold += gold.amount;
+                entities.delete(pickup.item).expect("Delete failed");
+                if pickup.collected_by == *player_entity {
+                    events.push(GameEvent::GoldPickup {
+                        amount: gold.amount,
+                    });
+                }
+                continue;
+            }
+
+            if let Some(stats) = combat_stats.get(pickup.collected_by) {
+                let carried = carried_weight(
+                    pickup.collected_by,
+                    &entities,
+                    &backpack,
+                    &equipped,
+                    &weights,
+                    &stacks,
+                );
+                if carried + item_weight(&weights, &stacks, pickup.item) > carry_capacity(stats) {
+                    if pickup.collected_by == *player_entity {
+                        log.add(LogCategory::Item, "It's too heavy to carry.");
+                    }
+                    continue;
+                }
+            }
+
+            if pickup.collected_by == *player_entity {
+                events.push(GameEvent::Pickup {
+                    item: item_name(&names, &unidentified, &known, pickup.item),
+                });
+            }
+
+            positions.remove(pickup.item);
+            stow(
+                &entities,
+                &known,
+                (&names, &unidentified),
+                &mut backpack,
+                &mut stacks,
+                pickup.collected_by,
+                pickup.item,
+            );
+        }
+
+        wants_pickup.clear();
+    }
+}
+
+pub struct ItemUseSystem {}
+
+impl<'a> System<'a> for ItemUseSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, Map>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Name>,
+        (ReadStorage<'a, Consumable>, WriteStorage<'a, Stackable>),
+        ReadStorage<'a, ProvidesHealing>,
+        (
+            ReadStorage<'a, InflictsDamage>,
+            ReadStorage<'a, Resistances>,
+        ),
+        ReadStorage<'a, AreaOfEffect>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, Confusion>,
+        WriteStorage<'a, Position>,
+        WriteExpect<'a, ParticleBuilder>,
+        ReadStorage<'a, ProvidesFood>,
+        WriteStorage<'a, HungerClock>,
+        ReadStorage<'a, ProvidesMapping>,
+        ReadStorage<'a, ProvidesTeleport>,
+        WriteStorage<'a, FieldOfView>,
+        WriteExpect<'a, Point>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        WriteStorage<'a, EntityMoved>,
+        ReadStorage<'a, ProvidesSummon>,
+        WriteExpect<'a, SpawnQueue>,
+        ReadStorage<'a, Projectile>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player_entity,
+            mut log,
+            mut map,
+            entities,
+            mut wants_use,
+            names,
+            (consumables, mut stacks),
+            healing,
+            (inflict_damage, resistances),
+            aoe,
+            mut combat_stats,
+            mut suffer_damage,
+            mut confused,
+            mut positions,
+            mut particle_builder,
+            food,
+            mut hunger_clocks,
+            mapping,
+            teleports,
+            mut fovs,
+            mut player_pos,
+            mut rng,
+            mut entity_moved,
+            summons,
+            mut spawns,
+            projectiles,
+        ) = data;
+
+        for (entity, useitem) in (&entities, &wants_use).join() {
+            let item_name = &names.get(useitem.item).unwrap().name;
+
+            // Targeted items affect whoever stands on the chosen tile (or
+            // within the blast, which walls block), everything else affects
+            // the user.
+            let targets: Vec<Entity> = match useitem.target {
+                None => vec![entity],
+                Some(target) => {
+                    if let (Some(projectile), Some(pos)) =
+                        (projectiles.get(useitem.item), positions.get(entity))
+                    {
+                        let path = line_path(Point::new(pos.x, pos.y), target);
+                        particle_builder.projectile(&path, projectile.fg, projectile.glyph);
+                    }
+
+                    let tiles =
+                        affected_tiles(&map, target, aoe.get(useitem.item), &mut particle_builder);
+                    creatures_on(&map, &tiles, &combat_stats)
+                }
+            };
+
+            if useitem.target.is_some() && targets.is_empty() && entity == *player_entity {
+                log.add(
+                    LogCategory::Item,
+                    format!("The {} hits nothing.", item_name),
+                );
+            }
+
+            if let Some(healer) = healing.get(useitem.item) {
+                for target in targets.iter() {
+                    if let Some(stats) = combat_stats.get_mut(*target) {
+                        let before = stats.hp;
+                        stats.hp = i32::min(stats.max_hp, stats.hp + healer.heal_amount);
+                        if entity == *player_entity {
+                            log.add(
+                                LogCategory::Item,
+                                format!(
+                                    "You drink the {}, healing {} hp.",
+                                    item_name,
+                                    stats.hp - before
+                                ),
+                            );
+                        }
+                    }
+                }
+            }
+
+            if food.get(useitem.item).is_some() {
+                for target in targets.iter() {
+                    if let Some(clock) = hunger_clocks.get_mut(*target) {
+                        clock.state = HungerState::WellFed;
+                        clock.duration = HUNGER_STATE_DURATION;
+                        if *target == *player_entity {
+                            log.add(LogCategory::Item, format!("You eat the {}.", item_name));
+                        }
+                    }
+                }
+            }
+
+            if let Some(damage) = inflict_damage.get(useitem.item) {
+                for mob in targets.iter() {
+                    SufferDamage::new_damage(
+                        &mut suffer_damage,
+                        *mob,
+                        damage.damage,
+                        damage.kind,
+                        entity == *player_entity,
+                    );
+                    if let Some(pos) = positions.get(*mob) {
+                        particle_builder.request(
+                            pos.x,
+                            pos.y,
+                            RGB::named(RED),
+                            RGBA::named(BLACK),
+                            to_cp437('‼'),
+                            200.0,
+                        );
+                    }
+                    if entity == *player_entity {
+                        log.add(
+                            LogCategory::Item,
+                            format!(
+                                "You use {} on {}, inflicting {} hp.",
+                                item_name,
+                                names.get(*mob).unwrap().name,
+                                after_resistance(damage.damage, damage.kind, resistances.get(*mob))
+                            ),
+                        );
+                    }
+                }
+            }
+
+            // Only the player's view of the map is kept, so only they learn
+            // anything. What's out there stays out of sight.
+            if mapping.get(useitem.item).is_some() && entity == *player_entity {
+                for revealed in map.revealed_tiles.iter_mut() {
+                    *revealed = true;
+                }
+                log.add(LogCategory::Item, "The map is revealed to you!");
+            }
+
+            if let Some(teleport) = teleports.get(useitem.item) {
+                let destination = if teleport.random {
+                    positions
+                        .get(entity)
+                        .and_then(|pos| random_open_tile(&map, &mut rng, pos.x, pos.y))
+                } else {
+                    useitem
+                        .target
+                        .filter(|target| map.is_open_tile(target.x, target.y))
+                };
+                match (destination, positions.get_mut(entity)) {
+                    (Some(destination), Some(pos)) => {
+                        pos.x = destination.x;
+                        pos.y = destination.y;
+                        if let Some(fov) = fovs.get_mut(entity) {
+                            fov.dirty = true;
+                        }
+                        entity_moved
+                            .insert(entity, EntityMoved {})
+                            .expect("Unable to insert marker");
+                        if entity == *player_entity {
+                            *player_pos = destination;
+                            log.add(LogCategory::Item, "You blink across the dungeon.");
+                        }
+                    }
+                    _ => {
+                        if entity == *player_entity {
+                            log.add(LogCategory::Item, "You flicker, but stay put.");
+                        }
+                    }
+                }
+            }
+
+            if let Some(summon) = summons.get(useitem.item) {
+                let spot = positions
+                    .get(entity)
+                    .and_then(|pos| map.open_neighbor(pos.x, pos.y));
+                match spot {
+                    Some(spot) => {
+                        spawns.request_ally(&summon.creature, spot.x, spot.y, summon.turns);
+                        // Nobody else gets to land there before it shows up
+                        let idx = map.xy_idx(spot.x, spot.y);
+                        map.blocked[idx] = true;
+                        if entity == *player_entity {
+                            log.add(
+                                LogCategory::Item,
+                                format!("A {} answers your call.", summon.creature),
+                            );
+                        }
+                    }
+                    None => {
+                        if entity == *player_entity {
+                            log.add(LogCategory::Item, "Nothing answers your call.");
+                        }
+                    }
+                }
+            }
+
+            // The item's own `Confusion` says how long its victims stay confused
+            if let Some(turns) = confused.get(useitem.item).map(|confusion| confusion.turns) {
+                for mob in targets.iter() {
+                    confused
+                        .insert(*mob, Confusion { turns })
+                        .expect("Unable to insert status");
+                    if entity == *player_entity {
+                        log.add(
+                            LogCategory::Item,
+                            format!(
+                                "You use {} on {}, confusing them.",
+                                item_name,
+                                names.get(*mob).unwrap().name
+                            ),
+                        );
+                    }
+                }
+            }
+
+            if consumables.get(useitem.item).is_some() {
+                match stacks.get_mut(useitem.item) {
+                    Some(stack) if stack.count > 1 => stack.count -= 1,
+                    _ => entities.delete(useitem.item).expect("Delete failed"),
+                }
+            }
+        }
+
+        wants_use.clear();
+    }
+}
+
+/// The tiles an item aimed at `target` affects: just that one, or everything
+/// in the blast that walls don't shelter. Blasts are shown as they go off.
+fn affected_tiles(
+    map: &Map,
+    target: Point,
+    aoe: Option<&AreaOfEffect>,
+    particle_builder: &mut ParticleBuilder,
+) -> Vec<Point> {
+    let Some(area_effect) = aoe else {
+        return vec![target];
+    };
+
+    let mut blast_tiles = field_of_view(target, area_effect.radius, map);
+    blast_tiles.retain(|p| p.x > 0 && p.x < map.width - 1 && p.y > 0 && p.y < map.height - 1);
+    for tile in blast_tiles.iter() {
+        particle_builder.request(
+            tile.x,
+            tile.y,
+            RGB::named(ORANGE),
+            RGBA::named(BLACK),
+            to_cp437('░'),
+            200.0,
+        );
+    }
+    blast_tiles
+}
+
+/// Everyone who can be hurt standing on one of `tiles`.
+fn creatures_on(
+    map: &Map,
+    tiles: &[Point],
+    combat_stats: &WriteStorage<CombatStats>,
+) -> Vec<Entity> {
+    tiles
+        .iter()
+        .flat_map(|tile| map.tile_content[map.xy_idx(tile.x, tile.y)].iter())
+        .filter(|mob| combat_stats.contains(**mob))
+        .cloned()
+        .collect()
+}
+
+/// Picks a random open tile that can be walked to from `(x, y)`, so nobody
+/// ends up shut in behind a locked door. Re-rolls whenever a tile turns out to
+/// be a wall, water or occupied, and gives up eventually on a map with hardly
+/// any room.
+fn random_open_tile(
+    map: &Map,
+    rng: &mut RandomNumberGenerator,
+    from_x: i32,
+    from_y: i32,
+) -> Option<Point> {
+    const ATTEMPTS: i32 = 1000;
+
+    let distances = map.walking_distances(from_x, from_y);
+    for _ in 0..ATTEMPTS {
+        let x = rng.range(1, map.width - 1);
+        let y = rng.range(1, map.height - 1);
+        if map.is_open_tile(x, y) && distances[map.xy_idx(x, y)].is_some() {
+            return Some(Point::new(x, y));
+        }
+    }
+
+    None
+}
+
+/// The kinds of item the player has learned this game, by name. Anything of
+/// a known kind goes by its real name, even if it turned up after the player
+/// learned it.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct KnownItems {
+    names: HashSet<String>,
+}
+
+impl KnownItems {
+    pub fn new() -> KnownItems {
+        KnownItems::default()
+    }
+
+    pub fn learn(&mut self, name: &str) {
+        self.names.insert(name.to_string());
+    }
+
+    /// Whether the player can tell what something called `name` is, given
+    /// the generic name it goes by if its kind needs identifying.
+    pub fn recognises(&self, name: &Name, unknown: Option<&Unidentified>) -> bool {
+        unknown.is_none() || self.names.contains(&name.name)
+    }
+
+    /// What the player calls something called `name`.
+    pub fn name_of(&self, name: &Name, unknown: Option<&Unidentified>) -> String {
+        match unknown {
+            Some(unknown) if !self.recognises(name, Some(unknown)) => unknown.name.clone(),
+            _ => name.name.clone(),
+        }
+    }
+}
+
+/// What the player knows `item` as: its real name once its kind has been
+/// identified, a generic one before that.
+pub fn item_name(
+    names: &ReadStorage<Name>,
+    unidentified: &ReadStorage<Unidentified>,
+    known: &KnownItems,
+    item: Entity,
+) -> String {
+    known.name_of(names.get(item).unwrap(), unidentified.get(item))
+}
+
+/// Identifies the kind of whatever the player uses, so they know every item
+/// of that kind from then on, as well as everything they carry when they
+/// read an identify scroll. Must run before `ItemUseSystem`, which clears the
+/// use requests, so that its messages name what was really used.
+pub struct IdentifySystem {}
+
+impl<'a> System<'a> for IdentifySystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        ReadStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, ProvidesIdentify>,
+        ReadStorage<'a, InInventory>,
+        ReadStorage<'a, Equipped>,
+        WriteExpect<'a, KnownItems>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player_entity,
+            mut log,
+            entities,
+            wants_use,
+            names,
+            identifiers,
+            backpack,
+            equipped,
+            mut known,
+        ) = data;
+
+        for (user, useitem) in (&entities, &wants_use).join() {
+            if user != *player_entity {
+                continue;
+            }
+
+            let mut to_identify = vec![useitem.item];
+            if identifiers.contains(useitem.item) {
+                to_identify.extend(
+                    (&entities, (&backpack).maybe(), (&equipped).maybe())
+                        .join()
+                        .filter(|(_item, pack, worn)| {
+                            pack.is_some_and(|pack| pack.owner == user)
+                                || worn.is_some_and(|worn| worn.owner == user)
+                        })
+                        .map(|(item, _pack, _worn)| item),
+                );
+                log.add(
+                    LogCategory::Item,
+                    "You now know everything you carry for what it is.",
+                );
+            }
+
+            for item in to_identify {
+                if let Some(name) = names.get(item) {
+                    known.learn(&name.name);
+                }
+            }
+        }
+    }
+}
+
+/// Handles using an equippable item: it moves from the pack into its slot,
+/// and whatever was in that slot goes back into the pack. Must run before
+/// `ItemUseSystem`, which clears the use requests.
+pub struct EquipSystem {}
+
+impl<'a> System<'a> for EquipSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        ReadStorage<'a, WantsToUseItem>,
+        WriteStorage<'a, Name>,
+        ReadStorage<'a, Equippable>,
+        WriteStorage<'a, Equipped>,
+        WriteStorage<'a, InInventory>,
+        ReadStorage<'a, Cursed>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player_entity,
+            mut log,
+            entities,
+            wants_use,
+            mut names,
+            equippable,
+            mut equipped,
+            mut backpack,
+            cursed,
+        ) = data;
+
+        for (target, useitem) in (&entities, &wants_use).join() {
+            let slot = match equippable.get(useitem.item) {
+                None => continue,
+                Some(can_equip) => can_equip.slot,
+            };
+
+            let to_unequip: Vec<Entity> = (&entities, &equipped)
+                .join()
+                .filter(|(_item, already)| already.owner == target && already.slot == slot)
+                .map(|(item, _already)| item)
+                .collect();
+            if let Some(stuck) = to_unequip.iter().find(|item| cursed.contains(**item)) {
+                if target == *player_entity {
+                    log.add(
+                        LogCategory::Item,
+                        format!(
+                            "You can't take off the {} — it's cursed!",
+                            names.get(*stuck).unwrap().name
+                        ),
+                    );
+                }
+                continue;
+            }
+            for item in to_unequip {
+                equipped.remove(item);
+                backpack
+                    .insert(item, InInventory { owner: target })
+                    .expect("Unable to insert backpack entry");
+                if target == *player_entity {
+                    log.add(
+                        LogCategory::Item,
+                        format!("You unequip {}.", names.get(item).unwrap().name),
+                    );
+                }
+            }
+
+            equipped
+                .insert(
+                    useitem.item,
+                    Equipped {
+                        owner: target,
+                        slot,
+                    },
+                )
+                .expect("Unable to insert equipped component");
+            backpack.remove(useitem.item);
+            if target == *player_entity {
+                log.add(
+                    LogCategory::Item,
+                    format!("You equip {}.", names.get(useitem.item).unwrap().name),
+                );
+            }
+
+            // The curse shows itself now, and the item is known for it after
+            if cursed.contains(useitem.item) {
+                let name = names.get_mut(useitem.item).unwrap();
+                if target == *player_entity {
+                    log.add(
+                        LogCategory::Item,
+                        format!("The {} is cursed! It won't let go.", name.name),
+                    );
+                }
+                if !name.name.starts_with("Cursed ") {
+                    name.name = format!("Cursed {}", name.name);
+                }
+            }
+        }
+    }
+}
+
+/// Puts dropped items down on the dropper's tile, where they can be picked up
+/// again.
+pub struct ItemDropSystem {}
+
+impl<'a> System<'a> for ItemDropSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToDropItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Unidentified>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, InInventory>,
+        ReadExpect<'a, KnownItems>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player_entity,
+            mut log,
+            entities,
+            mut wants_drop,
+            names,
+            unidentified,
+            mut positions,
+            mut backpack,
+            known,
+        ) = data;
+
+        for (entity, to_drop) in (&entities, &wants_drop).join() {
+            let Some(dropper_pos) = positions.get(entity).cloned() else {
+                continue;
+            };
+            positions
+                .insert(to_drop.item, dropper_pos)
+                .expect("Unable to insert position");
+            backpack.remove(to_drop.item);
+
+            if entity == *player_entity {
+                log.add(
+                    LogCategory::Item,
+                    format!(
+                        "You drop the {}.",
+                        item_name(&names, &unidentified, &known, to_drop.item)
+                    ),
+                );
+            }
+        }
+
+        wants_drop.clear();
+    }
+}
+
+/// Takes equipped items off and puts them back in the pack, unless they're
+/// cursed. Combat only counts what's equipped, so the bonuses go with them.
+pub struct ItemRemoveSystem {}
+
+impl<'a> System<'a> for ItemRemoveSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToRemoveItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Cursed>,
+        WriteStorage<'a, Equipped>,
+        WriteStorage<'a, InInventory>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player_entity,
+            mut log,
+            entities,
+            mut wants_remove,
+            names,
+            cursed,
+            mut equipped,
+            mut backpack,
+        ) = data;
+
+        for (entity, to_remove) in (&entities, &wants_remove).join() {
+            let is_player = entity == *player_entity;
+            if cursed.contains(to_remove.item) {
+                if is_player {
+                    log.add(LogCategory::Item, "You can't remove it — it's cursed!");
+                }
+                continue;
+            }
+
+            equipped.remove(to_remove.item);
+            backpack
+                .insert(to_remove.item, InInventory { owner: entity })
+                .expect("Unable to insert backpack entry");
+            if is_player {
+                log.add(
+                    LogCategory::Item,
+                    format!("You unequip {}.", names.get(to_remove.item).unwrap().name),
+                );
+            }
+        }
+
+        wants_remove.clear();
+    }
+}
+
+/// How far something of the given weight can be thrown. Light things go
+/// further; anything without a weight counts as weighing as much as a
+/// dagger.
+pub fn throw_range(weight: Option<f32>) -> i32 {
+    const MAX_RANGE: i32 = 8;
+
+    let weight = weight.unwrap_or(1.0).max(0.1);
+    ((6.0 / weight) as i32).clamp(1, MAX_RANGE)
+}
+
+/// How far from where it lands a thrown potion splashes, unless it has an
+/// area of effect of its own.
+const SPLASH_RADIUS: i32 = 1;
+
+/// Sends an item flying at a tile. Anything that hurts or confuses does so
+/// where it lands and is used up, and potions shatter, splashing everyone
+/// nearby. Anything else just ends up on the floor there, or short of the
+/// first wall in the way.
+pub struct ItemThrowSystem {}
+
+impl<'a> System<'a> for ItemThrowSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Map>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToThrowItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Unidentified>,
+        ReadStorage<'a, InflictsDamage>,
+        ReadStorage<'a, AreaOfEffect>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, Confusion>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, InInventory>,
+        ReadStorage<'a, Renderable>,
+        WriteExpect<'a, ParticleBuilder>,
+        ReadStorage<'a, Potion>,
+        ReadStorage<'a, ProvidesHealing>,
+        ReadStorage<'a, InflictsStatus>,
+        WriteStorage<'a, StatusEffect>,
+        WriteExpect<'a, SpawnQueue>,
+        ReadExpect<'a, KnownItems>,
+        ReadStorage<'a, Resistances>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player_entity,
+            mut log,
+            map,
+            entities,
+            mut wants_throw,
+            names,
+            unidentified,
+            inflict_damage,
+            aoe,
+            mut combat_stats,
+            mut suffer_damage,
+            mut confused,
+            mut positions,
+            mut backpack,
+            renderables,
+            mut particle_builder,
+            potions,
+            healing,
+            inflicts_status,
+            mut status_effects,
+            mut spawns,
+            known,
+            resistances,
+        ) = data;
+
+        for (entity, throw) in (&entities, &wants_throw).join() {
+            let Some(from) = positions.get(entity).map(|pos| Point::new(pos.x, pos.y)) else {
+                continue;
+            };
+            let item_name = item_name(&names, &unidentified, &known, throw.item);
+            let is_player = entity == *player_entity;
+
+            // It flies until the target or until a wall or closed door stops it
+            let path: Vec<Point> = line_path(from, throw.target)
+                .into_iter()
+                .take_while(|p| !map.is_opaque(map.xy_idx(p.x, p.y)))
+                .collect();
+            let landing = *path.last().unwrap_or(&from);
+            if let Some(renderable) = renderables.get(throw.item) {
+                particle_builder.projectile(&path, renderable.fg, renderable.glyph);
+            }
+
+            backpack.remove(throw.item);
+            if is_player {
+                log.add(LogCategory::Item, format!("You throw the {}.", item_name));
+            }
+
+            let potion = potions.get(throw.item).cloned();
+            let damage = inflict_damage.get(throw.item).cloned();
+            let confusion = confused.get(throw.item).map(|confusion| confusion.turns);
+            let status = inflicts_status
+                .get(throw.item)
+                .map(|inflicts| inflicts.effect.clone());
+            let heal_amount = healing.get(throw.item).map(|healer| healer.heal_amount);
+            if potion.is_none() && damage.is_none() && confusion.is_none() {
+                positions
+                    .insert(
+                        throw.item,
+                        Position {
+                            x: landing.x,
+                            y: landing.y,
+                        },
+                    )
+                    .expect("Unable to insert position");
+                continue;
+            }
+
+            let splash = potion.as_ref().map(|_potion| AreaOfEffect {
+                radius: SPLASH_RADIUS,
+            });
+            let area = aoe.get(throw.item).or(splash.as_ref());
+            let tiles = affected_tiles(&map, landing, area, &mut particle_builder);
+            let targets = creatures_on(&map, &tiles, &combat_stats);
+            if potion.is_some() && map.visible_tiles[map.xy_idx(landing.x, landing.y)] {
+                log.add(LogCategory::Item, format!("The {} shatters!", item_name));
+            }
+            if targets.is_empty() && is_player {
+                log.add(
+                    LogCategory::Item,
+                    format!("The {} hits nothing.", item_name),
+                );
+            }
+            for mob in targets.iter() {
+                let mob_name = &names.get(*mob).unwrap().name;
+                if let Some(damage) = &damage {
+                    SufferDamage::new_damage(
+                        &mut suffer_damage,
+                        *mob,
+                        damage.damage,
+                        damage.kind,
+                        is_player,
+                    );
+                    if let Some(pos) = positions.get(*mob) {
+                        particle_builder.request(
+                            pos.x,
+                            pos.y,
+                            RGB::named(RED),
+                            RGBA::named(BLACK),
+                            to_cp437('‼'),
+                            200.0,
+                        );
+                    }
+                    if is_player {
+                        log.add(
+                            LogCategory::Combat,
+                            format!(
+                                "The {} hits {} for {} hp.",
+                                item_name,
+                                mob_name,
+                                after_resistance(damage.damage, damage.kind, resistances.get(*mob))
+                            ),
+                        );
+                    }
+                }
+                if let Some(turns) = confusion {
+                    confused
+                        .insert(*mob, Confusion { turns })
+                        .expect("Unable to insert status");
+                    if is_player {
+                        log.add(
+                            LogCategory::Combat,
+                            format!("The {} leaves {} confused.", item_name, mob_name),
+                        );
+                    }
+                }
+                if let Some(effect) = &status {
+                    status_effects
+                        .insert(*mob, effect.clone())
+                        .expect("Unable to insert status");
+                    if is_player {
+                        log.add(
+                            LogCategory::Combat,
+                            format!(
+                                "The {} leaves {} {}.",
+                                item_name,
+                                mob_name,
+                                effect.kind.adjective()
+                            ),
+                        );
+                    }
+                }
+                if let (Some(heal_amount), Some(stats)) = (heal_amount, combat_stats.get_mut(*mob))
+                {
+                    stats.hp = i32::min(stats.max_hp, stats.hp + heal_amount);
+                    if is_player {
+                        log.add(
+                            LogCategory::Item,
+                            format!("The {} heals {}.", item_name, mob_name),
+                        );
+                    }
+                }
+            }
+            if let Some(puddle) = potion.and_then(|potion| potion.puddle) {
+                spawns.request(&puddle, landing.x, landing.y);
+            }
+            entities.delete(throw.item).expect("Delete failed");
+        }
+
+        wants_throw.clear();
+    }
+}
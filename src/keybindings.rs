@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use bracket_lib::prelude::VirtualKeyCode;
+use serde::Deserialize;
+
+/// Where players can rebind keys, in RON. Each entry names an action and
+/// lists the keys for it, like `{ PickUp: ["P"], MoveNorth: ["E", "Up"] }`.
+/// Key names are those of `VirtualKeyCode`, with `Shift+` in front for the key
+/// pressed with shift held, like `"Shift+R"`. Actions that aren't mentioned
+/// keep their default keys.
+const KEYBINDINGS_FILE: &str = "keybindings.ron";
+
+/// Everything the player can do with a key press on the map.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Deserialize)]
+pub enum Action {
+    MoveWest,
+    MoveEast,
+    MoveNorth,
+    MoveSouth,
+    MoveNorthEast,
+    MoveNorthWest,
+    MoveSouthEast,
+    MoveSouthWest,
+    Wait,
+    Explore,
+    PickUp,
+    Inventory,
+    Throw,
+    Drop,
+    Remove,
+    CharacterSheet,
+    Quests,
+    Cast,
+    Fire,
+    Sneak,
+    Search,
+    Rest,
+    Log,
+    Examine,
+    Quaff,
+    SaveAndQuit,
+    Descend,
+    Ascend,
+    GoToStairs,
+    ToggleMinimap,
+    ToggleWizardMode,
+}
+
+/// A key, and whether it's pressed with shift held.
+type Key = (VirtualKeyCode, bool);
+
+/// Which key does what. Each key does at most one thing.
+pub struct Keybindings {
+    actions: HashMap<Key, Action>,
+}
+
+impl Keybindings {
+    /// Arrow keys, the numpad and vi keys to move, and a letter for the rest.
+    pub fn defaults() -> Keybindings {
+        use VirtualKeyCode::*;
+
+        let defaults = [
+            (Action::MoveWest, vec![Left, Numpad4, H]),
+            (Action::MoveEast, vec![Right, Numpad6, L]),
+            (Action::MoveNorth, vec![Up, Numpad8, K]),
+            (Action::MoveSouth, vec![Down, Numpad2, J]),
+            (Action::MoveNorthEast, vec![Numpad9, U]),
+            (Action::MoveNorthWest, vec![Numpad7, Y]),
+            (Action::MoveSouthEast, vec![Numpad3, N]),
+            (Action::MoveSouthWest, vec![Numpad1, B]),
+            (Action::Wait, vec![Numpad5, Space]),
+            (Action::Explore, vec![O]),
+            (Action::PickUp, vec![G]),
+            (Action::Inventory, vec![I]),
+            (Action::Throw, vec![T]),
+            (Action::Drop, vec![D]),
+            (Action::Remove, vec![R]),
+            (Action::CharacterSheet, vec![C]),
+            (Action::Cast, vec![Z]),
+            (Action::Fire, vec![F]),
+            (Action::Sneak, vec![S]),
+            (Action::Log, vec![M]),
+            (Action::Examine, vec![X]),
+            (Action::Quaff, vec![Q]),
+            (Action::SaveAndQuit, vec![Escape]),
+            (Action::Descend, vec![Period]),
+            (Action::ToggleMinimap, vec![Tab]),
+        ];
+        let shifted_defaults = [
+            (Action::Rest, vec![R]),
+            (Action::Search, vec![S]),
+            (Action::GoToStairs, vec![Period]),
+            (Action::Ascend, vec![Comma]),
+            (Action::ToggleWizardMode, vec![W]),
+            (Action::Quests, vec![Q]),
+        ];
+
+        let mut keybindings = Keybindings {
+            actions: HashMap::new(),
+        };
+        for (action, keys) in defaults {
+            let keys: Vec<Key> = keys.into_iter().map(|key| (key, false)).collect();
+            keybindings.bind(action, &keys);
+        }
+        for (action, keys) in shifted_defaults {
+            let keys: Vec<Key> = keys.into_iter().map(|key| (key, true)).collect();
+            keybindings.bind(action, &keys);
+        }
+        keybindings
+    }
+
+    /// The defaults with whatever the keybindings file changes, or just the
+    /// defaults if there's no file or it can't be made sense of.
+    pub fn load() -> Keybindings {
+        std::fs::read_to_string(KEYBINDINGS_FILE)
+            .ok()
+            .and_then(|text| Keybindings::defaults().with_overrides(&text))
+            .unwrap_or_else(Keybindings::defaults)
+    }
+
+    /// Rebinds the actions listed in `text`, written the way the keybindings
+    /// file is. A key taken for one action stops doing whatever it did
+    /// before. Gives `None` if the text isn't valid or names an unknown key.
+    pub fn with_overrides(mut self, text: &str) -> Option<Keybindings> {
+        let overrides: HashMap<Action, Vec<String>> = ron::from_str(text).ok()?;
+        for (action, names) in overrides {
+            let keys = names
+                .iter()
+                .map(|name| key_named(name))
+                .collect::<Option<Vec<_>>>()?;
+            self.actions.retain(|_key, bound| *bound != action);
+            self.bind(action, &keys);
+        }
+        Some(self)
+    }
+
+    fn bind(&mut self, action: Action, keys: &[Key]) {
+        for key in keys {
+            self.actions.insert(*key, action);
+        }
+    }
+
+    /// What pressing `key` does, if anything. With shift held, keys without
+    /// a shifted binding of their own do what they do without it.
+    pub fn action(&self, key: VirtualKeyCode, shift: bool) -> Option<Action> {
+        self.actions
+            .get(&(key, shift))
+            .or_else(|| self.actions.get(&(key, false)))
+            .copied()
+    }
+}
+
+/// Looks a key up by its `VirtualKeyCode` name, `Shift+` and all.
+pub fn key_named(name: &str) -> Option<Key> {
+    let (name, shift) = match name.strip_prefix("Shift+") {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    BINDABLE_KEYS
+        .iter()
+        .find(|key| format!("{:?}", key) == name)
+        .map(|key| (*key, shift))
+}
+
+/// The keys that can be bound to an action.
+const BINDABLE_KEYS: &[VirtualKeyCode] = {
+    use VirtualKeyCode::*;
+    &[
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1,
+        Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Numpad0, Numpad1, Numpad2, Numpad3,
+        Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9, Left, Right, Up, Down, Home, End,
+        PageUp, PageDown, Space, Return, Tab, Escape, Back, Delete, Insert, Period, Comma,
+        Semicolon, Slash, Minus, Equals, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    ]
+};
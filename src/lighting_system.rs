@@ -0,0 +1,83 @@
+use crate::visibility_system::compute_fov_symmetric;
+use crate::{DistanceAlg, LightSource, Map, Point, Position, RGB};
+use specs::prelude::*;
+
+/// How much of a tile's color shows in plain view with no light on it.
+pub const AMBIENT_LIGHT: f32 = 0.6;
+
+/// How much light reaches each tile of the current level. Only rebuilt when a
+/// light source, or a door it might shine through, changes.
+#[derive(Default)]
+pub struct LightMap {
+    pub light: Vec<RGB>,
+    sources: Vec<(i32, i32, i32)>,
+    open_doors: usize,
+}
+
+impl LightMap {
+    pub fn new() -> LightMap {
+        LightMap::default()
+    }
+
+    /// Brightness to multiply a visible tile's color by: ambient light, plus
+    /// whatever the light sources add.
+    pub fn brightness(&self, idx: usize) -> RGB {
+        match self.light.get(idx) {
+            Some(light) => RGB::from_f32(
+                AMBIENT_LIGHT + light.r,
+                AMBIENT_LIGHT + light.g,
+                AMBIENT_LIGHT + light.b,
+            ),
+            None => RGB::from_f32(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+pub struct LightingSystem {}
+
+impl<'a> System<'a> for LightingSystem {
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, LightMap>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, LightSource>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (map, mut light_map, positions, light_sources) = data;
+
+        let sources: Vec<(i32, i32, i32)> = (&positions, &light_sources)
+            .join()
+            .map(|(pos, light)| (pos.x, pos.y, light.range))
+            .collect();
+        let open_doors = map.open_doors.iter().filter(|open| **open).count();
+        if light_map.light.len() == map.tiles.len()
+            && light_map.sources == sources
+            && light_map.open_doors == open_doors
+        {
+            return;
+        }
+
+        let mut light = vec![RGB::from_f32(0.0, 0.0, 0.0); map.tiles.len()];
+        for (pos, source) in (&positions, &light_sources).join() {
+            let origin = Point::new(pos.x, pos.y);
+            for tile in compute_fov_symmetric(origin, source.range, &map) {
+                if tile.x < 0 || tile.x >= map.width || tile.y < 0 || tile.y >= map.height {
+                    continue;
+                }
+
+                // Fades out towards the edge of the light's reach
+                let distance = DistanceAlg::Pythagoras.distance2d(origin, tile);
+                let strength = 1.0 - distance / (source.range as f32 + 1.0);
+                let lit = &mut light[map.xy_idx(tile.x, tile.y)];
+                lit.r = f32::min(1.0, lit.r + source.color.r * strength);
+                lit.g = f32::min(1.0, lit.g + source.color.g * strength);
+                lit.b = f32::min(1.0, lit.b + source.color.b * strength);
+            }
+        }
+
+        light_map.light = light;
+        light_map.sources = sources;
+        light_map.open_doors = open_doors;
+    }
+}
@@ -1,32 +1,102 @@
 use bracket_lib::prelude::*;
 use specs::prelude::*;
+use specs::saveload::{SimpleMarker, SimpleMarkerAllocator};
 
-use crate::colors::{FLOOR_COLOR, TRANSPARENT_COLOR};
+use crate::boss_ai_system::BossAI;
+use crate::colors::{Theme, FLOOR_COLOR, TRANSPARENT_COLOR};
 use crate::components::{
-    BlocksTile, CombatStats, FieldOfView, Monster, Name, Player, Position, Renderable,
-    SufferDamage, WantsToMelee,
+    AiMode, AiState, Ammo, AreaOfEffect, BlocksTile, Boss, CanFlee, CombatStats, Confusion,
+    Consumable, Cooldowns, Corpse, CritBonus, Cursed, DamageType, DefenseBonus, Dialogue, DoorUser,
+    EffectKind, EntityMoved, EntryTrigger, EquipmentSlot, Equippable, Equipped, Experience,
+    Faction, FieldOfView, Gold, Hidden, HungerClock, HungerState, InInventory, InflictsDamage,
+    InflictsStatus, Initiative, Item, Key, KilledByPlayer, Knockback, LightSource, Mana,
+    MeleePowerBonus, Monster, MyTurn, Name, OtherLevelPosition, PackMember, ParticleLifetime,
+    Player, Position, Potion, Price, Projectile, ProvidesFood, ProvidesHealing, ProvidesIdentify,
+    ProvidesMapping, ProvidesSummon, ProvidesTeleport, Ranged, RangedAttacker, RangedWeapon,
+    Regeneration, Renderable, Resistances, SerializationHelper, SerializeMe, SingleActivation,
+    Size, Sneaking, Spell, Stackable, StatusEffect, SufferDamage, Temporary, Unidentified, Vendor,
+    Wading, Wallet, WantsToDropItem, WantsToMelee, WantsToPickupItem, WantsToRemoveItem,
+    WantsToShoot, WantsToThrowItem, WantsToUseItem, Weight,
 };
+use crate::cooldown_system::CooldownSystem;
 use crate::damage_system::DamageSystem;
-use crate::map::{Map, TileType, HEIGHT, WIDTH};
+use crate::difficulty::{Difficulty, DifficultyLevel};
+use crate::event_log_system::{EventLogSystem, EventQueue};
+use crate::gui::{
+    CharacterSheetResult, DialogueResult, DropStackResult, ExamineResult, GameOverResult,
+    ItemMenuResult, LogViewResult, MainMenuResult, MainMenuSelection, QuestLogResult, UiState,
+    VendorMode, VendorResult,
+};
+use crate::hazard_system::HazardSystem;
+use crate::hunger_system::HungerSystem;
+use crate::initiative_system::InitiativeSystem;
+use crate::inventory_system::{
+    EquipSystem, IdentifySystem, ItemCollectionSystem, ItemDropSystem, ItemRemoveSystem,
+    ItemThrowSystem, ItemUseSystem, KnownItems,
+};
+use crate::keybindings::Keybindings;
+use crate::lighting_system::{LightMap, LightingSystem};
+use crate::map::{Depth, LevelStore, Map, MapGenerator, TileType, TOWN_DEPTH};
 use crate::map_indexing_system::MapIndexingSystem;
 use crate::melee_combat_system::MeleeCombatSystem;
 use crate::monster_ai_system::MonsterAI;
-use crate::player::player_input;
+use crate::noise::Noise;
+use crate::particle_system::{ParticleBuilder, ParticleSpawnSystem};
+use crate::player::{player_input, Autopilot};
+use crate::quest_system::{QuestSystem, Quests};
+use crate::ranged_combat_system::RangedCombatSystem;
+use crate::regen_system::RegenSystem;
+use crate::replay::{InputLog, RecordingHeader, Replay};
+use crate::screen_effects::ScreenEffects;
+use crate::spawner::SpawnQueue;
+use crate::status_effect_system::StatusEffectSystem;
+use crate::temporary_system::TemporarySystem;
+use crate::trap_detection_system::TrapDetectionSystem;
+use crate::trigger_system::TriggerSystem;
 use crate::visibility_system::VisibilitySystem;
+use crate::wandering_spawn_system::{WanderingSpawnSystem, WanderingSpawns};
 
+mod boss_ai_system;
+mod camera;
 mod colors;
 mod components;
+mod cooldown_system;
 mod damage_system;
+mod difficulty;
+mod event_log_system;
 mod gamelog;
 mod gui;
+mod hazard_system;
+mod hunger_system;
+mod initiative_system;
+mod inventory_system;
+mod keybindings;
+mod lighting_system;
 mod map;
 mod map_indexing_system;
 mod melee_combat_system;
 mod monster_ai_system;
+mod noise;
+mod particle_system;
 mod player;
+mod quest_system;
+mod random_table;
+mod ranged_combat_system;
+mod raws;
 mod rect;
+mod regen_system;
+mod replay;
+mod saveload_system;
+mod screen_effects;
 mod spawner;
+mod spells;
+mod status_effect_system;
+mod temporary_system;
+mod trap_detection_system;
+mod trigger_system;
+mod vendor;
 mod visibility_system;
+mod wandering_spawn_system;
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum RunState {
@@ -34,37 +104,428 @@ pub enum RunState {
     PreRun,
     PlayerTurn,
     MonsterTurn,
+    ShowInventory,
+    ShowLog { offset: usize },
+    ShowCharacter,
+    ShowQuests,
+    ShowSpells,
+    ShowSpellTargeting { range: i32, spell: Entity },
+    ShowFireTargeting { range: i32 },
+    Examine { cursor: Point },
+    ShowVendor { vendor: Entity, mode: VendorMode },
+    ShowDialogue { npc: Entity, line: usize },
+    ShowTargeting { range: i32, item: Entity },
+    ShowThrowItem,
+    ShowDropItem,
+    ShowDropStack { item: Entity },
+    ShowRemoveItem,
+    ShowThrowTargeting { range: i32, item: Entity },
+    NextLevel,
+    PreviousLevel,
+    SaveGame,
+    MainMenu { selection: MainMenuSelection },
+    GameOver,
+}
+
+impl RunState {
+    /// Whether the game waits on the player's keys or mouse in this state.
+    fn reads_input(self) -> bool {
+        !matches!(
+            self,
+            RunState::PreRun
+                | RunState::PlayerTurn
+                | RunState::MonsterTurn
+                | RunState::NextLevel
+                | RunState::PreviousLevel
+                | RunState::SaveGame
+                | RunState::MainMenu { .. }
+                | RunState::GameOver
+        )
+    }
 }
 
 pub struct State {
     pub ecs: World,
+    generator: MapGenerator,
+    /// Seed every new game starts from, if one was asked for. Otherwise each
+    /// game gets a fresh random one.
+    seed: Option<u64>,
+    /// Picked on the title screen, for the next game started or continued.
+    difficulty: DifficultyLevel,
+    /// A recording standing in for the player, if one is being played back.
+    replay: Option<Replay>,
 }
 
 impl State {
+    /// Whether wading or a sneaking step this turn costs the player another
+    /// one. Either is used up in finding out.
+    fn player_turn_costs_extra(&mut self, player_entity: Entity) -> bool {
+        let waded = self
+            .ecs
+            .write_storage::<Wading>()
+            .remove(player_entity)
+            .is_some();
+        let sneaked = self
+            .ecs
+            .write_storage::<Sneaking>()
+            .get_mut(player_entity)
+            .is_some_and(|sneak| std::mem::take(&mut sneak.stepped));
+        waded || sneaked
+    }
+
     fn run_systems(&mut self) {
         let mut vis = VisibilitySystem {};
         vis.run_now(&self.ecs);
+        let mut initiative = InitiativeSystem {};
+        initiative.run_now(&self.ecs);
+        let mut bosses = BossAI {};
+        bosses.run_now(&self.ecs);
         let mut mob = MonsterAI {};
         mob.run_now(&self.ecs);
         let mut map_index = MapIndexingSystem {};
         map_index.run_now(&self.ecs);
+        let mut trap_detection = TrapDetectionSystem {};
+        trap_detection.run_now(&self.ecs);
+        let mut triggers = TriggerSystem {};
+        triggers.run_now(&self.ecs);
         let mut melee = MeleeCombatSystem {};
         melee.run_now(&self.ecs);
+        let mut ranged = RangedCombatSystem {};
+        ranged.run_now(&self.ecs);
+        let mut hunger = HungerSystem {};
+        hunger.run_now(&self.ecs);
+        let mut temporaries = TemporarySystem {};
+        temporaries.run_now(&self.ecs);
+        let mut regen = RegenSystem {};
+        regen.run_now(&self.ecs);
+        let mut cooldowns = CooldownSystem {};
+        cooldowns.run_now(&self.ecs);
+        let mut wanderers = WanderingSpawnSystem {};
+        wanderers.run_now(&self.ecs);
+        let mut effects = StatusEffectSystem {};
+        effects.run_now(&self.ecs);
+        let mut hazards = HazardSystem {};
+        hazards.run_now(&self.ecs);
         let mut damage_system = DamageSystem {};
         damage_system.run_now(&self.ecs);
+        let mut pickup = ItemCollectionSystem {};
+        pickup.run_now(&self.ecs);
+        let mut equip = EquipSystem {};
+        equip.run_now(&self.ecs);
+        let mut identify = IdentifySystem {};
+        identify.run_now(&self.ecs);
+        let mut items = ItemUseSystem {};
+        items.run_now(&self.ecs);
+        let mut throws = ItemThrowSystem {};
+        throws.run_now(&self.ecs);
+        let mut drops = ItemDropSystem {};
+        drops.run_now(&self.ecs);
+        let mut removals = ItemRemoveSystem {};
+        removals.run_now(&self.ecs);
+        let mut event_log = EventLogSystem {};
+        event_log.run_now(&self.ecs);
+        let mut lighting = LightingSystem {};
+        lighting.run_now(&self.ecs);
+        let mut particles = ParticleSpawnSystem {};
+        particles.run_now(&self.ecs);
         self.ecs.maintain();
+        spawner::spawn_queued(&mut self.ecs);
+    }
+
+    /// Builds a fresh level at the given depth, replacing the `Map` resource
+    /// outright, and moves the player to its starting position.
+    fn generate_world_map(&mut self, new_depth: i32) {
+        let mut builder = map::new_builder(self.generator, new_depth);
+        let mut map;
+        {
+            let mut rng = self.ecs.write_resource::<RandomNumberGenerator>();
+            map = builder.build(&mut rng);
+        }
+        let start = builder.starting_position();
+        // Every level below the town has a way back up where the player arrives
+        if new_depth > TOWN_DEPTH {
+            let start_idx = map.xy_idx(start.x, start.y);
+            map.tiles[start_idx] = TileType::UpStairs;
+        }
+        self.ecs.insert(map);
+        self.ecs.insert(Depth(new_depth));
+        self.ecs.insert(LightMap::new());
+
+        builder.spawn_entities(&mut self.ecs);
+
+        self.place_player(start);
+    }
+
+    /// Puts the player down at `start` on the current level.
+    fn place_player(&mut self, start: Position) {
+        {
+            let mut player_pos = self.ecs.write_resource::<Point>();
+            *player_pos = Point::new(start.x, start.y);
+        }
+
+        let player_entity = *self.ecs.fetch::<Entity>();
+        let mut positions = self.ecs.write_storage::<Position>();
+        positions
+            .insert(player_entity, start)
+            .expect("Unable to move player");
+
+        let mut fovs = self.ecs.write_storage::<FieldOfView>();
+        if let Some(fov) = fovs.get_mut(player_entity) {
+            fov.dirty = true;
+        }
+    }
+
+    /// Throws away the whole world, dead player included, and starts over
+    /// with a new player on a fresh first level. The game is only recorded
+    /// when `record` is set, so that the world made to sit behind the title
+    /// screen doesn't wipe out the last recording.
+    fn new_game(&mut self, record: bool) {
+        let seed = self
+            .seed
+            .unwrap_or_else(|| RandomNumberGenerator::new().next_u64());
+        self.ecs = new_world(seed);
+        self.ecs.insert(Difficulty::new(self.difficulty));
+        if record {
+            self.ecs.insert(InputLog::start(RecordingHeader {
+                seed,
+                generator: self.generator,
+                difficulty: self.difficulty,
+            }));
+        }
+
+        // Player, placed properly once the first level exists
+        let player_entity = spawner::player(&mut self.ecs, 0, 0);
+        self.ecs.insert(player_entity);
+
+        self.generate_world_map(TOWN_DEPTH);
+
+        self.ecs.insert(gamelog::GameLog::new(format!(
+            "Welcome to vortex! (seed {})",
+            seed
+        )));
+    }
+
+    /// Everything on the current level that doesn't travel with the player to
+    /// the next one. Whatever is put away on other levels stays where it is.
+    fn entities_to_remove_on_level_change(&mut self) -> Vec<Entity> {
+        let entities = self.ecs.entities();
+        let player_entity = self.ecs.fetch::<Entity>();
+        let backpack = self.ecs.read_storage::<InInventory>();
+        let equipped = self.ecs.read_storage::<Equipped>();
+        let spells = self.ecs.read_storage::<Spell>();
+        let elsewhere = self.ecs.read_storage::<OtherLevelPosition>();
+
+        entities
+            .join()
+            .filter(|entity| *entity != *player_entity)
+            .filter(|entity| !elsewhere.contains(*entity))
+            // Shop stock and the like belong to whoever carries it
+            .filter(|entity| {
+                let owner = backpack
+                    .get(*entity)
+                    .map(|pack| pack.owner)
+                    .or_else(|| equipped.get(*entity).map(|gear| gear.owner))
+                    .or_else(|| spells.get(*entity).map(|spell| spell.owner));
+                owner.is_none_or(|owner| !elsewhere.contains(owner))
+            })
+            .filter(|entity| {
+                backpack
+                    .get(*entity)
+                    .is_none_or(|pack| pack.owner != *player_entity)
+            })
+            .filter(|entity| {
+                equipped
+                    .get(*entity)
+                    .is_none_or(|gear| gear.owner != *player_entity)
+            })
+            .filter(|entity| {
+                spells
+                    .get(*entity)
+                    .is_none_or(|spell| spell.owner != *player_entity)
+            })
+            .collect()
+    }
+
+    /// Has the player drop `item` on their turn.
+    fn drop_item(&mut self, item: Entity) {
+        let player_entity = *self.ecs.fetch::<Entity>();
+        let mut intent = self.ecs.write_storage::<WantsToDropItem>();
+        intent
+            .insert(player_entity, WantsToDropItem { item })
+            .expect("Unable to insert intent");
+    }
+
+    /// Leaves the current level for the one at `new_depth`, putting it away
+    /// with everything on it. A level visited before comes back as it was
+    /// left; otherwise a new one is built.
+    fn change_level(&mut self, new_depth: i32) {
+        let old_depth = self.ecs.fetch::<Depth>().0;
+        let leaving = self.entities_to_remove_on_level_change();
+        self.put_level_away(old_depth, &leaving);
+
+        let stored = self.ecs.write_resource::<LevelStore>().take(new_depth);
+        match stored {
+            Some(map) => self.bring_level_back(new_depth, map, new_depth > old_depth),
+            None => self.generate_world_map(new_depth),
+        }
+    }
+
+    /// Stores the current map, and moves `entities` off it so nothing acts
+    /// on them until the player is back.
+    fn put_level_away(&mut self, depth: i32, entities: &[Entity]) {
+        let map = (*self.ecs.fetch::<Map>()).clone();
+        self.ecs.write_resource::<LevelStore>().store(depth, map);
+
+        let mut positions = self.ecs.write_storage::<Position>();
+        let mut elsewhere = self.ecs.write_storage::<OtherLevelPosition>();
+        for entity in entities {
+            if let Some(pos) = positions.remove(*entity) {
+                elsewhere
+                    .insert(
+                        *entity,
+                        OtherLevelPosition {
+                            x: pos.x,
+                            y: pos.y,
+                            depth,
+                        },
+                    )
+                    .expect("Unable to insert position");
+            }
+        }
+    }
+
+    /// Makes a stored level the current one again, with everything that was
+    /// left on it back in place. The player arrives on the stairs they'd
+    /// have come by: up ones when coming down, down ones when coming up.
+    fn bring_level_back(&mut self, depth: i32, map: Map, descending: bool) {
+        let arrival = if descending {
+            TileType::UpStairs
+        } else {
+            TileType::DownStairs
+        };
+        let start = match map.tiles.iter().position(|tile| *tile == arrival) {
+            Some(idx) => Position {
+                x: idx as i32 % map.width,
+                y: idx as i32 / map.width,
+            },
+            None => {
+                let (x, y) = map.first_open_tile();
+                Position { x, y }
+            }
+        };
+        self.ecs.insert(map);
+        self.ecs.insert(Depth(depth));
+        self.ecs.insert(LightMap::new());
+
+        {
+            let entities = self.ecs.entities();
+            let mut positions = self.ecs.write_storage::<Position>();
+            let mut elsewhere = self.ecs.write_storage::<OtherLevelPosition>();
+            let returning: Vec<(Entity, OtherLevelPosition)> = (&entities, &elsewhere)
+                .join()
+                .filter(|(_entity, pos)| pos.depth == depth)
+                .map(|(entity, pos)| (entity, pos.clone()))
+                .collect();
+            for (entity, pos) in returning {
+                elsewhere.remove(entity);
+                positions
+                    .insert(entity, Position { x: pos.x, y: pos.y })
+                    .expect("Unable to insert position");
+            }
+            for fov in (&mut self.ecs.write_storage::<FieldOfView>()).join() {
+                fov.dirty = true;
+            }
+        }
+
+        self.place_player(start);
+    }
+
+    fn goto_next_level(&mut self) {
+        let new_depth = self.ecs.fetch::<Depth>().0 + 1;
+        self.change_level(new_depth);
+
+        let mut log = self.ecs.write_resource::<gamelog::GameLog>();
+        log.add(
+            gamelog::LogCategory::General,
+            format!("You descend to level {}.", new_depth),
+        );
+    }
+
+    fn goto_previous_level(&mut self) {
+        let new_depth = self.ecs.fetch::<Depth>().0 - 1;
+        self.change_level(new_depth);
+
+        let mut log = self.ecs.write_resource::<gamelog::GameLog>();
+        log.add(
+            gamelog::LogCategory::General,
+            if new_depth == TOWN_DEPTH {
+                "You climb back up to the town.".to_string()
+            } else {
+                format!("You climb up to level {}.", new_depth)
+            },
+        );
     }
 }
 
 impl GameState for State {
     fn tick(&mut self, ctx: &mut BTerm) {
-        ctx.cls();
+        particle_system::cull_dead_particles(&mut self.ecs, ctx);
 
         let mut new_run_state;
         {
             new_run_state = *self.ecs.fetch::<RunState>();
         }
 
+        ctx.set_active_console(0);
+        ctx.cls();
+        ctx.set_active_console(1);
+        ctx.cls();
+        screen_effects::draw_screen_effects(&self.ecs, ctx);
+
+        if new_run_state.reads_input() {
+            let turn = self.ecs.fetch::<InputLog>().turn;
+            if let Some(replay) = &mut self.replay {
+                replay.feed(turn, ctx);
+                if replay.is_finished() {
+                    self.replay = None;
+                    let mut log = self.ecs.write_resource::<gamelog::GameLog>();
+                    log.add(gamelog::LogCategory::General, "The replay is over.");
+                }
+            }
+            self.ecs.write_resource::<InputLog>().record(ctx);
+        }
+
+        match new_run_state {
+            RunState::MainMenu { .. } | RunState::GameOver => {}
+            _ => {
+                ctx.set_active_console(0);
+                camera::render_camera(&self.ecs, ctx);
+                ctx.set_active_console(1);
+
+                let map = self.ecs.fetch::<Map>();
+                let theme = self.ecs.fetch::<Theme>();
+                let wizard_mode = self.ecs.fetch::<UiState>().wizard_mode;
+                let origin = camera::origin(&self.ecs);
+                for (pos, render) in renderables_in_draw_order(&self.ecs) {
+                    let idx = map.xy_idx(pos.x, pos.y);
+                    let (screen_x, screen_y) = (pos.x - origin.x, pos.y - origin.y);
+                    if camera::contents_drawn(&map, idx, wizard_mode)
+                        && camera::in_view(screen_x, screen_y)
+                    {
+                        // Things lying on the floor blend in with it
+                        let bg = if render.bg == FLOOR_COLOR {
+                            theme.floor
+                        } else {
+                            render.bg
+                        };
+                        ctx.set(screen_x, screen_y, render.fg, bg, render.glyph);
+                    }
+                }
+
+                gui::draw_ui(&self.ecs, ctx);
+            }
+        }
+
         match new_run_state {
             RunState::PreRun => {
                 self.run_systems();
@@ -75,12 +536,307 @@ impl GameState for State {
             }
             RunState::PlayerTurn => {
                 self.run_systems();
+                self.ecs.write_resource::<InputLog>().turn += 1;
                 new_run_state = RunState::MonsterTurn;
             }
             RunState::MonsterTurn => {
                 self.run_systems();
-                new_run_state = RunState::AwaitingInput;
+
+                // Faster monsters may have another action in them. After that,
+                // wading or a sneaking step costs the player a turn: it passes
+                // without them.
+                let player_entity = *self.ecs.fetch::<Entity>();
+                new_run_state = if initiative_system::anyone_ready(&self.ecs) {
+                    RunState::MonsterTurn
+                } else if self.player_turn_costs_extra(player_entity) {
+                    RunState::PlayerTurn
+                } else {
+                    RunState::AwaitingInput
+                };
+            }
+            RunState::ShowInventory => {
+                let (result, item) = gui::show_inventory(&self.ecs, ctx);
+                match result {
+                    ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    ItemMenuResult::NoResponse => {}
+                    ItemMenuResult::Selected => {
+                        let item = item.unwrap();
+                        let ranged = self.ecs.read_storage::<Ranged>();
+                        if let Some(ranged) = ranged.get(item) {
+                            new_run_state = RunState::ShowTargeting {
+                                range: ranged.range,
+                                item,
+                            };
+                        } else {
+                            let player_entity = *self.ecs.fetch::<Entity>();
+                            let mut intent = self.ecs.write_storage::<WantsToUseItem>();
+                            intent
+                                .insert(player_entity, WantsToUseItem { item, target: None })
+                                .expect("Unable to insert intent");
+                            new_run_state = RunState::PlayerTurn;
+                        }
+                    }
+                }
+            }
+            RunState::ShowLog { offset } => match gui::show_log(&self.ecs, ctx, offset) {
+                LogViewResult::Open { offset } => new_run_state = RunState::ShowLog { offset },
+                LogViewResult::Close => new_run_state = RunState::AwaitingInput,
+            },
+            RunState::ShowCharacter => {
+                if gui::show_character_sheet(&self.ecs, ctx) == CharacterSheetResult::Close {
+                    new_run_state = RunState::AwaitingInput;
+                }
+            }
+            RunState::ShowQuests => {
+                if gui::show_quests(&self.ecs, ctx) == QuestLogResult::Close {
+                    new_run_state = RunState::AwaitingInput;
+                }
+            }
+            RunState::Examine { cursor } => match gui::examine(&self.ecs, ctx, cursor) {
+                ExamineResult::Open { cursor } => new_run_state = RunState::Examine { cursor },
+                ExamineResult::Close => new_run_state = RunState::AwaitingInput,
+            },
+            RunState::ShowVendor { vendor, mode } => {
+                match gui::show_vendor(&self.ecs, ctx, vendor, mode) {
+                    VendorResult::NoResponse => {}
+                    VendorResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    VendorResult::ToggleMode => {
+                        let mode = match mode {
+                            VendorMode::Buy => VendorMode::Sell,
+                            VendorMode::Sell => VendorMode::Buy,
+                        };
+                        new_run_state = RunState::ShowVendor { vendor, mode };
+                    }
+                    VendorResult::Buy { item } => vendor::buy_item(&mut self.ecs, vendor, item),
+                    VendorResult::Sell { item } => vendor::sell_item(&mut self.ecs, vendor, item),
+                }
+            }
+            RunState::ShowDialogue { npc, line } => {
+                match gui::show_dialogue(&self.ecs, ctx, npc, line) {
+                    DialogueResult::Open => {}
+                    DialogueResult::Next { line } => {
+                        new_run_state = RunState::ShowDialogue { npc, line }
+                    }
+                    DialogueResult::Close => new_run_state = RunState::AwaitingInput,
+                }
+            }
+            RunState::ShowTargeting { range, item } => {
+                let (result, target) = gui::ranged_target(&self.ecs, ctx, range);
+                match result {
+                    ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    ItemMenuResult::NoResponse => {}
+                    ItemMenuResult::Selected => {
+                        let player_entity = *self.ecs.fetch::<Entity>();
+                        let mut intent = self.ecs.write_storage::<WantsToUseItem>();
+                        intent
+                            .insert(player_entity, WantsToUseItem { item, target })
+                            .expect("Unable to insert intent");
+                        new_run_state = RunState::PlayerTurn;
+                    }
+                }
+            }
+            RunState::ShowThrowItem => {
+                let (result, item) = gui::show_throw_menu(&self.ecs, ctx);
+                match result {
+                    ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    ItemMenuResult::NoResponse => {}
+                    ItemMenuResult::Selected => {
+                        let item = item.unwrap();
+                        let weight = self.ecs.read_storage::<Weight>().get(item).map(|w| w.value);
+                        new_run_state = RunState::ShowThrowTargeting {
+                            range: inventory_system::throw_range(weight),
+                            item,
+                        };
+                    }
+                }
+            }
+            RunState::ShowDropItem => {
+                let (result, item) = gui::show_drop_item_menu(&self.ecs, ctx);
+                match result {
+                    ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    ItemMenuResult::NoResponse => {}
+                    ItemMenuResult::Selected => {
+                        let item = item.unwrap();
+                        let stacked = self
+                            .ecs
+                            .read_storage::<Stackable>()
+                            .get(item)
+                            .is_some_and(|stack| stack.count > 1);
+                        new_run_state = if stacked {
+                            RunState::ShowDropStack { item }
+                        } else {
+                            self.drop_item(item);
+                            RunState::PlayerTurn
+                        };
+                    }
+                }
+            }
+            RunState::ShowDropStack { item } => {
+                match gui::drop_stack_prompt(&self.ecs, ctx, item) {
+                    DropStackResult::NoResponse => {}
+                    DropStackResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    DropStackResult::One => {
+                        let single = inventory_system::split_stack(&mut self.ecs, item);
+                        self.drop_item(single);
+                        new_run_state = RunState::PlayerTurn;
+                    }
+                    DropStackResult::All => {
+                        self.drop_item(item);
+                        new_run_state = RunState::PlayerTurn;
+                    }
+                }
+            }
+            RunState::ShowRemoveItem => {
+                let (result, item) = gui::show_remove_item_menu(&self.ecs, ctx);
+                match result {
+                    ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    ItemMenuResult::NoResponse => {}
+                    ItemMenuResult::Selected => {
+                        let player_entity = *self.ecs.fetch::<Entity>();
+                        let mut intent = self.ecs.write_storage::<WantsToRemoveItem>();
+                        intent
+                            .insert(
+                                player_entity,
+                                WantsToRemoveItem {
+                                    item: item.unwrap(),
+                                },
+                            )
+                            .expect("Unable to insert intent");
+                        new_run_state = RunState::PlayerTurn;
+                    }
+                }
+            }
+            RunState::ShowThrowTargeting { range, item } => {
+                let (result, target) = gui::ranged_target(&self.ecs, ctx, range);
+                match result {
+                    ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    ItemMenuResult::NoResponse => {}
+                    ItemMenuResult::Selected => {
+                        let item = inventory_system::split_stack(&mut self.ecs, item);
+                        let player_entity = *self.ecs.fetch::<Entity>();
+                        let mut intent = self.ecs.write_storage::<WantsToThrowItem>();
+                        intent
+                            .insert(
+                                player_entity,
+                                WantsToThrowItem {
+                                    item,
+                                    target: target.unwrap(),
+                                },
+                            )
+                            .expect("Unable to insert intent");
+                        new_run_state = RunState::PlayerTurn;
+                    }
+                }
+            }
+            RunState::ShowSpells => {
+                let (result, spell) = gui::show_spells(&self.ecs, ctx);
+                match result {
+                    ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    ItemMenuResult::NoResponse => {}
+                    ItemMenuResult::Selected => {
+                        let spell = spell.unwrap();
+                        let range = self
+                            .ecs
+                            .read_storage::<Ranged>()
+                            .get(spell)
+                            .map(|r| r.range);
+                        new_run_state = if !spells::can_cast(&self.ecs, spell) {
+                            RunState::AwaitingInput
+                        } else if let Some(range) = range {
+                            RunState::ShowSpellTargeting { range, spell }
+                        } else if spells::cast_spell(&mut self.ecs, spell, None) {
+                            RunState::PlayerTurn
+                        } else {
+                            RunState::AwaitingInput
+                        };
+                    }
+                }
             }
+            RunState::ShowSpellTargeting { range, spell } => {
+                let (result, target) = gui::ranged_target(&self.ecs, ctx, range);
+                match result {
+                    ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    ItemMenuResult::NoResponse => {}
+                    ItemMenuResult::Selected => {
+                        new_run_state = if spells::cast_spell(&mut self.ecs, spell, target) {
+                            RunState::PlayerTurn
+                        } else {
+                            RunState::AwaitingInput
+                        };
+                    }
+                }
+            }
+            RunState::ShowFireTargeting { range } => {
+                let (result, target) = gui::ranged_target(&self.ecs, ctx, range);
+                match result {
+                    ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    ItemMenuResult::NoResponse => {}
+                    ItemMenuResult::Selected => {
+                        new_run_state = match target {
+                            Some(target) if player::fire_at(&mut self.ecs, target) => {
+                                RunState::PlayerTurn
+                            }
+                            _ => RunState::AwaitingInput,
+                        };
+                    }
+                }
+            }
+            RunState::NextLevel => {
+                self.goto_next_level();
+                new_run_state = RunState::PreRun;
+            }
+            RunState::PreviousLevel => {
+                self.goto_previous_level();
+                new_run_state = RunState::PreRun;
+            }
+            RunState::SaveGame => {
+                saveload_system::save_game(&mut self.ecs);
+                new_run_state = RunState::MainMenu {
+                    selection: MainMenuSelection::Continue,
+                };
+            }
+            RunState::MainMenu { selection } => {
+                match gui::main_menu(&self.ecs, ctx, selection, self.difficulty) {
+                    MainMenuResult::NoSelection { selected } => {
+                        new_run_state = RunState::MainMenu {
+                            selection: selected,
+                        };
+                    }
+                    MainMenuResult::Previous { .. } => {
+                        self.difficulty = self.difficulty.previous();
+                        new_run_state = RunState::MainMenu { selection };
+                    }
+                    MainMenuResult::Selected { selected } => match selected {
+                        MainMenuSelection::NewGame => {
+                            self.new_game(true);
+                            new_run_state = RunState::PreRun;
+                        }
+                        MainMenuSelection::Difficulty => {
+                            self.difficulty = self.difficulty.next();
+                            new_run_state = RunState::MainMenu { selection };
+                        }
+                        MainMenuSelection::Continue => {
+                            if saveload_system::does_save_exist() {
+                                saveload_system::load_game(&mut self.ecs);
+                                self.ecs.insert(LightMap::new());
+                                self.difficulty = self.ecs.fetch::<Difficulty>().level;
+                                self.ecs.insert(InputLog::off());
+                                saveload_system::delete_save();
+                                new_run_state = RunState::PreRun;
+                            }
+                        }
+                        MainMenuSelection::Quit => ctx.quit(),
+                    },
+                }
+            }
+            RunState::GameOver => match gui::game_over(&self.ecs, ctx) {
+                GameOverResult::NoSelection => {}
+                GameOverResult::QuitToMenu => {
+                    new_run_state = RunState::MainMenu {
+                        selection: MainMenuSelection::NewGame,
+                    };
+                }
+            },
         }
 
         {
@@ -89,62 +845,184 @@ impl GameState for State {
         }
 
         DamageSystem::delete_the_dead(&mut self.ecs);
+        let mut quests = QuestSystem {};
+        quests.run_now(&self.ecs);
+        let mut event_log = EventLogSystem {};
+        event_log.run_now(&self.ecs);
 
-        ctx.set_active_console(0);
-        draw_map(&self.ecs, ctx);
+        // The dead can leave particles behind, show them right away
+        let mut particles = ParticleSpawnSystem {};
+        particles.run_now(&self.ecs);
+    }
+}
 
-        ctx.set_active_console(1);
+/// Everything there is to draw, bottom layer first, so whatever matters most
+/// on a shared tile is drawn last and ends up on top.
+fn renderables_in_draw_order(ecs: &World) -> Vec<(Point, Renderable)> {
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let particles = ecs.read_storage::<ParticleLifetime>();
+    let sizes = ecs.read_storage::<Size>();
+    let wizard_mode = ecs.fetch::<UiState>().wizard_mode;
 
-        let positions = self.ecs.read_storage::<Position>();
-        let renderables = self.ecs.read_storage::<Renderable>();
-        let map = self.ecs.fetch::<Map>();
+    let mut drawn: Vec<(Point, Renderable)> = (
+        &positions,
+        &renderables,
+        (&hidden).maybe(),
+        (&particles).maybe(),
+        (&sizes).maybe(),
+    )
+        .join()
+        .filter(|(_pos, _render, hidden, _particle, _size)| wizard_mode || hidden.is_none())
+        // Particles still waiting their turn aren't drawn yet
+        .filter(|(_pos, _render, _hidden, particle, _size)| {
+            particle.is_none_or(|particle| particle.delay_ms <= 0.0)
+        })
+        // Big things are drawn on every tile they cover
+        .flat_map(|(pos, render, _hidden, _particle, size)| {
+            map::footprint(pos.x, pos.y, size)
+                .into_iter()
+                .map(move |tile| (tile, render.clone()))
+        })
+        .collect();
+    drawn.sort_by_key(|(_pos, render)| -render.render_order);
+    drawn
+}
 
-        for (pos, render) in (&positions, &renderables).join() {
-            let idx = map.xy_idx(pos.x, pos.y);
-            if map.visible_tiles[idx] {
-                ctx.set(pos.x, pos.y, render.fg, FLOOR_COLOR, render.glyph);
-            }
-        }
+/// Creates an empty world with every component registered and the resources
+/// the systems expect already in place, with its random numbers drawn from
+/// `seed`.
+fn new_world(seed: u64) -> World {
+    let mut ecs = World::new();
 
-        gui::draw_ui(&self.ecs, ctx);
-    }
-}
+    ecs.register::<Position>();
+    ecs.register::<Renderable>();
+    ecs.register::<Player>();
+    ecs.register::<Monster>();
+    ecs.register::<Name>();
+    ecs.register::<FieldOfView>();
+    ecs.register::<BlocksTile>();
+    ecs.register::<CombatStats>();
+    ecs.register::<WantsToMelee>();
+    ecs.register::<SufferDamage>();
+    ecs.register::<KilledByPlayer>();
+    ecs.register::<Item>();
+    ecs.register::<InInventory>();
+    ecs.register::<WantsToPickupItem>();
+    ecs.register::<ProvidesHealing>();
+    ecs.register::<WantsToUseItem>();
+    ecs.register::<Consumable>();
+    ecs.register::<Equippable>();
+    ecs.register::<Equipped>();
+    ecs.register::<MeleePowerBonus>();
+    ecs.register::<DefenseBonus>();
+    ecs.register::<Ranged>();
+    ecs.register::<InflictsDamage>();
+    ecs.register::<AreaOfEffect>();
+    ecs.register::<Confusion>();
+    ecs.register::<ParticleLifetime>();
+    ecs.register::<Experience>();
+    ecs.register::<HungerClock>();
+    ecs.register::<ProvidesFood>();
+    ecs.register::<DoorUser>();
+    ecs.register::<Wading>();
+    ecs.register::<StatusEffect>();
+    ecs.register::<InflictsStatus>();
+    ecs.register::<CanFlee>();
+    ecs.register::<RangedAttacker>();
+    ecs.register::<WantsToShoot>();
+    ecs.register::<Hidden>();
+    ecs.register::<EntryTrigger>();
+    ecs.register::<SingleActivation>();
+    ecs.register::<EntityMoved>();
+    ecs.register::<Initiative>();
+    ecs.register::<MyTurn>();
+    ecs.register::<LightSource>();
+    ecs.register::<AiState>();
+    ecs.register::<Faction>();
+    ecs.register::<Gold>();
+    ecs.register::<Wallet>();
+    ecs.register::<Price>();
+    ecs.register::<Vendor>();
+    ecs.register::<Key>();
+    ecs.register::<ProvidesMapping>();
+    ecs.register::<ProvidesTeleport>();
+    ecs.register::<Boss>();
+    ecs.register::<ProvidesSummon>();
+    ecs.register::<Temporary>();
+    ecs.register::<Projectile>();
+    ecs.register::<Corpse>();
+    ecs.register::<WantsToThrowItem>();
+    ecs.register::<Weight>();
+    ecs.register::<WantsToDropItem>();
+    ecs.register::<WantsToRemoveItem>();
+    ecs.register::<Cursed>();
+    ecs.register::<Unidentified>();
+    ecs.register::<ProvidesIdentify>();
+    ecs.register::<CritBonus>();
+    ecs.register::<Knockback>();
+    ecs.register::<Resistances>();
+    ecs.register::<Regeneration>();
+    ecs.register::<Mana>();
+    ecs.register::<Spell>();
+    ecs.register::<Cooldowns>();
+    ecs.register::<RangedWeapon>();
+    ecs.register::<Ammo>();
+    ecs.register::<Stackable>();
+    ecs.register::<Sneaking>();
+    ecs.register::<Size>();
+    ecs.register::<OtherLevelPosition>();
+    ecs.register::<PackMember>();
+    ecs.register::<Potion>();
+    ecs.register::<Dialogue>();
+    ecs.register::<SimpleMarker<SerializeMe>>();
+    ecs.register::<SerializationHelper>();
 
-fn draw_map(ecs: &World, ctx: &mut BTerm) {
-    let mut fovs = ecs.write_storage::<FieldOfView>();
-    let mut players = ecs.write_storage::<Player>();
-    let map = ecs.fetch::<Map>();
+    ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+    ecs.insert(RandomNumberGenerator::seeded(seed));
+    ecs.insert(ParticleBuilder::new());
+    ecs.insert(SpawnQueue::new());
+    ecs.insert(ScreenEffects::new());
+    ecs.insert(LightMap::new());
+    ecs.insert(Noise::new());
+    ecs.insert(EventQueue::new());
+    ecs.insert(WanderingSpawns::new());
+    ecs.insert(LevelStore::new());
+    ecs.insert(KnownItems::new());
+    ecs.insert(Quests::new());
+    ecs.insert(Point::new(0, 0));
+    ecs.insert(RunState::PreRun);
+    ecs.insert(Autopilot::Off);
+    ecs.insert(Keybindings::load());
+    ecs.insert(Theme::load());
+    ecs.insert(UiState::new());
+    ecs.insert(InputLog::off());
 
-    for (_player, _fov) in (&mut players, &mut fovs).join() {
-        let mut x = 0;
-        let mut y = 0;
-        for (idx, tile) in map.tiles.iter().enumerate() {
-            if map.revealed_tiles[idx] {
-                let glyph;
-                let mut fg;
-                match tile {
-                    TileType::Floor => {
-                        glyph = to_cp437('█');
-                        fg = FLOOR_COLOR;
-                    }
-                    TileType::Wall => {
-                        glyph = to_cp437('█');
-                        fg = RGBA::from_u8(0, 20, 70, 255);
-                    }
-                }
-                if !map.visible_tiles[idx] {
-                    fg = fg.lerp(BLACK.into(), 0.5)
-                }
-                ctx.set(x, y, fg, RGB::from_u8(0, 0, 0), glyph);
-            }
+    ecs
+}
 
-            x += 1;
-            if x > 79 {
-                x = 0;
-                y += 1;
-            }
-        }
-    }
+/// The seed asked for with `--seed <n>` or the `VORTEX_SEED` environment
+/// variable, if any. The same seed always builds the same dungeon.
+fn requested_seed() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    let from_args = args
+        .iter()
+        .position(|arg| arg == "--seed")
+        .and_then(|i| args.get(i + 1))
+        .cloned();
+    from_args
+        .or_else(|| std::env::var("VORTEX_SEED").ok())
+        .and_then(|seed| seed.parse().ok())
+}
+
+/// The recording asked for with `--replay <file>`, if any.
+fn requested_replay() -> Option<Replay> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == "--replay")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|path| Replay::load(path))
 }
 
 fn main() -> BError {
@@ -155,40 +1033,34 @@ fn main() -> BError {
         .with_sparse_console_no_bg(80, 50, font)
         .with_title("vortex")
         .build()?;
-    let mut gs = State { ecs: World::new() };
-
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Monster>();
-    gs.ecs.register::<Name>();
-    gs.ecs.register::<FieldOfView>();
-    gs.ecs.register::<BlocksTile>();
-    gs.ecs.register::<CombatStats>();
-    gs.ecs.register::<WantsToMelee>();
-    gs.ecs.register::<SufferDamage>();
-
-    let map = Map::new_map_rooms_and_corridors();
-    let (player_x, player_y) = map.rooms[0].center();
-
-    gs.ecs.insert(RandomNumberGenerator::new());
-
-    // monsters
-    for room in map.rooms.iter().skip(1) {
-        let (x, y) = room.center();
-        spawner::random_monster(&mut gs.ecs, x, y);
+    let generator = if std::env::args().any(|arg| arg == "--caves") {
+        MapGenerator::CellularAutomata
+    } else if std::env::args().any(|arg| arg == "--bsp") {
+        MapGenerator::Bsp
+    } else if std::env::args().any(|arg| arg == "--drunkard") {
+        MapGenerator::DrunkardsWalk
+    } else {
+        MapGenerator::RoomsAndCorridors
+    };
+    let mut gs = State {
+        ecs: World::new(),
+        generator,
+        seed: requested_seed(),
+        difficulty: DifficultyLevel::Normal,
+        replay: requested_replay(),
+    };
+    // A replay starts straight away, the way the recorded game began
+    if let Some(header) = gs.replay.as_ref().map(|replay| replay.header) {
+        gs.seed = Some(header.seed);
+        gs.generator = header.generator;
+        gs.difficulty = header.difficulty;
+        gs.new_game(true);
+    } else {
+        gs.new_game(false);
+        gs.ecs.insert(RunState::MainMenu {
+            selection: MainMenuSelection::NewGame,
+        });
     }
 
-    // Player
-    let player_entity = spawner::player(&mut gs.ecs, player_x, player_y);
-
-    gs.ecs.insert(player_entity);
-    gs.ecs.insert(map);
-    gs.ecs.insert(Point::new(player_x, player_y));
-    gs.ecs.insert(RunState::PreRun);
-    gs.ecs.insert(gamelog::GameLog {
-        entries: vec!["Welcome to vortex!".to_string()],
-    });
-
     main_loop(context, gs)
 }
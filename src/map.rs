@@ -1,25 +1,142 @@
 use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use bracket_lib::prelude::*;
-use specs::Entity;
+use serde::{Deserialize, Serialize};
+use specs::{Entity, World};
 
+use crate::components::{Position, Size};
 use crate::rect::Rect;
 
+pub use bsp::BspBuilder;
+pub use cellular_automata::CellularAutomataBuilder;
+pub use drunkards_walk::DrunkardsWalkBuilder;
+pub use rooms_and_corridors::{RoomsAndCorridorsBuilder, BOSS_DEPTH};
+pub use town::TownBuilder;
+
+mod bsp;
+mod cellular_automata;
+mod drunkards_walk;
+mod prefab;
+mod rooms_and_corridors;
+mod town;
+
 pub const WIDTH: i32 = 80;
 pub const HEIGHT: i32 = 43;
 
-#[derive(PartialEq, Copy, Clone)]
+/// Whether a diagonal step may slip between two walls that meet at a corner.
+pub const ALLOW_CORNER_SQUEEZE: bool = false;
+
+/// Generates a level. Builders keep whatever they need from `build` so they
+/// can answer where the player starts and what else lives on the level.
+pub trait MapBuilder {
+    fn build(&mut self, rng: &mut RandomNumberGenerator) -> Map;
+    fn starting_position(&self) -> Position;
+
+    /// Places monsters and items for the level that was just built.
+    fn spawn_entities(&self, _ecs: &mut World) {}
+}
+
+/// Selects which `MapBuilder` `new_builder` hands out.
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum MapGenerator {
+    RoomsAndCorridors,
+    CellularAutomata,
+    Bsp,
+    DrunkardsWalk,
+}
+
+/// A builder for a level at `depth`. The town is always the same, whatever
+/// the generator.
+pub fn new_builder(generator: MapGenerator, depth: i32) -> Box<dyn MapBuilder> {
+    if depth == TOWN_DEPTH {
+        return Box::new(TownBuilder::new());
+    }
+    match generator {
+        MapGenerator::RoomsAndCorridors => Box::new(RoomsAndCorridorsBuilder::new(depth)),
+        MapGenerator::CellularAutomata => Box::new(CellularAutomataBuilder::new()),
+        MapGenerator::Bsp => Box::new(BspBuilder::new()),
+        MapGenerator::DrunkardsWalk => Box::new(DrunkardsWalkBuilder::new()),
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum TileType {
     Wall,
     Floor,
+    DownStairs,
+    UpStairs,
+    Door,
+    ShallowWater,
+    DeepWater,
+    Lava,
+    /// A door that passes for wall until someone searches next to it.
+    SecretDoor,
 }
 
+/// How deep the player has descended. The town is at `TOWN_DEPTH` and the
+/// first level of the dungeon is depth 1.
+pub struct Depth(pub i32);
+
+pub const TOWN_DEPTH: i32 = 0;
+
+/// Extra pathing cost of going through a closed door rather than around it.
+const CLOSED_DOOR_COST: f32 = 2.0;
+
+/// Extra pathing cost of wading through shallow water, which holds up
+/// whoever steps in.
+const SHALLOW_WATER_COST: f32 = 2.0;
+
+/// Extra pathing cost of stepping onto lava: far past anything a Dijkstra map
+/// spreads to, so paths only cross it when there's no other way at all.
+const LAVA_COST: f32 = 1000.0;
+
+/// Every level the player has left, keyed by depth, ready for them to come
+/// back to. Only the map is kept here; whatever was on the level stays in the
+/// world with an `OtherLevelPosition` instead of a `Position`, so it sits
+/// still until the player returns.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct LevelStore {
+    maps: HashMap<i32, Map>,
+}
+
+impl LevelStore {
+    pub fn new() -> LevelStore {
+        LevelStore::default()
+    }
+
+    pub fn store(&mut self, depth: i32, map: Map) {
+        self.maps.insert(depth, map);
+    }
+
+    /// Hands back the level stored at `depth`, if there is one.
+    pub fn take(&mut self, depth: i32) -> Option<Map> {
+        self.maps.remove(&depth)
+    }
+}
+
+/// Every tile taken up by something at `(x, y)`: just that one, unless it
+/// has a `Size`.
+pub fn footprint(x: i32, y: i32, size: Option<&Size>) -> Vec<Point> {
+    let (width, height) = size.map_or((1, 1), |size| (size.width, size.height));
+    (0..height)
+        .flat_map(|dy| (0..width).map(move |dx| Point::new(x + dx, y + dy)))
+        .collect()
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Map {
     pub tiles: Vec<TileType>,
+    #[serde(skip)]
     pub tile_content: Vec<Vec<Entity>>,
     pub revealed_tiles: Vec<bool>,
     pub visible_tiles: Vec<bool>,
     pub blocked: Vec<bool>,
+    pub open_doors: Vec<bool>,
+    /// Doors that only open for a `Key` with the same id.
+    pub locked_doors: HashMap<usize, u32>,
+    /// Tiles someone has been hurt on. Purely cosmetic.
+    pub bloodstains: HashSet<usize>,
     pub rooms: Vec<Rect>,
     pub width: i32,
     pub height: i32,
@@ -27,44 +144,11 @@ pub struct Map {
 
 impl BaseMap for Map {
     fn is_opaque(&self, idx: usize) -> bool {
-        self.tiles[idx] == TileType::Wall
+        self.is_wall(idx) || self.is_closed_door(idx)
     }
 
     fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
-        let mut exits = SmallVec::new();
-        let x = idx as i32 % self.width;
-        let y = idx as i32 / self.width;
-        let w = self.width as usize;
-
-        // Cardinal directions
-        if self.is_exit_valid(x - 1, y) {
-            exits.push((idx - 1, 1.0));
-        }
-        if self.is_exit_valid(x + 1, y) {
-            exits.push((idx + 1, 1.0));
-        }
-        if self.is_exit_valid(x, y - 1) {
-            exits.push((idx - w, 1.0));
-        }
-        if self.is_exit_valid(x, y + 1) {
-            exits.push((idx + w, 1.0));
-        }
-
-        // Diagonals
-        if self.is_exit_valid(x - 1, y - 1) {
-            exits.push(((idx - w) - 1, 1.45));
-        }
-        if self.is_exit_valid(x + 1, y - 1) {
-            exits.push(((idx - w) + 1, 1.45));
-        }
-        if self.is_exit_valid(x - 1, y + 1) {
-            exits.push(((idx + w) - 1, 1.45));
-        }
-        if self.is_exit_valid(x + 1, y + 1) {
-            exits.push(((idx + w) + 1, 1.45));
-        }
-
-        exits
+        self.exits(idx, false)
     }
 
     fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
@@ -83,68 +167,194 @@ impl Algorithm2D for Map {
 }
 
 impl Map {
-    pub fn new_map_rooms_and_corridors() -> Map {
-        let mut map = Map {
+    fn new_filled() -> Map {
+        Map {
             tiles: vec![TileType::Wall; (WIDTH * HEIGHT) as usize],
             tile_content: vec![vec![]; (WIDTH * HEIGHT) as usize],
             revealed_tiles: vec![false; (WIDTH * HEIGHT) as usize],
             visible_tiles: vec![false; (WIDTH * HEIGHT) as usize],
             blocked: vec![false; (WIDTH * HEIGHT) as usize],
+            open_doors: vec![false; (WIDTH * HEIGHT) as usize],
+            locked_doors: HashMap::new(),
+            bloodstains: HashSet::new(),
             rooms: vec![],
             width: WIDTH,
             height: HEIGHT,
-        };
+        }
+    }
 
-        const MAX_ROOMS: i32 = 30;
-        const MIN_SIZE: i32 = 6;
-        const MAX_SIZE: i32 = 10;
-
-        let mut rng = RandomNumberGenerator::new();
-
-        for _ in 0..MAX_ROOMS {
-            let w = rng.range(MIN_SIZE, MAX_SIZE);
-            let h = rng.range(MIN_SIZE, MAX_SIZE);
-            let x = rng.roll_dice(1, WIDTH - w - 1) - 1;
-            let y = rng.roll_dice(1, HEIGHT - h - 1) - 1;
-            let new_room = Rect::new(x, y, w, h);
-            let mut ok = true;
-            for other_room in map.rooms.iter() {
-                if new_room.intersects(other_room) {
-                    ok = false;
-                    break;
-                }
+    /// Returns the floor tile closest to the middle of the map. Levels with
+    /// rooms start in the center of the first room instead.
+    pub fn first_open_tile(&self) -> (i32, i32) {
+        if let Some(room) = self.rooms.first() {
+            return room.center();
+        }
+
+        let (center_x, center_y) = (self.width / 2, self.height / 2);
+        let mut best = (center_x, center_y);
+        let mut best_distance = i32::MAX;
+        for (idx, tile) in self.tiles.iter().enumerate() {
+            if *tile != TileType::Floor {
+                continue;
+            }
+            let x = idx as i32 % self.width;
+            let y = idx as i32 / self.width;
+            let distance = (x - center_x).abs() + (y - center_y).abs();
+            if distance < best_distance {
+                best_distance = distance;
+                best = (x, y);
             }
+        }
 
-            if ok {
-                map.apply_room_to_map(&new_room);
-
-                if !map.rooms.is_empty() {
-                    let (new_x, new_y) = new_room.center();
-                    let (prev_x, prev_y) = map.rooms[map.rooms.len() - 1].center();
-                    if rng.range(0, 2) == 1 {
-                        map.apply_horizontal_tunnel(prev_x, new_x, prev_y);
-                        map.apply_vertical_tunnel(prev_y, new_y, new_x);
-                    } else {
-                        map.apply_vertical_tunnel(prev_y, new_y, prev_x);
-                        map.apply_horizontal_tunnel(prev_x, new_x, new_y);
-                    }
+        best
+    }
+
+    /// Turns every floor tile that can't be reached from the given start into
+    /// wall.
+    fn remove_unreachable_areas(&mut self, start_x: i32, start_y: i32) {
+        let distances = self.walking_distances(start_x, start_y);
+        for (idx, tile) in self.tiles.iter_mut().enumerate() {
+            if distances[idx].is_none() {
+                *tile = TileType::Wall;
+            }
+        }
+    }
+
+    /// Returns the reachable tile furthest (in steps) from the given start.
+    fn furthest_reachable_tile(&self, start_x: i32, start_y: i32) -> usize {
+        let distances = self.walking_distances(start_x, start_y);
+        let mut furthest = self.xy_idx(start_x, start_y);
+        let mut furthest_distance = 0;
+        for (idx, distance) in distances.iter().enumerate() {
+            if let Some(distance) = *distance {
+                if distance > furthest_distance {
+                    furthest_distance = distance;
+                    furthest = idx;
                 }
+            }
+        }
 
-                map.rooms.push(new_room);
+        furthest
+    }
+
+    /// Counts the orthogonal steps from the start to every tile that isn't a
+    /// wall, without going through locked doors. Unreachable tiles are `None`.
+    pub fn walking_distances(&self, start_x: i32, start_y: i32) -> Vec<Option<i32>> {
+        let mut distances = vec![None; self.tiles.len()];
+        let mut open = VecDeque::new();
+
+        let start_idx = self.xy_idx(start_x, start_y);
+        distances[start_idx] = Some(0);
+        open.push_back((start_x, start_y, 0));
+
+        while let Some((x, y, distance)) = open.pop_front() {
+            for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || nx >= self.width || ny < 0 || ny >= self.height {
+                    continue;
+                }
+                let idx = self.xy_idx(nx, ny);
+                if distances[idx].is_none() && !self.is_wall(idx) && !self.is_locked(idx) {
+                    distances[idx] = Some(distance + 1);
+                    open.push_back((nx, ny, distance + 1));
+                }
             }
         }
 
-        map
+        distances
     }
 
     pub fn populate_blocked(&mut self) {
-        for (i, tile) in self.tiles.iter().enumerate() {
-            self.blocked[i] = *tile == TileType::Wall;
+        for i in 0..self.tiles.len() {
+            self.blocked[i] = self.terrain_blocks(i);
+        }
+    }
+
+    /// Whether the tile itself is in the way, whoever is or isn't on it.
+    fn terrain_blocks(&self, idx: usize) -> bool {
+        self.is_wall(idx) || self.tiles[idx] == TileType::DeepWater || self.is_closed_door(idx)
+    }
+
+    /// Whether stepping by `(dx, dy)` from `(x, y)` means slipping between two
+    /// walls that only touch at the corner, and that isn't allowed.
+    pub fn squeezes_past_corner(&self, x: i32, y: i32, dx: i32, dy: i32) -> bool {
+        if ALLOW_CORNER_SQUEEZE || dx == 0 || dy == 0 {
+            return false;
         }
+        let (side_x, side_y) = (x + dx, y + dy);
+        if side_x < 0 || side_x >= self.width || side_y < 0 || side_y >= self.height {
+            return false;
+        }
+
+        self.is_wall(self.xy_idx(side_x, y)) && self.is_wall(self.xy_idx(x, side_y))
+    }
+
+    /// Whether `idx` is solid rock as far as anyone can tell: a wall, or a
+    /// secret door nobody has found yet.
+    pub fn is_wall(&self, idx: usize) -> bool {
+        matches!(self.tiles[idx], TileType::Wall | TileType::SecretDoor)
+    }
+
+    /// Turns a secret door into an ordinary closed one.
+    pub fn reveal_secret_door(&mut self, idx: usize) {
+        self.tiles[idx] = TileType::Door;
+        self.blocked[idx] = true;
+    }
+
+    pub fn is_closed_door(&self, idx: usize) -> bool {
+        self.tiles[idx] == TileType::Door && !self.open_doors[idx]
+    }
+
+    pub fn is_locked(&self, idx: usize) -> bool {
+        self.locked_doors.contains_key(&idx)
+    }
+
+    /// Whether something could be put down at `(x, y)`: plain floor with
+    /// nothing in the way.
+    pub fn is_open_tile(&self, x: i32, y: i32) -> bool {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return false;
+        }
+        let idx = self.xy_idx(x, y);
+        self.tiles[idx] == TileType::Floor && !self.blocked[idx]
+    }
+
+    /// Whether something of `size` could stand with its top left corner at
+    /// `(x, y)`. Tiles in `own` are taken by whatever is moving, so they
+    /// don't count as in the way.
+    pub fn footprint_fits(&self, x: i32, y: i32, size: &Size, own: &[Point]) -> bool {
+        footprint(x, y, Some(size)).iter().all(|tile| {
+            tile.x >= 0
+                && tile.x < self.width
+                && tile.y >= 0
+                && tile.y < self.height
+                && (own.contains(tile) || !self.blocked[self.xy_idx(tile.x, tile.y)])
+        })
+    }
+
+    /// An open tile right next to `(x, y)`, if there is one.
+    pub fn open_neighbor(&self, x: i32, y: i32) -> Option<Point> {
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|&delta| delta != (0, 0))
+            .map(|(dx, dy)| Point::new(x + dx, y + dy))
+            .find(|p| self.is_open_tile(p.x, p.y))
+    }
+
+    /// Opens the door at `idx`, letting things through and sight past it.
+    pub fn open_door(&mut self, idx: usize) {
+        self.open_doors[idx] = true;
+        self.blocked[idx] = false;
+    }
+
+    /// Shuts the door at `idx` again.
+    pub fn close_door(&mut self, idx: usize) {
+        self.open_doors[idx] = false;
+        self.blocked[idx] = true;
     }
 
     pub fn xy_idx(&self, x: i32, y: i32) -> usize {
-        (y * WIDTH) as usize + x as usize
+        (y * self.width) as usize + x as usize
     }
 
     pub fn clear_content_index(&mut self) {
@@ -165,7 +375,7 @@ impl Map {
     fn apply_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32) {
         for x in min(x1, x2)..=max(x1, x2) {
             let idx = self.xy_idx(x, y);
-            if idx > 0 && idx < (WIDTH * HEIGHT) as usize {
+            if idx > 0 && idx < self.tiles.len() {
                 self.tiles[idx] = TileType::Floor;
             }
         }
@@ -174,19 +384,90 @@ impl Map {
     fn apply_vertical_tunnel(&mut self, y1: i32, y2: i32, x: i32) {
         for y in min(y1, y2)..=max(y1, y2) {
             let idx = self.xy_idx(x, y);
-            if idx > 0 && idx < (WIDTH * HEIGHT) as usize {
+            if idx > 0 && idx < self.tiles.len() {
                 self.tiles[idx] = TileType::Floor;
             }
         }
     }
 
-    fn is_exit_valid(&self, x: i32, y: i32) -> bool {
+    /// The tiles a step from `idx` leads to, and what each step costs. With
+    /// `through_crowds`, tiles that are only blocked by whoever stands on them
+    /// count as open too.
+    pub fn exits(&self, idx: usize, through_crowds: bool) -> SmallVec<[(usize, f32); 10]> {
+        let mut exits = SmallVec::new();
+        let x = idx as i32 % self.width;
+        let y = idx as i32 / self.width;
+        let w = self.width as usize;
+
+        // Cardinal directions
+        if self.is_exit_valid(x - 1, y, through_crowds) {
+            exits.push((idx - 1, self.exit_cost(idx - 1, 1.0)));
+        }
+        if self.is_exit_valid(x + 1, y, through_crowds) {
+            exits.push((idx + 1, self.exit_cost(idx + 1, 1.0)));
+        }
+        if self.is_exit_valid(x, y - 1, through_crowds) {
+            exits.push((idx - w, self.exit_cost(idx - w, 1.0)));
+        }
+        if self.is_exit_valid(x, y + 1, through_crowds) {
+            exits.push((idx + w, self.exit_cost(idx + w, 1.0)));
+        }
+
+        // Diagonals
+        if self.is_exit_valid(x - 1, y - 1, through_crowds)
+            && !self.squeezes_past_corner(x, y, -1, -1)
+        {
+            exits.push(((idx - w) - 1, self.exit_cost((idx - w) - 1, 1.45)));
+        }
+        if self.is_exit_valid(x + 1, y - 1, through_crowds)
+            && !self.squeezes_past_corner(x, y, 1, -1)
+        {
+            exits.push(((idx - w) + 1, self.exit_cost((idx - w) + 1, 1.45)));
+        }
+        if self.is_exit_valid(x - 1, y + 1, through_crowds)
+            && !self.squeezes_past_corner(x, y, -1, 1)
+        {
+            exits.push(((idx + w) - 1, self.exit_cost((idx + w) - 1, 1.45)));
+        }
+        if self.is_exit_valid(x + 1, y + 1, through_crowds)
+            && !self.squeezes_past_corner(x, y, 1, 1)
+        {
+            exits.push(((idx + w) + 1, self.exit_cost((idx + w) + 1, 1.45)));
+        }
+
+        exits
+    }
+
+    fn is_exit_valid(&self, x: i32, y: i32, through_crowds: bool) -> bool {
         if x < 1 || x > self.width - 1 || y < 1 || y > self.height - 1 {
             return false;
         }
 
         let idx = self.xy_idx(x, y);
 
-        !self.blocked[idx]
+        let blocked = if through_crowds {
+            self.terrain_blocks(idx)
+        } else {
+            self.blocked[idx]
+        };
+        // Closed doors are in the way, but only until someone opens them.
+        // Locked ones stay in the way.
+        !blocked || (self.is_closed_door(idx) && !self.is_locked(idx))
+    }
+
+    /// What stepping onto `idx` costs, `base` being the length of the step.
+    /// A closed door takes a turn to open and water slows things down, so
+    /// both cost more, and lava costs so much it's as good as a wall. Nothing
+    /// costs less than its length, so the straight-line distance A* goes by
+    /// never overestimates.
+    fn exit_cost(&self, idx: usize, base: f32) -> f32 {
+        if self.is_closed_door(idx) {
+            return base + CLOSED_DOOR_COST;
+        }
+        match self.tiles[idx] {
+            TileType::ShallowWater => base + SHALLOW_WATER_COST,
+            TileType::Lava => base + LAVA_COST,
+            _ => base,
+        }
     }
 }
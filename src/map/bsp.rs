@@ -0,0 +1,159 @@
+use std::ops::Range;
+
+use bracket_lib::prelude::RandomNumberGenerator;
+use specs::World;
+
+use crate::components::Position;
+use crate::map::rooms_and_corridors::{add_doors, add_pools, place_stairs};
+use crate::map::{Depth, Map, MapBuilder};
+use crate::rect::Rect;
+use crate::spawner;
+
+/// Smallest a partition may get along either side, walls included.
+const MIN_LEAF: i32 = 8;
+/// Partitions no bigger than this along both sides may stop splitting early.
+const MAX_LEAF: i32 = 16;
+const MIN_ROOM: i32 = 4;
+
+/// Binary space partitioning: the map is cut in two again and again until
+/// the pieces are small, and each piece gets one room. Every room stays
+/// inside its own piece, so rooms never overlap, and the two halves of every
+/// cut are joined by a corridor, so the whole level is connected.
+pub struct BspBuilder {
+    map: Map,
+}
+
+impl BspBuilder {
+    pub fn new() -> BspBuilder {
+        BspBuilder {
+            map: Map::new_filled(),
+        }
+    }
+}
+
+impl MapBuilder for BspBuilder {
+    fn build(&mut self, rng: &mut RandomNumberGenerator) -> Map {
+        let mut map = Map::new_filled();
+
+        let whole = Rect {
+            x1: 0,
+            y1: 0,
+            x2: map.width - 1,
+            y2: map.height - 1,
+        };
+        partition(&mut map, &whole, rng);
+
+        add_doors(&mut map, rng);
+        add_pools(&mut map, rng);
+        place_stairs(&mut map);
+
+        self.map = map.clone();
+        map
+    }
+
+    fn starting_position(&self) -> Position {
+        let (x, y) = self.map.rooms[0].center();
+        Position { x, y }
+    }
+
+    fn spawn_entities(&self, ecs: &mut World) {
+        let depth = ecs.fetch::<Depth>().0;
+        for room in self.map.rooms.iter().skip(1) {
+            spawner::spawn_room(ecs, room, depth);
+        }
+    }
+}
+
+/// Splits `leaf` and recurses into both halves, or places a room in it once
+/// it is small enough. The edges of `leaf` are walls shared with its
+/// neighbours. Returns the range of `map.rooms` that ended up inside it.
+fn partition(map: &mut Map, leaf: &Rect, rng: &mut RandomNumberGenerator) -> Range<usize> {
+    let width = leaf.x2 - leaf.x1;
+    let height = leaf.y2 - leaf.y1;
+    let can_split_x = width >= MIN_LEAF * 2;
+    let can_split_y = height >= MIN_LEAF * 2;
+    let small = width <= MAX_LEAF && height <= MAX_LEAF;
+
+    if !(can_split_x || can_split_y) || (small && rng.roll_dice(1, 3) == 1) {
+        let w = rng.range(MIN_ROOM, width);
+        let h = rng.range(MIN_ROOM, height);
+        let x = rng.range(leaf.x1, leaf.x2 - w);
+        let y = rng.range(leaf.y1, leaf.y2 - h);
+        let room = Rect::new(x, y, w, h);
+        map.apply_room_to_map(&room);
+        map.rooms.push(room);
+        return map.rooms.len() - 1..map.rooms.len();
+    }
+
+    // Cut across the longer side, unless only the other one is big enough
+    let split_x = if can_split_x && can_split_y {
+        width >= height
+    } else {
+        can_split_x
+    };
+    let (first, second) = if split_x {
+        let cut = rng.range(leaf.x1 + MIN_LEAF, leaf.x2 - MIN_LEAF + 1);
+        (
+            Rect {
+                x2: cut,
+                ..leaf.clone()
+            },
+            Rect {
+                x1: cut,
+                ..leaf.clone()
+            },
+        )
+    } else {
+        let cut = rng.range(leaf.y1 + MIN_LEAF, leaf.y2 - MIN_LEAF + 1);
+        (
+            Rect {
+                y2: cut,
+                ..leaf.clone()
+            },
+            Rect {
+                y1: cut,
+                ..leaf.clone()
+            },
+        )
+    };
+
+    let first_rooms = partition(map, &first, rng);
+    let second_rooms = partition(map, &second, rng);
+    connect_closest(map, first_rooms.clone(), second_rooms.clone(), rng);
+
+    first_rooms.start..second_rooms.end
+}
+
+/// Joins the two rooms, one from each side of a cut, that are closest
+/// together with a dog-leg corridor.
+fn connect_closest(
+    map: &mut Map,
+    first: Range<usize>,
+    second: Range<usize>,
+    rng: &mut RandomNumberGenerator,
+) {
+    let mut closest = None;
+    for a in first {
+        for b in second.clone() {
+            let (ax, ay) = map.rooms[a].center();
+            let (bx, by) = map.rooms[b].center();
+            let distance = (ax - bx).pow(2) + (ay - by).pow(2);
+            if closest.is_none_or(|(_, _, best)| distance < best) {
+                closest = Some((a, b, distance));
+            }
+        }
+    }
+
+    let Some((a, b, _)) = closest else {
+        return;
+    };
+    let (ax, ay) = map.rooms[a].center();
+    let (bx, by) = map.rooms[b].center();
+    if rng.range(0, 2) == 1 {
+        map.apply_horizontal_tunnel(ax, bx, ay);
+        map.apply_vertical_tunnel(ay, by, bx);
+    } else {
+        map.apply_vertical_tunnel(ay, by, ax);
+        map.apply_horizontal_tunnel(ax, bx, by);
+    }
+}
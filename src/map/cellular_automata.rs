@@ -0,0 +1,97 @@
+use bracket_lib::prelude::RandomNumberGenerator;
+use specs::World;
+
+use crate::components::Position;
+use crate::map::{Map, MapBuilder, TileType};
+use crate::spawner;
+
+const SMOOTHING_PASSES: i32 = 15;
+
+/// Builds an open cave level by randomly seeding walls and smoothing them
+/// with a cellular automaton. Floor that can't be reached from the starting
+/// position is filled back in, so the cave is always connected.
+pub struct CellularAutomataBuilder {
+    map: Map,
+    start: (i32, i32),
+}
+
+impl CellularAutomataBuilder {
+    pub fn new() -> CellularAutomataBuilder {
+        CellularAutomataBuilder {
+            map: Map::new_filled(),
+            start: (0, 0),
+        }
+    }
+}
+
+impl MapBuilder for CellularAutomataBuilder {
+    fn build(&mut self, rng: &mut RandomNumberGenerator) -> Map {
+        let mut map = Map::new_filled();
+
+        // Roughly 55% floor, with a solid border
+        for y in 1..map.height - 1 {
+            for x in 1..map.width - 1 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = if rng.roll_dice(1, 100) > 55 {
+                    TileType::Floor
+                } else {
+                    TileType::Wall
+                };
+            }
+        }
+
+        for _ in 0..SMOOTHING_PASSES {
+            let mut new_tiles = map.tiles.clone();
+
+            for y in 1..map.height - 1 {
+                for x in 1..map.width - 1 {
+                    let neighbors = count_wall_neighbors(&map, x, y);
+                    let idx = map.xy_idx(x, y);
+                    new_tiles[idx] = if neighbors > 4 || neighbors == 0 {
+                        TileType::Wall
+                    } else {
+                        TileType::Floor
+                    };
+                }
+            }
+
+            map.tiles = new_tiles;
+        }
+
+        self.start = map.first_open_tile();
+        map.remove_unreachable_areas(self.start.0, self.start.1);
+
+        let stairs_idx = map.furthest_reachable_tile(self.start.0, self.start.1);
+        map.tiles[stairs_idx] = TileType::DownStairs;
+
+        self.map = map.clone();
+        map
+    }
+
+    fn starting_position(&self) -> Position {
+        Position {
+            x: self.start.0,
+            y: self.start.1,
+        }
+    }
+
+    fn spawn_entities(&self, ecs: &mut World) {
+        spawner::scatter_monsters(ecs, &self.map, self.start.0, self.start.1);
+    }
+}
+
+fn count_wall_neighbors(map: &Map, x: i32, y: i32) -> i32 {
+    let mut neighbors = 0;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            if map.tiles[map.xy_idx(x + dx, y + dy)] == TileType::Wall {
+                neighbors += 1;
+            }
+        }
+    }
+
+    neighbors
+}
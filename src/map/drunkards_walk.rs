@@ -0,0 +1,93 @@
+use bracket_lib::prelude::RandomNumberGenerator;
+use specs::World;
+
+use crate::components::Position;
+use crate::map::{Map, MapBuilder, TileType};
+use crate::spawner;
+
+const DEFAULT_OPEN_PERCENT: i32 = 40;
+const DEFAULT_MAX_STEPS: i32 = 400;
+
+/// Carves winding caves by letting a digger stumble about at random. The
+/// first walk starts in the middle of the map and each later one from some
+/// floor that's already been dug, so the cave grows as one piece until
+/// enough of the map is open.
+pub struct DrunkardsWalkBuilder {
+    map: Map,
+    start: (i32, i32),
+    open_percent: i32,
+    max_steps: i32,
+}
+
+impl DrunkardsWalkBuilder {
+    pub fn new() -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::with_settings(DEFAULT_OPEN_PERCENT, DEFAULT_MAX_STEPS)
+    }
+
+    /// `open_percent` is how much of the map should end up floor, and
+    /// `max_steps` how far a single walk may wander before it gives up.
+    pub fn with_settings(open_percent: i32, max_steps: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder {
+            map: Map::new_filled(),
+            start: (0, 0),
+            open_percent,
+            max_steps,
+        }
+    }
+}
+
+impl MapBuilder for DrunkardsWalkBuilder {
+    fn build(&mut self, rng: &mut RandomNumberGenerator) -> Map {
+        let mut map = Map::new_filled();
+
+        self.start = (map.width / 2, map.height / 2);
+        let start_idx = map.xy_idx(self.start.0, self.start.1);
+        map.tiles[start_idx] = TileType::Floor;
+
+        let wanted = map.tiles.len() as i32 * self.open_percent / 100;
+        let mut floor = vec![start_idx];
+        while (floor.len() as i32) < wanted {
+            let idx = floor[rng.range(0, floor.len() as i32) as usize];
+            let (mut x, mut y) = (idx as i32 % map.width, idx as i32 / map.width);
+
+            for _ in 0..self.max_steps {
+                let idx = map.xy_idx(x, y);
+                if map.tiles[idx] == TileType::Wall {
+                    map.tiles[idx] = TileType::Floor;
+                    floor.push(idx);
+                    if floor.len() as i32 >= wanted {
+                        break;
+                    }
+                }
+
+                // Stagger about, but never onto the border
+                match rng.roll_dice(1, 4) {
+                    1 if x > 1 => x -= 1,
+                    2 if x < map.width - 2 => x += 1,
+                    3 if y > 1 => y -= 1,
+                    4 if y < map.height - 2 => y += 1,
+                    _ => {}
+                }
+            }
+        }
+
+        map.remove_unreachable_areas(self.start.0, self.start.1);
+
+        let stairs_idx = map.furthest_reachable_tile(self.start.0, self.start.1);
+        map.tiles[stairs_idx] = TileType::DownStairs;
+
+        self.map = map.clone();
+        map
+    }
+
+    fn starting_position(&self) -> Position {
+        Position {
+            x: self.start.0,
+            y: self.start.1,
+        }
+    }
+
+    fn spawn_entities(&self, ecs: &mut World) {
+        spawner::scatter_monsters(ecs, &self.map, self.start.0, self.start.1);
+    }
+}
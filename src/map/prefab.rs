@@ -0,0 +1,164 @@
+use crate::map::{Map, TileType};
+
+/// A hand-made piece of level, drawn as text:
+///
+/// - `#` wall, `.` floor, `+` door, `~` shallow water, `>` stairs down
+/// - `B` orc warlord, `o` orc, `g` goblin, `S` shopkeeper, `E` elder
+/// - `!` health potion, `?` fireball scroll, `/` dagger, `$` gold and `^` bear
+///   trap
+/// - monsters and items stand on floor
+/// - a space leaves whatever was there alone
+///
+/// Rows are separated by newlines; leading and trailing blank lines are
+/// ignored.
+pub struct Prefab {
+    pub template: &'static str,
+}
+
+/// Treasure behind a door, with a couple of guards and a trap for whoever
+/// barges in.
+pub const TREASURE_VAULT: Prefab = Prefab {
+    template: "
+#########
+#.o...o.#
+#..$.$..#
+#..!?!..#
+#...g...#
+#...^...#
+####+####
+",
+};
+
+/// Where the way down is kept, by an orc warlord and two of its guards.
+pub const BOSS_LAIR: Prefab = Prefab {
+    template: "
+###########
+#.........#
+#..o...o..#
+#....B....#
+#.........#
+#....>....#
+#####+#####
+",
+};
+
+/// The town at the top of the dungeon: a few houses, the shop and the way
+/// down, inside a wall.
+pub const TOWN: Prefab = Prefab {
+    template: "
+#########################################
+#.......................................#
+#..#######.............~~~.....#######..#
+#..#.....#............~~~~~....#.....#..#
+#..#..S..#.............~~~.....#.....#..#
+#..#.....#.....................#.....#..#
+#..###+###.....................###+###..#
+#.......................................#
+#.......................................#
+#...................>...................#
+#.......................................#
+#.......................................#
+#..###+###.....................###+###..#
+#..#.....#.....................#.....#..#
+#..#..E..#.....................#.....#..#
+#..#.....#.....................#.....#..#
+#..#######.....................#######..#
+#.......................................#
+#########################################
+",
+};
+
+/// Something a prefab wants spawned once the level has entities.
+pub struct PrefabSpawn {
+    pub x: i32,
+    pub y: i32,
+    pub name: &'static str,
+}
+
+impl Prefab {
+    fn rows(&self) -> Vec<&'static str> {
+        self.template.trim_matches('\n').lines().collect::<Vec<_>>()
+    }
+
+    pub fn width(&self) -> i32 {
+        self.rows()
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0) as i32
+    }
+
+    pub fn height(&self) -> i32 {
+        self.rows().len() as i32
+    }
+
+    /// Grid positions, relative to the top left, of every door in the prefab.
+    pub fn doors(&self) -> Vec<(i32, i32)> {
+        let mut doors = Vec::new();
+        for (y, row) in self.rows().iter().enumerate() {
+            for (x, ch) in row.chars().enumerate() {
+                if ch == '+' {
+                    doors.push((x as i32, y as i32));
+                }
+            }
+        }
+        doors
+    }
+}
+
+/// Copies the prefab into the map with its top left corner at `(x, y)` and
+/// returns what it wants spawned. Nothing is changed if the prefab doesn't
+/// fit inside the map, border included, or if it would cover the stairs.
+pub fn stamp(map: &mut Map, prefab: &Prefab, x: i32, y: i32) -> Option<Vec<PrefabSpawn>> {
+    if x < 1 || y < 1 || x + prefab.width() > map.width - 1 || y + prefab.height() > map.height - 1
+    {
+        return None;
+    }
+
+    let mut cells = Vec::new();
+    for (dy, row) in prefab.rows().iter().enumerate() {
+        for (dx, ch) in row.chars().enumerate() {
+            if ch == ' ' {
+                continue;
+            }
+            let (tile_x, tile_y) = (x + dx as i32, y + dy as i32);
+            if map.tiles[map.xy_idx(tile_x, tile_y)] == TileType::DownStairs {
+                return None;
+            }
+            cells.push((tile_x, tile_y, ch));
+        }
+    }
+
+    let mut spawns = Vec::new();
+    for (tile_x, tile_y, ch) in cells {
+        let idx = map.xy_idx(tile_x, tile_y);
+        map.tiles[idx] = match ch {
+            '#' => TileType::Wall,
+            '+' => TileType::Door,
+            '~' => TileType::ShallowWater,
+            '>' => TileType::DownStairs,
+            _ => TileType::Floor,
+        };
+
+        let name = match ch {
+            'B' => "Orc Warlord",
+            'o' => "Orc",
+            'g' => "Goblin",
+            'S' => "Shopkeeper",
+            'E' => "Elder",
+            '!' => "Health Potion",
+            '?' => "Fireball Scroll",
+            '/' => "Dagger",
+            '$' => "Gold",
+            '^' => "Bear Trap",
+            _ => continue,
+        };
+        spawns.push(PrefabSpawn {
+            x: tile_x,
+            y: tile_y,
+            name,
+        });
+    }
+
+    Some(spawns)
+}
@@ -0,0 +1,339 @@
+use bracket_lib::prelude::{DistanceAlg, Point, RandomNumberGenerator};
+use specs::World;
+
+use crate::components::Position;
+use crate::map::prefab::{self, Prefab, PrefabSpawn, BOSS_LAIR, TREASURE_VAULT};
+use crate::map::{Depth, Map, MapBuilder, TileType};
+use crate::rect::Rect;
+use crate::spawner;
+
+/// The classic generator: random non-overlapping rooms joined by dog-leg
+/// corridors, some of them behind doors, and the odd room flooded. The player
+/// starts in the first room and every other room gets a roll on the spawn
+/// table. The stairs down go in the room furthest from the start. Now and
+/// then a vault is dug into the rock between the rooms, locked, with the key
+/// left in one of the rooms, or sometimes behind a secret door instead. At
+/// `BOSS_DEPTH` the vault is a boss lair, and the stairs are in there with
+/// the boss.
+pub struct RoomsAndCorridorsBuilder {
+    map: Map,
+    depth: i32,
+    vault_spawns: Vec<PrefabSpawn>,
+    key_position: Option<(i32, i32)>,
+}
+
+impl RoomsAndCorridorsBuilder {
+    pub fn new(depth: i32) -> RoomsAndCorridorsBuilder {
+        RoomsAndCorridorsBuilder {
+            map: Map::new_filled(),
+            depth,
+            vault_spawns: Vec::new(),
+            key_position: None,
+        }
+    }
+}
+
+impl MapBuilder for RoomsAndCorridorsBuilder {
+    fn build(&mut self, rng: &mut RandomNumberGenerator) -> Map {
+        let mut map = Map::new_filled();
+        add_rooms(&mut map, rng);
+
+        let mut secret_vault = false;
+        self.vault_spawns = if self.depth == BOSS_DEPTH {
+            // The boss has to live somewhere, so lay the rooms out afresh
+            // until its lair fits
+            let mut spawns = add_vault(&mut map, &BOSS_LAIR, false, rng);
+            for _ in 0..BOSS_LAYOUT_TRIES {
+                if !spawns.is_empty() {
+                    break;
+                }
+                map = Map::new_filled();
+                add_rooms(&mut map, rng);
+                spawns = add_vault(&mut map, &BOSS_LAIR, false, rng);
+            }
+            spawns
+        } else if rng.roll_dice(1, 2) == 1 {
+            secret_vault = rng.roll_dice(1, SECRET_VAULT_ODDS) == 1;
+            add_vault(&mut map, &TREASURE_VAULT, secret_vault, rng)
+        } else {
+            Vec::new()
+        };
+
+        add_doors(&mut map, rng);
+        add_pools(&mut map, rng);
+        // Unless the boss lair already has them, the stairs go in a room
+        if !map.tiles.contains(&TileType::DownStairs) {
+            place_stairs(&mut map);
+        }
+
+        // The vault is a dead end, so every room can be reached without it.
+        // A secret one has no lock to need a key for.
+        self.key_position = if self.vault_spawns.is_empty() || secret_vault {
+            None
+        } else {
+            random_room_floor(&map, rng)
+        };
+
+        self.map = map.clone();
+        map
+    }
+
+    fn starting_position(&self) -> Position {
+        let (x, y) = self.map.rooms[0].center();
+        Position { x, y }
+    }
+
+    fn spawn_entities(&self, ecs: &mut World) {
+        let depth = ecs.fetch::<Depth>().0;
+        for room in self.map.rooms.iter().skip(1) {
+            spawner::spawn_room(ecs, room, depth);
+        }
+        for spawn in self.vault_spawns.iter() {
+            spawner::spawn_entity(ecs, spawn.name, spawn.x, spawn.y);
+        }
+        if let Some((x, y)) = self.key_position {
+            spawner::key(ecs, x, y, VAULT_KEY);
+        }
+    }
+}
+
+/// Random non-overlapping rooms, each joined to the one before by a dog-leg
+/// corridor.
+fn add_rooms(map: &mut Map, rng: &mut RandomNumberGenerator) {
+    const MAX_ROOMS: i32 = 30;
+    const MIN_SIZE: i32 = 6;
+    const MAX_SIZE: i32 = 10;
+
+    for _ in 0..MAX_ROOMS {
+        let w = rng.range(MIN_SIZE, MAX_SIZE);
+        let h = rng.range(MIN_SIZE, MAX_SIZE);
+        let x = rng.roll_dice(1, map.width - w - 1) - 1;
+        let y = rng.roll_dice(1, map.height - h - 1) - 1;
+        let new_room = Rect::new(x, y, w, h);
+        let mut ok = true;
+        for other_room in map.rooms.iter() {
+            if new_room.intersects(other_room) {
+                ok = false;
+                break;
+            }
+        }
+
+        if ok {
+            map.apply_room_to_map(&new_room);
+
+            if !map.rooms.is_empty() {
+                let (new_x, new_y) = new_room.center();
+                let (prev_x, prev_y) = map.rooms[map.rooms.len() - 1].center();
+                if rng.range(0, 2) == 1 {
+                    map.apply_horizontal_tunnel(prev_x, new_x, prev_y);
+                    map.apply_vertical_tunnel(prev_y, new_y, new_x);
+                } else {
+                    map.apply_vertical_tunnel(prev_y, new_y, prev_x);
+                    map.apply_horizontal_tunnel(prev_x, new_x, new_y);
+                }
+            }
+
+            map.rooms.push(new_room);
+        }
+    }
+}
+
+/// The level whose stairs down are guarded by a boss.
+pub const BOSS_DEPTH: i32 = 5;
+/// How many times a boss level may be laid out again to make room for the
+/// lair.
+const BOSS_LAYOUT_TRIES: i32 = 10;
+
+/// Lock id of the vault door.
+const VAULT_KEY: u32 = 1;
+
+/// One in this many treasure vaults is hidden behind a secret door rather
+/// than locked.
+const SECRET_VAULT_ODDS: i32 = 3;
+
+/// Looks for solid rock to stamp the prefab into, locks its door (or makes
+/// it a secret one) and digs a corridor from there to the nearest room out
+/// that way. The corridor only ever heads away from the prefab, so it can't
+/// cut through its walls. Returns what the prefab wants spawned, or nothing
+/// if no spot was found.
+fn add_vault(
+    map: &mut Map,
+    vault: &Prefab,
+    secret: bool,
+    rng: &mut RandomNumberGenerator,
+) -> Vec<PrefabSpawn> {
+    const ATTEMPTS: i32 = 50;
+
+    let (width, height) = (vault.width(), vault.height());
+    let Some(&(door_x, door_y)) = vault.doors().first() else {
+        return Vec::new();
+    };
+    let (dx, dy) = if door_y == 0 {
+        (0, -1)
+    } else if door_y == height - 1 {
+        (0, 1)
+    } else if door_x == 0 {
+        (-1, 0)
+    } else {
+        (1, 0)
+    };
+
+    for _ in 0..ATTEMPTS {
+        let x = rng.range(1, map.width - width);
+        let y = rng.range(1, map.height - height);
+        if !is_solid_rock(map, x - 1, y - 1, x + width, y + height) {
+            continue;
+        }
+
+        let (entrance_x, entrance_y) = (x + door_x + dx, y + door_y + dy);
+        let nearest_room = map
+            .rooms
+            .iter()
+            .map(|room| room.center())
+            .filter(|&(room_x, room_y)| {
+                (room_x - entrance_x) * dx + (room_y - entrance_y) * dy >= 0
+            })
+            .min_by_key(|&(room_x, room_y)| {
+                (room_x - entrance_x).pow(2) + (room_y - entrance_y).pow(2)
+            });
+        let Some((room_x, room_y)) = nearest_room else {
+            continue;
+        };
+
+        let Some(spawns) = prefab::stamp(map, vault, x, y) else {
+            continue;
+        };
+        let door_idx = map.xy_idx(x + door_x, y + door_y);
+        if secret {
+            map.tiles[door_idx] = TileType::SecretDoor;
+        } else {
+            map.locked_doors.insert(door_idx, VAULT_KEY);
+        }
+        if dy != 0 {
+            map.apply_horizontal_tunnel(entrance_x, room_x, entrance_y);
+            map.apply_vertical_tunnel(entrance_y, room_y, room_x);
+        } else {
+            map.apply_vertical_tunnel(entrance_y, room_y, entrance_x);
+            map.apply_horizontal_tunnel(entrance_x, room_x, room_y);
+        }
+        return spawns;
+    }
+
+    Vec::new()
+}
+
+/// A random floor tile inside one of the rooms, if one turns up.
+fn random_room_floor(map: &Map, rng: &mut RandomNumberGenerator) -> Option<(i32, i32)> {
+    const ATTEMPTS: i32 = 20;
+
+    for _ in 0..ATTEMPTS {
+        let room = &map.rooms[rng.range(0, map.rooms.len() as i32) as usize];
+        let x = rng.range(room.x1 + 1, room.x2 + 1);
+        let y = rng.range(room.y1 + 1, room.y2 + 1);
+        if map.tiles[map.xy_idx(x, y)] == TileType::Floor {
+            return Some((x, y));
+        }
+    }
+
+    None
+}
+
+/// Whether everything from `(x1, y1)` to `(x2, y2)`, inclusive, is wall.
+fn is_solid_rock(map: &Map, x1: i32, y1: i32, x2: i32, y2: i32) -> bool {
+    (y1..=y2).all(|y| (x1..=x2).all(|x| map.tiles[map.xy_idx(x, y)] == TileType::Wall))
+}
+
+/// Puts the way down in whichever room is furthest from the first one.
+pub(super) fn place_stairs(map: &mut Map) {
+    let (start_x, start_y) = map.rooms[0].center();
+    let stairs_room = map
+        .rooms
+        .iter()
+        .max_by_key(|room| {
+            let (x, y) = room.center();
+            (x - start_x).pow(2) + (y - start_y).pow(2)
+        })
+        .unwrap();
+    let (stairs_x, stairs_y) = stairs_room.center();
+    let stairs_idx = map.xy_idx(stairs_x, stairs_y);
+    map.tiles[stairs_idx] = TileType::DownStairs;
+}
+
+/// Puts a door in about half the gaps corridors left in the room walls. Only
+/// single-tile gaps count; a corridor that runs along a wall opens the whole
+/// side up and doesn't get one.
+pub(super) fn add_doors(map: &mut Map, rng: &mut RandomNumberGenerator) {
+    let mut doorways = Vec::new();
+    for room in map.rooms.iter() {
+        for x in room.x1 + 1..=room.x2 {
+            for y in [room.y1, room.y2 + 1] {
+                if is_doorway(map, x, y, (1, 0)) {
+                    doorways.push(map.xy_idx(x, y));
+                }
+            }
+        }
+        for y in room.y1 + 1..=room.y2 {
+            for x in [room.x1, room.x2 + 1] {
+                if is_doorway(map, x, y, (0, 1)) {
+                    doorways.push(map.xy_idx(x, y));
+                }
+            }
+        }
+    }
+
+    for idx in doorways {
+        if rng.range(0, 2) == 1 {
+            map.tiles[idx] = TileType::Door;
+        }
+    }
+}
+
+/// A floor tile in a wall running along `(dx, dy)`.
+fn is_doorway(map: &Map, x: i32, y: i32, (dx, dy): (i32, i32)) -> bool {
+    if x - dx < 0 || x + dx >= map.width || y - dy < 0 || y + dy >= map.height {
+        return false;
+    }
+
+    map.tiles[map.xy_idx(x, y)] == TileType::Floor
+        && map.tiles[map.xy_idx(x - dx, y - dy)] == TileType::Wall
+        && map.tiles[map.xy_idx(x + dx, y + dy)] == TileType::Wall
+}
+
+/// Floods the odd room with a pool of water, or now and then lava. Pools keep
+/// clear of the room's edge, so there's always a dry way around, and of its
+/// center, where monsters and the stairs go. The first room is left alone.
+pub(super) fn add_pools(map: &mut Map, rng: &mut RandomNumberGenerator) {
+    let rooms = map.rooms.clone();
+    for room in rooms.iter().skip(1) {
+        if rng.roll_dice(1, 4) != 1 {
+            continue;
+        }
+
+        let lava = rng.roll_dice(1, 3) == 1;
+        let pool_center = Point::new(
+            rng.range(room.x1 + 2, room.x2),
+            rng.range(room.y1 + 2, room.y2),
+        );
+        let (center_x, center_y) = room.center();
+
+        for y in room.y1 + 2..room.y2 {
+            for x in room.x1 + 2..room.x2 {
+                let idx = map.xy_idx(x, y);
+                if (x, y) == (center_x, center_y) || map.tiles[idx] != TileType::Floor {
+                    continue;
+                }
+
+                let distance = DistanceAlg::Pythagoras.distance2d(Point::new(x, y), pool_center);
+                if distance <= 1.0 {
+                    map.tiles[idx] = if lava {
+                        TileType::Lava
+                    } else {
+                        TileType::DeepWater
+                    };
+                } else if distance <= 2.0 && !lava {
+                    map.tiles[idx] = TileType::ShallowWater;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use bracket_lib::prelude::RandomNumberGenerator;
+use specs::World;
+
+use crate::components::Position;
+use crate::map::prefab::{self, PrefabSpawn, TOWN};
+use crate::map::{Map, MapBuilder};
+use crate::spawner;
+
+/// Where the player turns up in the town, relative to its top left corner.
+const TOWN_START: (i32, i32) = (20, 15);
+
+/// The safe town above the dungeon, the same every time: no monsters, just
+/// the shopkeeper, the elder and the stairs down. It's built once and kept,
+/// so it's found as it was left.
+pub struct TownBuilder {
+    start: (i32, i32),
+    spawns: Vec<PrefabSpawn>,
+}
+
+impl TownBuilder {
+    pub fn new() -> TownBuilder {
+        TownBuilder {
+            start: (0, 0),
+            spawns: Vec::new(),
+        }
+    }
+}
+
+impl MapBuilder for TownBuilder {
+    fn build(&mut self, _rng: &mut RandomNumberGenerator) -> Map {
+        let mut map = Map::new_filled();
+
+        let x = (map.width - TOWN.width()) / 2;
+        let y = (map.height - TOWN.height()) / 2;
+        self.spawns = prefab::stamp(&mut map, &TOWN, x, y).unwrap_or_default();
+        self.start = (x + TOWN_START.0, y + TOWN_START.1);
+
+        map
+    }
+
+    fn starting_position(&self) -> Position {
+        Position {
+            x: self.start.0,
+            y: self.start.1,
+        }
+    }
+
+    fn spawn_entities(&self, ecs: &mut World) {
+        for spawn in self.spawns.iter() {
+            spawner::spawn_entity(ecs, spawn.name, spawn.x, spawn.y);
+        }
+    }
+}
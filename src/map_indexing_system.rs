@@ -1,4 +1,5 @@
-use crate::{BlocksTile, Map, Position};
+use crate::map::footprint;
+use crate::{BlocksTile, Map, Position, Size};
 use specs::prelude::*;
 
 pub struct MapIndexingSystem {}
@@ -8,24 +9,31 @@ impl<'a> System<'a> for MapIndexingSystem {
         WriteExpect<'a, Map>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, BlocksTile>,
+        ReadStorage<'a, Size>,
         Entities<'a>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, position, blockers, entities) = data;
+        let (mut map, position, blockers, sizes, entities) = data;
 
         map.populate_blocked();
         map.clear_content_index();
         for (entity, position) in (&entities, &position).join() {
-            let idx = map.xy_idx(position.x, position.y);
+            // Big things are found on, and block, every tile they cover
+            for tile in footprint(position.x, position.y, sizes.get(entity)) {
+                if tile.x < 0 || tile.x >= map.width || tile.y < 0 || tile.y >= map.height {
+                    continue;
+                }
+                let idx = map.xy_idx(tile.x, tile.y);
 
-            // If they block, update the blocking list
-            let _p = blockers.get(entity);
-            if let Some(_p) = _p {
-                map.blocked[idx] = true;
-            }
+                // If they block, update the blocking list
+                let _p = blockers.get(entity);
+                if let Some(_p) = _p {
+                    map.blocked[idx] = true;
+                }
 
-            map.tile_content[idx].push(entity);
+                map.tile_content[idx].push(entity);
+            }
         }
     }
 }
@@ -1,23 +1,103 @@
-use super::{CombatStats, Name, SufferDamage, WantsToMelee};
-use crate::gamelog::GameLog;
+use super::{
+    AiMode, AiState, CombatStats, CritBonus, DamageType, DefenseBonus, EntityMoved, Equipped,
+    FieldOfView, InflictsStatus, Knockback, Map, MeleePowerBonus, Name, Player, Position,
+    Resistances, Size, Sneaking, StatusEffect, SufferDamage, WantsToMelee,
+};
+use crate::damage_system::after_resistance;
+use crate::event_log_system::{EventQueue, GameEvent};
+use crate::noise::{Noise, MELEE_NOISE};
+use crate::particle_system::ParticleBuilder;
+use bracket_lib::prelude::{
+    to_cp437, Algorithm2D, BaseMap, Point, RandomNumberGenerator, BLACK, RED, RGB, RGBA, YELLOW,
+};
 use specs::prelude::*;
 
+/// Percent chance of any blow landing as a critical hit, before gear
+const BASE_CRIT_CHANCE: i32 = 5;
+
+/// How many times harder a backstab hits than an ordinary blow
+const BACKSTAB_MULTIPLIER: i32 = 3;
+
+/// Percent chance to hit an evenly matched target, how much each point of
+/// attack over the target's dodge adds to it, and the bounds it stays within
+const BASE_HIT_CHANCE: i32 = 75;
+const HIT_CHANCE_PER_POINT: i32 = 5;
+const MIN_HIT_CHANCE: i32 = 25;
+const MAX_HIT_CHANCE: i32 = 95;
+
+/// How likely, in percent, a blow with `attack` is to land on someone with
+/// `dodge`.
+fn hit_chance(attack: i32, dodge: i32) -> i32 {
+    (BASE_HIT_CHANCE + HIT_CHANCE_PER_POINT * (attack - dodge))
+        .clamp(MIN_HIT_CHANCE, MAX_HIT_CHANCE)
+}
+
+/// What being knocked into a wall costs
+const KNOCKBACK_COLLISION_DAMAGE: i32 = 2;
+
 pub struct MeleeCombatSystem {}
 
 impl<'a> System<'a> for MeleeCombatSystem {
     type SystemData = (
         Entities<'a>,
-        WriteExpect<'a, GameLog>,
         WriteStorage<'a, WantsToMelee>,
         ReadStorage<'a, Name>,
         ReadStorage<'a, CombatStats>,
         WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, MeleePowerBonus>,
+        ReadStorage<'a, DefenseBonus>,
+        WriteStorage<'a, Position>,
+        WriteExpect<'a, ParticleBuilder>,
+        ReadStorage<'a, InflictsStatus>,
+        WriteStorage<'a, StatusEffect>,
+        ReadStorage<'a, Player>,
+        WriteExpect<'a, Map>,
+        WriteExpect<'a, Noise>,
+        ReadStorage<'a, CritBonus>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        ReadStorage<'a, Knockback>,
+        WriteStorage<'a, FieldOfView>,
+        WriteStorage<'a, EntityMoved>,
+        WriteExpect<'a, Point>,
+        WriteStorage<'a, Sneaking>,
+        ReadStorage<'a, AiState>,
+        WriteExpect<'a, EventQueue>,
+        ReadStorage<'a, Size>,
+        ReadStorage<'a, Resistances>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, mut log, mut wants_melee, names, combat_stats, mut inflict_damage) = data;
+        let (
+            entities,
+            mut wants_melee,
+            names,
+            combat_stats,
+            mut inflict_damage,
+            equipped,
+            melee_power_bonuses,
+            defense_bonuses,
+            mut positions,
+            mut particle_builder,
+            inflicts_status,
+            mut effects,
+            players,
+            mut map,
+            mut noise,
+            crit_bonuses,
+            mut rng,
+            knockbacks,
+            mut fovs,
+            mut entity_moved,
+            mut player_pos,
+            mut sneaking,
+            ai_states,
+            mut events,
+            sizes,
+            resistances,
+        ) = data;
 
-        for (_entity, wants_melee, name, stats) in
+        for (entity, wants_melee, name, stats) in
             (&entities, &wants_melee, &names, &combat_stats).join()
         {
             if stats.hp <= 0 {
@@ -26,21 +106,194 @@ impl<'a> System<'a> for MeleeCombatSystem {
 
             let target_stats = combat_stats.get(wants_melee.target).unwrap();
             if target_stats.hp > 0 {
+                if let Some(pos) = positions.get(entity) {
+                    noise.make(&map, pos.x, pos.y, MELEE_NOISE);
+                }
+
                 let target_name = names.get(wants_melee.target).unwrap();
+                let attacker_is_player = players.contains(entity);
+                let target_is_player = players.contains(wants_melee.target);
+                // Fights the player can't see go unreported
+                let seen = attacker_is_player
+                    || target_is_player
+                    || positions
+                        .get(entity)
+                        .is_some_and(|pos| map.visible_tiles[map.xy_idx(pos.x, pos.y)]);
+
+                // Attacking ends sneaking; the first blow against a monster
+                // that hasn't noticed the attacker is a backstab
+                let backstab = sneaking.remove(entity).is_some()
+                    && ai_states
+                        .get(wants_melee.target)
+                        .is_some_and(|ai| !matches!(ai.mode, AiMode::Hunting { .. }));
+
+                if rng.roll_dice(1, 100) > hit_chance(stats.attack, target_stats.dodge) {
+                    if seen {
+                        events.push(GameEvent::Miss {
+                            attacker: name.clone(),
+                            attacker_is_player,
+                            target: target_name.clone(),
+                            target_is_player,
+                        });
+                    }
+                    continue;
+                }
+
+                let offensive_bonus: i32 = (&equipped, &melee_power_bonuses)
+                    .join()
+                    .filter(|(equipped_by, _bonus)| equipped_by.owner == entity)
+                    .map(|(_equipped_by, bonus)| bonus.power)
+                    .sum();
+                let defensive_bonus: i32 = (&equipped, &defense_bonuses)
+                    .join()
+                    .filter(|(equipped_by, _bonus)| equipped_by.owner == wants_melee.target)
+                    .map(|(_equipped_by, bonus)| bonus.defense)
+                    .sum();
+
+                let crit_chance: i32 = BASE_CRIT_CHANCE
+                    + (&equipped, &crit_bonuses)
+                        .join()
+                        .filter(|(equipped_by, _bonus)| equipped_by.owner == entity)
+                        .map(|(_equipped_by, bonus)| bonus.chance)
+                        .sum::<i32>();
+
+                let mut damage = i32::max(
+                    0,
+                    (stats.power + offensive_bonus) - (target_stats.defense + defensive_bonus),
+                );
+                let critical = damage > 0 && rng.roll_dice(1, 100) <= crit_chance;
+                if critical {
+                    damage *= 2;
+                }
+                if backstab {
+                    damage *= BACKSTAB_MULTIPLIER;
+                }
+
+                if seen {
+                    events.push(GameEvent::Damage {
+                        attacker: name.clone(),
+                        attacker_is_player,
+                        target: target_name.clone(),
+                        target_is_player,
+                        amount: after_resistance(
+                            damage,
+                            DamageType::Physical,
+                            resistances.get(wants_melee.target),
+                        ),
+                        critical,
+                        backstab,
+                    });
+                }
+                if damage > 0 {
+                    SufferDamage::new_damage(
+                        &mut inflict_damage,
+                        wants_melee.target,
+                        damage,
+                        DamageType::Physical,
+                        attacker_is_player,
+                    );
+                    if let Some(pos) = positions.get(wants_melee.target) {
+                        let (color, glyph) = if critical {
+                            (YELLOW, '☼')
+                        } else {
+                            (RED, '‼')
+                        };
+                        particle_builder.request(
+                            pos.x,
+                            pos.y,
+                            RGB::named(color),
+                            RGBA::named(BLACK),
+                            to_cp437(glyph),
+                            200.0,
+                        );
+                    }
+
+                    // Heavy weapons, and some monsters' fists, send the target
+                    // flying back along the line of the blow. Big monsters
+                    // stand their ground.
+                    let knockback = (&equipped, &knockbacks)
+                        .join()
+                        .filter(|(equipped_by, _knockback)| equipped_by.owner == entity)
+                        .map(|(_equipped_by, knockback)| knockback.distance)
+                        .chain(knockbacks.get(entity).map(|knockback| knockback.distance))
+                        .max()
+                        .filter(|_distance| !sizes.contains(wants_melee.target));
+                    if let (Some(distance), Some(from), Some(to)) = (
+                        knockback,
+                        positions.get(entity).cloned(),
+                        positions.get(wants_melee.target).cloned(),
+                    ) {
+                        let (dx, dy) = ((to.x - from.x).signum(), (to.y - from.y).signum());
+                        let mut landing = Point::new(to.x, to.y);
+                        for _ in 0..distance {
+                            let next = Point::new(landing.x + dx, landing.y + dy);
+                            if !map.in_bounds(next)
+                                || map.blocked[map.xy_idx(next.x, next.y)]
+                                || map.squeezes_past_corner(landing.x, landing.y, dx, dy)
+                            {
+                                break;
+                            }
+                            landing = next;
+                        }
+
+                        if landing != Point::new(to.x, to.y) {
+                            let old_idx = map.xy_idx(to.x, to.y);
+                            let new_idx = map.xy_idx(landing.x, landing.y);
+                            map.blocked[old_idx] = false;
+                            map.blocked[new_idx] = true;
+                            let pos = positions.get_mut(wants_melee.target).unwrap();
+                            pos.x = landing.x;
+                            pos.y = landing.y;
+                            if let Some(fov) = fovs.get_mut(wants_melee.target) {
+                                fov.dirty = true;
+                            }
+                            entity_moved
+                                .insert(wants_melee.target, EntityMoved {})
+                                .expect("Unable to insert marker");
+                            if target_is_player {
+                                *player_pos = landing;
+                            }
+                            if seen {
+                                events.push(GameEvent::KnockedBack {
+                                    target: target_name.clone(),
+                                    target_is_player,
+                                });
+                            }
+                        } else if map.is_opaque(map.xy_idx(to.x + dx, to.y + dy)) {
+                            // Nowhere to go but into the wall
+                            SufferDamage::new_damage(
+                                &mut inflict_damage,
+                                wants_melee.target,
+                                KNOCKBACK_COLLISION_DAMAGE,
+                                DamageType::Physical,
+                                attacker_is_player,
+                            );
+                            if seen {
+                                events.push(GameEvent::HitWall {
+                                    target: target_name.clone(),
+                                    target_is_player,
+                                    amount: KNOCKBACK_COLLISION_DAMAGE,
+                                });
+                            }
+                        }
+                    }
 
-                let damage = i32::max(0, stats.power - target_stats.defense);
-
-                if damage == 0 {
-                    log.entries.push(format!(
-                        "{} is unable to hurt {}",
-                        &name.name, &target_name.name
-                    ));
-                } else {
-                    log.entries.push(format!(
-                        "{} hits {}, for {} hp.",
-                        &name.name, &target_name.name, damage
-                    ));
-                    SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage);
+                    // Envenomed blades and the like
+                    for (_equipped_by, inflicts) in (&equipped, &inflicts_status)
+                        .join()
+                        .filter(|(equipped_by, _inflicts)| equipped_by.owner == entity)
+                    {
+                        if seen {
+                            events.push(GameEvent::Afflicted {
+                                target: target_name.clone(),
+                                target_is_player,
+                                kind: inflicts.effect.kind,
+                            });
+                        }
+                        effects
+                            .insert(wants_melee.target, inflicts.effect.clone())
+                            .expect("Unable to insert status effect");
+                    }
                 }
             }
         }
@@ -1,8 +1,25 @@
+use crate::gamelog::{GameLog, LogCategory};
+use crate::map::{footprint, TileType};
+use crate::noise::Noise;
+use crate::raws::{raws, Raws, Reaction};
 use crate::{
-    a_star_search, DistanceAlg, FieldOfView, Map, Monster, Point, Position, RunState, WantsToMelee,
+    a_star_search, AiMode, AiState, BaseMap, CanFlee, CombatStats, Confusion, DijkstraMap,
+    DistanceAlg, DoorUser, EntityMoved, Faction, FieldOfView, Map, Monster, MyTurn, Name,
+    PackMember, Point, Position, RandomNumberGenerator, RangedAttacker, RunState, Size, SmallVec,
+    Sneaking, Wading, WantsToMelee, WantsToShoot,
 };
 use specs::prelude::*;
 
+/// How far (in steps) the approach map spreads out from the player.
+const APPROACH_DEPTH: f32 = 200.0;
+
+/// How many turns a monster keeps looking for the player after losing track
+/// of them.
+pub const HUNT_TURNS: i32 = 10;
+
+/// How far a pack member strays from its leader before heading back.
+const PACK_RADIUS: f32 = 2.0;
+
 pub struct MonsterAI {}
 
 impl<'a> System<'a> for MonsterAI {
@@ -16,6 +33,23 @@ impl<'a> System<'a> for MonsterAI {
         ReadStorage<'a, Monster>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, WantsToMelee>,
+        WriteStorage<'a, Confusion>,
+        ReadStorage<'a, Name>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, DoorUser>,
+        WriteStorage<'a, Wading>,
+        WriteStorage<'a, CanFlee>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, RangedAttacker>,
+        WriteStorage<'a, WantsToShoot>,
+        WriteStorage<'a, EntityMoved>,
+        ReadStorage<'a, MyTurn>,
+        WriteExpect<'a, Noise>,
+        WriteStorage<'a, AiState>,
+        ReadStorage<'a, Faction>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        WriteStorage<'a, Sneaking>,
+        (ReadStorage<'a, Size>, ReadStorage<'a, PackMember>),
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -29,43 +63,492 @@ impl<'a> System<'a> for MonsterAI {
             monster,
             mut position,
             mut wants_to_melee,
+            mut confused,
+            names,
+            mut log,
+            door_users,
+            mut wading,
+            mut can_flee,
+            combat_stats,
+            ranged_attackers,
+            mut wants_to_shoot,
+            mut entity_moved,
+            my_turn,
+            mut noise,
+            mut ai_states,
+            factions,
+            mut rng,
+            mut sneaking,
+            (sizes, pack_members),
         ) = data;
 
         if *runstate != RunState::MonsterTurn {
             return;
         }
 
-        for (entity, mut fov, _monster, mut pos) in
-            (&entities, &mut fovs, &monster, &mut position).join()
+        // One map of distances to the player serves every monster this turn
+        let approach_map = DijkstraMap::new(
+            map.width,
+            map.height,
+            &[map.xy_idx(player_pos.x, player_pos.y)],
+            &ThroughCrowds {
+                map: &map,
+                opens_doors: true,
+            },
+            APPROACH_DEPTH,
+        );
+
+        // Whoever was in earshot of this turn's noise wakes up and comes
+        // looking, even those that don't get to act right now
+        for (_monster, pos, ai) in (&monster, &position, &mut ai_states).join() {
+            if noise.heard_at(map.xy_idx(pos.x, pos.y)) {
+                ai.mode = AiMode::Hunting {
+                    last_known: *player_pos,
+                    turns: HUNT_TURNS,
+                };
+            }
+        }
+        noise.clear();
+
+        // Everyone who could be fought, with where they stand and who they
+        // side with
+        let mut targets: Vec<(Entity, Point, String)> =
+            (&entities, &position, &factions, &combat_stats)
+                .join()
+                .filter(|(_entity, _pos, _faction, stats)| stats.hp > 0)
+                .map(|(entity, pos, faction, _stats)| {
+                    (entity, Point::new(pos.x, pos.y), faction.name.clone())
+                })
+                .collect();
+
+        // Those who can't open doors need their own way round them, made
+        // the first time one of them goes after the player
+        let mut doorless_approach: Option<DijkstraMap> = None;
+
+        let mut doors_changed = false;
+        for (entity, fov, _monster, pos, _my_turn) in
+            (&entities, &mut fovs, &monster, &mut position, &my_turn).join()
         {
-            let distance =
-                DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), *player_pos);
-            if distance < 1.5 {
-                wants_to_melee
-                    .insert(
+            if let Some(confusion) = confused.get_mut(entity) {
+                confusion.turns -= 1;
+                if confusion.turns < 1 {
+                    confused.remove(entity);
+                }
+                if let Some(name) = names.get(entity) {
+                    log.add(
+                        LogCategory::General,
+                        format!("{} is confused and can't act.", name.name),
+                    );
+                }
+                continue;
+            }
+
+            // Still struggling out of the water
+            if wading.remove(entity).is_some() {
+                continue;
+            }
+
+            let here = Point::new(pos.x, pos.y);
+            // A sneaking player has to come within half the usual sight
+            // range to be noticed, and being noticed by a foe gives the game
+            // away
+            let sees_player = fov.visible_tiles.contains(&*player_pos)
+                && (!sneaking.contains(*player_entity)
+                    || DistanceAlg::Pythagoras.distance2d(here, *player_pos)
+                        <= (fov.range / 2) as f32);
+            let my_faction = factions.get(entity).map(|faction| faction.name.as_str());
+            if sees_player
+                && is_hostile(raws(), my_faction, "Player")
+                && sneaking.remove(*player_entity).is_some()
+            {
+                if let Some(name) = names.get(entity) {
+                    log.add(LogCategory::General, format!("{} spots you!", name.name));
+                }
+            }
+            let mode = match ai_states.get_mut(entity) {
+                None => AiMode::Wandering,
+                Some(ai) => {
+                    ai.mode = next_mode(ai.mode, sees_player, *player_pos, here);
+                    ai.mode
+                }
+            };
+            if mode == AiMode::Sleeping {
+                continue;
+            }
+
+            if let (Some(flee), Some(stats)) = (can_flee.get_mut(entity), combat_stats.get(entity))
+            {
+                let badly_hurt = (stats.hp as f32) < flee.threshold * stats.max_hp as f32;
+                let escape = if badly_hurt && sees_player {
+                    highest_exit(&approach_map, map.xy_idx(pos.x, pos.y), &map)
+                } else {
+                    None
+                };
+
+                // With nowhere to run, cornered monsters fight on
+                let escape = escape.filter(|step| has_room(&map, pos, sizes.get(entity), *step));
+                if let Some(step) = escape {
+                    if !flee.fleeing {
+                        if let Some(name) = names.get(entity) {
+                            log.add(
+                                LogCategory::General,
+                                format!("{} turns to flee!", name.name),
+                            );
+                        }
+                    }
+                    flee.fleeing = true;
+                    let left = footprint(pos.x, pos.y, sizes.get(entity));
+                    move_monster(
+                        &mut map,
                         entity,
-                        WantsToMelee {
-                            target: *player_entity,
-                        },
-                    )
-                    .expect("Unable to insert attack");
-            } else if fov.visible_tiles.contains(&*player_pos) {
-                // Path to the player
-                let path = a_star_search(
-                    map.xy_idx(pos.x, pos.y),
-                    map.xy_idx(player_pos.x, player_pos.y),
-                    &*map,
-                );
-                if path.success && path.steps.len() > 1 {
-                    let mut idx = map.xy_idx(pos.x, pos.y);
-                    map.blocked[idx] = false;
-                    pos.x = path.steps[1] as i32 % map.width;
-                    pos.y = path.steps[1] as i32 / map.width;
-                    idx = map.xy_idx(pos.x, pos.y);
-                    map.blocked[idx] = true;
-                    fov.dirty = true;
+                        pos,
+                        fov,
+                        step,
+                        &mut wading,
+                        &mut entity_moved,
+                    );
+                    move_footprint(&mut map, &left, pos, sizes.get(entity));
+                    continue;
                 }
+                if !badly_hurt {
+                    flee.fleeing = false;
+                }
+            }
+
+            // Go for the nearest enemy in sight
+            let target = targets
+                .iter()
+                .filter(|(other, other_pos, _faction)| {
+                    *other != entity && fov.visible_tiles.contains(other_pos)
+                })
+                .filter(|(_other, _pos, faction)| is_hostile(raws(), my_faction, faction))
+                .min_by(|(_, a, _), (_, b, _)| {
+                    let to_a = DistanceAlg::Pythagoras.distance2d(here, *a);
+                    let to_b = DistanceAlg::Pythagoras.distance2d(here, *b);
+                    to_a.total_cmp(&to_b)
+                })
+                .map(|(other, other_pos, _faction)| (*other, *other_pos));
+
+            let idx = map.xy_idx(pos.x, pos.y);
+            let opens_doors = door_users.contains(entity);
+            let step = match target {
+                Some((target, target_pos)) => {
+                    // Big monsters reach from whichever of their tiles is
+                    // closest
+                    let distance = footprint(pos.x, pos.y, sizes.get(entity))
+                        .iter()
+                        .map(|tile| DistanceAlg::Pythagoras.distance2d(*tile, target_pos))
+                        .fold(f32::MAX, f32::min);
+                    let in_range = ranged_attackers
+                        .get(entity)
+                        .is_some_and(|ranged| distance <= ranged.range as f32);
+                    if distance >= 1.5 && in_range {
+                        wants_to_shoot
+                            .insert(entity, WantsToShoot { target })
+                            .expect("Unable to insert shot");
+                        continue;
+                    }
+                    if distance < 1.5 {
+                        wants_to_melee
+                            .insert(entity, WantsToMelee { target })
+                            .expect("Unable to insert attack");
+                        continue;
+                    }
+
+                    if target == *player_entity && opens_doors {
+                        lowest_exit(&approach_map, idx, &map, opens_doors)
+                    } else if target == *player_entity {
+                        let around = doorless_approach.get_or_insert_with(|| {
+                            DijkstraMap::new(
+                                map.width,
+                                map.height,
+                                &[map.xy_idx(player_pos.x, player_pos.y)],
+                                &ThroughCrowds {
+                                    map: &map,
+                                    opens_doors: false,
+                                },
+                                APPROACH_DEPTH,
+                            )
+                        });
+                        lowest_exit(around, idx, &map, opens_doors)
+                    } else {
+                        let target_idx = map.xy_idx(target_pos.x, target_pos.y);
+                        step_towards(&mut map, idx, target_idx, opens_doors)
+                    }
+                }
+                None => match mode {
+                    AiMode::Hunting { last_known, .. } => {
+                        let last_known_idx = map.xy_idx(last_known.x, last_known.y);
+                        step_towards(&mut map, idx, last_known_idx, opens_doors)
+                    }
+                    // A pack keeps together, but a member without a leader
+                    // fends for itself
+                    AiMode::Wandering => match pack_members.get(entity).and_then(|member| {
+                        targets.iter().find(|(other, _, _)| *other == member.leader)
+                    }) {
+                        Some((_leader, leader_pos, _faction))
+                            if DistanceAlg::Pythagoras.distance2d(here, *leader_pos)
+                                > PACK_RADIUS =>
+                        {
+                            let leader_idx = map.xy_idx(leader_pos.x, leader_pos.y);
+                            step_towards(&mut map, idx, leader_idx, opens_doors)
+                        }
+                        _ => random_step(&map, idx, opens_doors, &mut rng),
+                    },
+                    AiMode::Sleeping => None,
+                },
+            };
+
+            // Step on, or wait if there's nowhere to go
+            let Some(step) = step else {
+                continue;
+            };
+            if map.is_closed_door(step) {
+                // Opening the door takes the whole turn
+                map.open_door(step);
+                if map.visible_tiles[step] {
+                    log.add(LogCategory::General, "The door creaks open.");
+                }
+                doors_changed = true;
+                continue;
+            }
+            if !has_room(&map, pos, sizes.get(entity), step) {
+                continue;
+            }
+            let left = footprint(pos.x, pos.y, sizes.get(entity));
+            move_monster(
+                &mut map,
+                entity,
+                pos,
+                fov,
+                step,
+                &mut wading,
+                &mut entity_moved,
+            );
+            move_footprint(&mut map, &left, pos, sizes.get(entity));
+            if let Some(me) = targets.iter_mut().find(|(other, _, _)| *other == entity) {
+                me.1 = Point::new(pos.x, pos.y);
+            }
+
+            // Unhurried door users pull the door to behind them
+            if opens_doors && mode == AiMode::Wandering && map.tiles[idx] == TileType::Door {
+                map.close_door(idx);
+                doors_changed = true;
+            }
+        }
+
+        if doors_changed {
+            for fov in (&mut fovs).join() {
+                fov.dirty = true;
+            }
+        }
+    }
+}
+
+/// Works out what a monster is up to this turn. Seeing the player wakes it
+/// up and sets it hunting. Out of sight, a hunt goes on to where the player
+/// was last known to be, and the monster gives up once it gets there or runs
+/// out of patience.
+pub fn next_mode(mode: AiMode, sees_player: bool, player_pos: Point, here: Point) -> AiMode {
+    if sees_player {
+        return AiMode::Hunting {
+            last_known: player_pos,
+            turns: HUNT_TURNS,
+        };
+    }
+
+    match mode {
+        AiMode::Hunting { last_known, turns } if turns > 1 && last_known != here => {
+            AiMode::Hunting {
+                last_known,
+                turns: turns - 1,
             }
         }
+        AiMode::Hunting { .. } => AiMode::Wandering,
+        mode => mode,
+    }
+}
+
+/// A random neighbor of `idx` to amble to, if there is one.
+fn random_step(
+    map: &Map,
+    idx: usize,
+    opens_doors: bool,
+    rng: &mut RandomNumberGenerator,
+) -> Option<usize> {
+    let exits: Vec<usize> = map
+        .get_available_exits(idx)
+        .iter()
+        .map(|(exit, _cost)| *exit)
+        .filter(|exit| opens_doors || !map.is_closed_door(*exit))
+        .collect();
+    if exits.is_empty() {
+        return None;
+    }
+    Some(exits[rng.range(0, exits.len() as i32) as usize])
+}
+
+/// Whether a member of `mine` attacks members of `theirs` on sight. Those
+/// without a faction keep to themselves.
+pub fn is_hostile(raws: &Raws, mine: Option<&str>, theirs: &str) -> bool {
+    mine.is_some_and(|mine| raws.reaction(mine, theirs) == Reaction::Hostile)
+}
+
+/// The map as seen by something that can't open doors: closed ones are as
+/// good as walls, so paths go around them.
+struct AvoidingDoors<'a>(&'a Map);
+
+impl BaseMap for AvoidingDoors<'_> {
+    fn is_opaque(&self, idx: usize) -> bool {
+        self.0.is_opaque(idx)
+    }
+
+    fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
+        self.0
+            .get_available_exits(idx)
+            .into_iter()
+            .filter(|(exit, _cost)| !self.0.is_closed_door(*exit))
+            .collect()
+    }
+
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        self.0.get_pathing_distance(idx1, idx2)
+    }
+}
+
+/// The map as it is under everyone's feet, to measure distances by: tiles
+/// that are only blocked by whoever stands on them are open, and so have a
+/// distance like any other. Closed doors are walls to those who can't open
+/// them.
+struct ThroughCrowds<'a> {
+    map: &'a Map,
+    opens_doors: bool,
+}
+
+impl BaseMap for ThroughCrowds<'_> {
+    fn is_opaque(&self, idx: usize) -> bool {
+        self.map.is_opaque(idx)
+    }
+
+    fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
+        self.map
+            .exits(idx, true)
+            .into_iter()
+            .filter(|(exit, _cost)| self.opens_doors || !self.map.is_closed_door(*exit))
+            .collect()
     }
+
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        self.map.get_pathing_distance(idx1, idx2)
+    }
+}
+
+/// First step on the shortest path from `idx` to `target`, for chasing
+/// something other than the player. The target stands on its own tile, so that
+/// is opened up while the path is found.
+fn step_towards(map: &mut Map, idx: usize, target: usize, opens_doors: bool) -> Option<usize> {
+    let was_blocked = map.blocked[target];
+    map.blocked[target] = false;
+    let path = if opens_doors {
+        a_star_search(idx, target, &*map)
+    } else {
+        a_star_search(idx, target, &AvoidingDoors(map))
+    };
+    map.blocked[target] = was_blocked;
+
+    if !path.success || path.steps.len() < 2 {
+        return None;
+    }
+    let step = path.steps[1];
+    // A closed door is only in the way of those who can't open it
+    let passable = if map.is_closed_door(step) {
+        opens_doors
+    } else {
+        !map.blocked[step]
+    };
+    passable.then_some(step)
+}
+
+/// Moves a monster to the tile `step`, keeping the blocked tiles up to date.
+fn move_monster(
+    map: &mut Map,
+    entity: Entity,
+    pos: &mut Position,
+    fov: &mut FieldOfView,
+    step: usize,
+    wading: &mut WriteStorage<Wading>,
+    entity_moved: &mut WriteStorage<EntityMoved>,
+) {
+    let idx = map.xy_idx(pos.x, pos.y);
+    map.blocked[idx] = false;
+    pos.x = step as i32 % map.width;
+    pos.y = step as i32 / map.width;
+    map.blocked[step] = true;
+    fov.dirty = true;
+    entity_moved
+        .insert(entity, EntityMoved {})
+        .expect("Unable to insert marker");
+
+    if map.tiles[step] == TileType::ShallowWater {
+        wading
+            .insert(entity, Wading {})
+            .expect("Unable to insert wading");
+    }
+}
+
+/// Whether a monster of `size` has room to move its top left corner to
+/// `step`. Those of ordinary size only ever need the one tile.
+fn has_room(map: &Map, pos: &Position, size: Option<&Size>, step: usize) -> bool {
+    let Some(size) = size else {
+        return true;
+    };
+    let own = footprint(pos.x, pos.y, Some(size));
+    map.footprint_fits(step as i32 % map.width, step as i32 / map.width, size, &own)
+}
+
+/// Moves the tiles a big monster blocks from those it `left` to those it
+/// covers now.
+fn move_footprint(map: &mut Map, left: &[Point], pos: &Position, size: Option<&Size>) {
+    if size.is_none() {
+        return;
+    }
+    for tile in left {
+        let idx = map.xy_idx(tile.x, tile.y);
+        map.blocked[idx] = false;
+    }
+    for tile in footprint(pos.x, pos.y, size) {
+        let idx = map.xy_idx(tile.x, tile.y);
+        map.blocked[idx] = true;
+    }
+}
+
+/// Picks the open neighbor of `idx` that's furthest from the start of the
+/// Dijkstra map, as long as it's further than `idx` itself.
+fn highest_exit(dijkstra: &DijkstraMap, idx: usize, map: &Map) -> Option<usize> {
+    let here = dijkstra.map[idx];
+    map.get_available_exits(idx)
+        .iter()
+        .map(|(exit, _cost)| *exit)
+        .filter(|exit| dijkstra.map[*exit] < f32::MAX && dijkstra.map[*exit] > here)
+        .filter(|exit| !map.is_closed_door(*exit))
+        .max_by(|a, b| dijkstra.map[*a].total_cmp(&dijkstra.map[*b]))
+}
+
+/// Picks the unblocked neighbor of `idx` that's closest to the start of the
+/// Dijkstra map, as long as it's closer than `idx` itself: with the way
+/// ahead taken, waiting beats stepping back. Neighbors the map never reached
+/// don't count, and closed doors only count for those who can open them.
+pub fn lowest_exit(
+    dijkstra: &DijkstraMap,
+    idx: usize,
+    map: &Map,
+    opens_doors: bool,
+) -> Option<usize> {
+    map.get_available_exits(idx)
+        .iter()
+        .map(|(exit, _cost)| *exit)
+        .filter(|exit| dijkstra.map[*exit] < f32::MAX)
+        .filter(|exit| opens_doors || !map.is_closed_door(*exit))
+        .min_by(|a, b| dijkstra.map[*a].total_cmp(&dijkstra.map[*b]))
+        .filter(|exit| dijkstra.map[*exit] < dijkstra.map[idx])
 }
@@ -0,0 +1,63 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{BaseMap, Map};
+
+/// How loud a melee attack is: the number of tiles it carries.
+pub const MELEE_NOISE: i32 = 6;
+
+/// How loud a door creaking open is.
+pub const DOOR_NOISE: i32 = 3;
+
+/// Everything that was heard since the monsters last listened, as the
+/// loudest intensity reaching each tile.
+#[derive(Default)]
+pub struct Noise {
+    pub tiles: HashMap<usize, i32>,
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise::default()
+    }
+
+    /// Makes a noise at `(x, y)` that spreads through open space, losing one
+    /// point of intensity for every tile it travels. Walls and closed doors
+    /// stop it; creatures in the way don't.
+    pub fn make(&mut self, map: &Map, x: i32, y: i32, loudness: i32) {
+        let start = map.xy_idx(x, y);
+        let mut heard: HashMap<usize, i32> = HashMap::new();
+        let mut open_list = VecDeque::from([(start, loudness)]);
+        heard.insert(start, loudness);
+
+        while let Some((idx, intensity)) = open_list.pop_front() {
+            if intensity <= 1 {
+                continue;
+            }
+            let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+            for (nx, ny) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy))) {
+                if nx < 0 || nx >= map.width || ny < 0 || ny >= map.height {
+                    continue;
+                }
+                let next = map.xy_idx(nx, ny);
+                if map.is_opaque(next) || heard.contains_key(&next) {
+                    continue;
+                }
+                heard.insert(next, intensity - 1);
+                open_list.push_back((next, intensity - 1));
+            }
+        }
+
+        for (idx, intensity) in heard {
+            let loudest = self.tiles.entry(idx).or_insert(0);
+            *loudest = i32::max(*loudest, intensity);
+        }
+    }
+
+    pub fn heard_at(&self, idx: usize) -> bool {
+        self.tiles.get(&idx).is_some_and(|intensity| *intensity > 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{ParticleLifetime, Position, Renderable, RGB, RGBA};
+use bracket_lib::prelude::{line2d, BTerm, FontCharType, LineAlg, Point, BLACK};
+use specs::prelude::*;
+
+/// How long a projectile takes to cross one tile.
+const PROJECTILE_STEP_MS: f32 = 30.0;
+
+/// Counts down particle delays, then lifetimes, by the time the last frame
+/// took and deletes the particles that have run out.
+pub fn cull_dead_particles(ecs: &mut World, ctx: &BTerm) {
+    let mut dead_particles: Vec<Entity> = Vec::new();
+    {
+        let mut particles = ecs.write_storage::<ParticleLifetime>();
+        let entities = ecs.entities();
+        for (entity, particle) in (&entities, &mut particles).join() {
+            if particle.delay_ms > 0.0 {
+                particle.delay_ms -= ctx.frame_time_ms;
+                continue;
+            }
+            particle.lifetime_ms -= ctx.frame_time_ms;
+            if particle.lifetime_ms < 0.0 {
+                dead_particles.push(entity);
+            }
+        }
+    }
+
+    ecs.delete_entities(&dead_particles)
+        .expect("Particle will not die");
+}
+
+struct ParticleRequest {
+    x: i32,
+    y: i32,
+    fg: RGB,
+    bg: RGBA,
+    glyph: FontCharType,
+    lifetime: f32,
+    delay: f32,
+}
+
+/// Every tile on the straight line from `start` to `end`, both included, each
+/// touching the one before it.
+pub fn line_path(start: Point, end: Point) -> Vec<Point> {
+    line2d(LineAlg::Bresenham, start, end)
+}
+
+/// Queue of particles that systems want shown. `ParticleSpawnSystem` turns
+/// them into entities.
+pub struct ParticleBuilder {
+    requests: Vec<ParticleRequest>,
+}
+
+impl ParticleBuilder {
+    pub fn new() -> ParticleBuilder {
+        ParticleBuilder {
+            requests: Vec::new(),
+        }
+    }
+
+    pub fn request(
+        &mut self,
+        x: i32,
+        y: i32,
+        fg: RGB,
+        bg: RGBA,
+        glyph: FontCharType,
+        lifetime: f32,
+    ) {
+        self.requests.push(ParticleRequest {
+            x,
+            y,
+            fg,
+            bg,
+            glyph,
+            lifetime,
+            delay: 0.0,
+        });
+    }
+
+    /// Sends a shot flying along `path`, lighting up one tile after the
+    /// other. The tile it's fired from is skipped. Nothing waits for it to
+    /// land; it's only there to be seen.
+    pub fn projectile(&mut self, path: &[Point], fg: RGB, glyph: FontCharType) {
+        for (step, point) in path.iter().skip(1).enumerate() {
+            self.requests.push(ParticleRequest {
+                x: point.x,
+                y: point.y,
+                fg,
+                bg: RGBA::named(BLACK),
+                glyph,
+                lifetime: PROJECTILE_STEP_MS,
+                delay: step as f32 * PROJECTILE_STEP_MS,
+            });
+        }
+    }
+}
+
+pub struct ParticleSpawnSystem {}
+
+impl<'a> System<'a> for ParticleSpawnSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Renderable>,
+        WriteStorage<'a, ParticleLifetime>,
+        WriteExpect<'a, ParticleBuilder>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut positions, mut renderables, mut particles, mut particle_builder) = data;
+
+        for new_particle in particle_builder.requests.drain(..) {
+            let p = entities.create();
+            positions
+                .insert(
+                    p,
+                    Position {
+                        x: new_particle.x,
+                        y: new_particle.y,
+                    },
+                )
+                .expect("Unable to insert position");
+            renderables
+                .insert(
+                    p,
+                    Renderable {
+                        glyph: new_particle.glyph,
+                        fg: new_particle.fg,
+                        bg: new_particle.bg,
+                        render_order: 0,
+                    },
+                )
+                .expect("Unable to insert renderable");
+            particles
+                .insert(
+                    p,
+                    ParticleLifetime {
+                        lifetime_ms: new_particle.lifetime,
+                        delay_ms: new_particle.delay,
+                    },
+                )
+                .expect("Unable to insert lifetime");
+        }
+    }
+}
@@ -2,10 +2,74 @@ use bracket_lib::prelude::*;
 use num::clamp;
 use specs::prelude::*;
 
+use crate::camera;
 use crate::components::Player;
-use crate::{CombatStats, FieldOfView, Map, Position, RunState, State, WantsToMelee};
+use crate::gamelog::{GameLog, LogCategory};
+use crate::gui::{UiState, VendorMode};
+use crate::keybindings::{Action, Keybindings};
+use crate::map::TileType;
+use crate::monster_ai_system::lowest_exit;
+use crate::noise::{Noise, DOOR_NOISE};
+use crate::ranged_combat_system::{ammo_left, equipped_ranged_weapon};
+use crate::raws::{raws, Reaction};
+use crate::{
+    CombatStats, Confusion, Dialogue, EntityMoved, Faction, FieldOfView, HungerClock, HungerState,
+    InInventory, Item, Key, Map, Monster, Position, ProvidesHealing, RunState, Sneaking, State,
+    Vendor, Wading, WantsToMelee, WantsToPickupItem, WantsToShoot, WantsToUseItem,
+};
 
-pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
+/// How far (in steps) auto-explore looks for unexplored tiles.
+const EXPLORE_DEPTH: f32 = 1000.0;
+
+/// Odds, one in this many, of a search turning up each secret door next to
+/// the player.
+const SEARCH_CHANCE: i32 = 3;
+
+/// Whatever the player is doing without waiting for a key each turn.
+#[derive(PartialEq, Clone)]
+pub enum Autopilot {
+    Off,
+    Explore,
+    /// Passing turns until healed, or until something comes up.
+    Rest,
+    /// Walking to a clicked tile, with the steps still to take, and going
+    /// down once there if it's the stairs.
+    Travel {
+        path: Vec<usize>,
+        descend: bool,
+    },
+}
+
+/// The way a confused player lurches instead of where they meant to go, or
+/// `None` if they aren't confused.
+fn stumble(ecs: &mut World) -> Option<(i32, i32)> {
+    let player_entity = *ecs.fetch::<Entity>();
+    if !ecs.read_storage::<Confusion>().contains(player_entity) {
+        return None;
+    }
+
+    let direction = ecs.write_resource::<RandomNumberGenerator>().range(0, 8);
+    let step = [
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+        (-1, 0),
+        (1, 0),
+        (-1, 1),
+        (0, 1),
+        (1, 1),
+    ][direction as usize];
+    ecs.fetch_mut::<GameLog>()
+        .add(LogCategory::General, "You stumble, confused!");
+    Some(step)
+}
+
+/// Moves the player, or attacks, opens a door, starts trading or talks,
+/// depending on what's in the way. A confused player goes whichever way they
+/// stumble.
+/// Returns the screen to go to next.
+pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) -> RunState {
+    let (delta_x, delta_y) = stumble(ecs).unwrap_or((delta_x, delta_y));
     let mut positions = ecs.write_storage::<Position>();
     let mut players = ecs.write_storage::<Player>();
     let mut fovs = ecs.write_storage::<FieldOfView>();
@@ -13,14 +77,48 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
     let entities = ecs.entities();
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
     let combat_stats = ecs.read_storage::<CombatStats>();
-    let map = ecs.fetch::<Map>();
+    let vendors = ecs.read_storage::<Vendor>();
+    let dialogues = ecs.read_storage::<Dialogue>();
+    let factions = ecs.read_storage::<Faction>();
+    let keys = ecs.read_storage::<Key>();
+    let backpack = ecs.read_storage::<InInventory>();
+    let mut wading = ecs.write_storage::<Wading>();
+    let mut entity_moved = ecs.write_storage::<EntityMoved>();
+    let mut noise = ecs.fetch_mut::<Noise>();
+    let mut sneaking = ecs.write_storage::<Sneaking>();
+    let mut map = ecs.fetch_mut::<Map>();
+    let mut door_opened = false;
 
     for (entity, _player, pos, fov) in (&entities, &mut players, &mut positions, &mut fovs).join() {
-        let dest_x = clamp(pos.x + delta_x, 0, 79);
-        let dest_y = clamp(pos.y + delta_y, 0, 49);
+        let dest_x = clamp(pos.x + delta_x, 0, map.width - 1);
+        let dest_y = clamp(pos.y + delta_y, 0, map.height - 1);
+        if map.squeezes_past_corner(pos.x, pos.y, delta_x, delta_y) {
+            return RunState::PlayerTurn;
+        }
         let destination_idx = map.xy_idx(dest_x, dest_y);
 
         for potential_target in map.tile_content[destination_idx].iter() {
+            if vendors.contains(*potential_target) {
+                return RunState::ShowVendor {
+                    vendor: *potential_target,
+                    mode: VendorMode::Buy,
+                };
+            }
+
+            let peaceful = is_peaceful(&factions, *potential_target);
+            if peaceful && dialogues.contains(*potential_target) {
+                return RunState::ShowDialogue {
+                    npc: *potential_target,
+                    line: 0,
+                };
+            }
+
+            // Allies and those who mean no harm are waited on to make way,
+            // not attacked
+            if peaceful || is_ally(&factions, entity, *potential_target) {
+                return RunState::PlayerTurn;
+            }
+
             let target = combat_stats.get(*potential_target);
             match target {
                 None => {}
@@ -34,57 +132,645 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
                             },
                         )
                         .expect("Add target failed");
-                    return;
+                    return RunState::PlayerTurn;
+                }
+            }
+        }
+
+        if map.is_locked(destination_idx) {
+            let lock = map.locked_doors[&destination_idx];
+            let key = (&entities, &keys, &backpack)
+                .join()
+                .find(|(_entity, key, pack)| key.id == lock && pack.owner == entity)
+                .map(|(key_entity, _key, _pack)| key_entity);
+            let mut log = ecs.fetch_mut::<GameLog>();
+            match key {
+                None => {
+                    log.add(LogCategory::General, "The door is locked.");
+                    return RunState::AwaitingInput;
+                }
+                Some(key) => {
+                    // The key stays in the lock
+                    entities.delete(key).expect("Unable to delete key");
+                    map.locked_doors.remove(&destination_idx);
+                    log.add(LogCategory::General, "You unlock the door.");
                 }
             }
         }
 
-        if !map.blocked[destination_idx] {
+        if map.is_closed_door(destination_idx) {
+            map.open_door(destination_idx);
+            noise.make(&map, dest_x, dest_y, DOOR_NOISE);
+            door_opened = true;
+        } else if !map.blocked[destination_idx] {
             pos.x = dest_x;
             pos.y = dest_y;
             fov.dirty = true;
 
             player_pos.x = pos.x;
             player_pos.y = pos.y;
+            entity_moved
+                .insert(entity, EntityMoved {})
+                .expect("Unable to insert marker");
+            if let Some(sneak) = sneaking.get_mut(entity) {
+                sneak.stepped = true;
+            }
+
+            if map.tiles[destination_idx] == TileType::ShallowWater {
+                wading
+                    .insert(entity, Wading {})
+                    .expect("Unable to insert wading");
+            }
         }
     }
+
+    if door_opened {
+        let mut log = ecs.fetch_mut::<GameLog>();
+        log.add(LogCategory::General, "The door creaks open.");
+        // Everyone's view might reach further now
+        for fov in (&mut fovs).join() {
+            fov.dirty = true;
+        }
+    }
+
+    RunState::PlayerTurn
 }
 
-pub fn player_input(gs: &mut State, ctx: &mut BTerm) -> RunState {
-    match ctx.key {
+fn get_item(ecs: &mut World) {
+    let player_pos = ecs.fetch::<Point>();
+    let player_entity = ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let items = ecs.read_storage::<Item>();
+    let positions = ecs.read_storage::<Position>();
+    let mut log = ecs.fetch_mut::<GameLog>();
+
+    let mut target_item: Option<Entity> = None;
+    for (item_entity, _item, position) in (&entities, &items, &positions).join() {
+        if position.x == player_pos.x && position.y == player_pos.y {
+            target_item = Some(item_entity);
+        }
+    }
+
+    match target_item {
+        None => log.add(LogCategory::Item, "There is nothing here to pick up."),
+        Some(item) => {
+            let mut pickup = ecs.write_storage::<WantsToPickupItem>();
+            pickup
+                .insert(
+                    *player_entity,
+                    WantsToPickupItem {
+                        collected_by: *player_entity,
+                        item,
+                    },
+                )
+                .expect("Unable to insert want to pickup");
+        }
+    }
+}
+
+/// Drinks the first healing potion in the player's pack.
+fn quaff_potion(ecs: &mut World) -> bool {
+    let player_entity = ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let backpack = ecs.read_storage::<InInventory>();
+    let healing = ecs.read_storage::<ProvidesHealing>();
+
+    let potion = (&entities, &backpack, &healing)
+        .join()
+        .find(|(_entity, pack, _healing)| pack.owner == *player_entity)
+        .map(|(entity, _pack, _healing)| entity);
+
+    match potion {
         None => {
-            return RunState::AwaitingInput;
-        } // Nothing happened
-        Some(key) => match key {
-            VirtualKeyCode::Left | VirtualKeyCode::Numpad4 | VirtualKeyCode::H => {
-                try_move_player(-1, 0, &mut gs.ecs)
-            }
+            let mut log = ecs.fetch_mut::<GameLog>();
+            log.add(LogCategory::Item, "You have no potions.");
+            false
+        }
+        Some(item) => {
+            let mut wants_use = ecs.write_storage::<WantsToUseItem>();
+            wants_use
+                .insert(*player_entity, WantsToUseItem { item, target: None })
+                .expect("Unable to insert intent");
+            true
+        }
+    }
+}
 
-            VirtualKeyCode::Right | VirtualKeyCode::Numpad6 | VirtualKeyCode::L => {
-                try_move_player(1, 0, &mut gs.ecs)
-            }
+/// Starts or stops sneaking. Doesn't take a turn.
+fn toggle_sneaking(ecs: &mut World) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut sneaking = ecs.write_storage::<Sneaking>();
+    let mut log = ecs.fetch_mut::<GameLog>();
+    if sneaking.remove(player_entity).is_some() {
+        log.add(LogCategory::General, "You stop sneaking.");
+    } else {
+        sneaking
+            .insert(player_entity, Sneaking { stepped: false })
+            .expect("Unable to insert sneaking");
+        log.add(LogCategory::General, "You start sneaking.");
+    }
+}
 
-            VirtualKeyCode::Up | VirtualKeyCode::Numpad8 | VirtualKeyCode::K => {
-                try_move_player(0, -1, &mut gs.ecs)
+/// Gives each secret door next to `pos` a chance of being found, turning it
+/// into an ordinary door. Returns how many were found.
+pub fn search_for_secret_doors(
+    map: &mut Map,
+    pos: Point,
+    rng: &mut RandomNumberGenerator,
+) -> usize {
+    let mut found = 0;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let (x, y) = (pos.x + dx, pos.y + dy);
+            if x < 0 || x >= map.width || y < 0 || y >= map.height {
+                continue;
+            }
+            let idx = map.xy_idx(x, y);
+            if map.tiles[idx] == TileType::SecretDoor && rng.roll_dice(1, SEARCH_CHANCE) == 1 {
+                map.reveal_secret_door(idx);
+                found += 1;
             }
+        }
+    }
+    found
+}
+
+/// Spends the turn searching the walls around the player.
+fn search(ecs: &mut World) {
+    let player_pos = *ecs.fetch::<Point>();
+    let mut map = ecs.fetch_mut::<Map>();
+    let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+    let mut log = ecs.fetch_mut::<GameLog>();
+    for _ in 0..search_for_secret_doors(&mut map, player_pos, &mut rng) {
+        log.add(LogCategory::General, "You found a hidden passage!");
+    }
+}
+
+/// Picks a target for the equipped bow, as long as there is one and
+/// something to shoot with it.
+fn ready_to_fire(ecs: &World) -> RunState {
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut log = ecs.fetch_mut::<GameLog>();
+    match equipped_ranged_weapon(ecs, player_entity) {
+        None => {
+            log.add(LogCategory::Combat, "You have nothing to shoot with.");
+            RunState::AwaitingInput
+        }
+        Some((_weapon, ranged)) if ammo_left(ecs, player_entity, &ranged.ammo) == 0 => {
+            log.add(LogCategory::Combat, format!("Out of {}s!", ranged.ammo));
+            RunState::AwaitingInput
+        }
+        Some((_weapon, ranged)) => RunState::ShowFireTargeting {
+            range: ranged.range,
+        },
+    }
+}
+
+/// Lines up a shot at whoever stands on `target`. Gives `false` if there's
+/// nobody there to shoot.
+pub fn fire_at(ecs: &mut World, target: Point) -> bool {
+    let player_entity = *ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let idx = map.xy_idx(target.x, target.y);
+    let victim = map.tile_content[idx]
+        .iter()
+        .copied()
+        .find(|entity| *entity != player_entity && combat_stats.contains(*entity));
+
+    match victim {
+        None => {
+            let mut log = ecs.fetch_mut::<GameLog>();
+            log.add(LogCategory::Combat, "There's nothing there to shoot.");
+            false
+        }
+        Some(victim) => {
+            let mut wants_shoot = ecs.write_storage::<WantsToShoot>();
+            wants_shoot
+                .insert(player_entity, WantsToShoot { target: victim })
+                .expect("Unable to insert intent");
+            true
+        }
+    }
+}
+
+/// Whether `other` is on the same side as `entity`.
+fn is_ally(factions: &ReadStorage<Faction>, entity: Entity, other: Entity) -> bool {
+    match (factions.get(entity), factions.get(other)) {
+        (Some(mine), Some(theirs)) => other != entity && mine.name == theirs.name,
+        _ => false,
+    }
+}
+
+/// Whether `other` belongs to a faction that leaves the player be. Those
+/// without one are fair game.
+fn is_peaceful(factions: &ReadStorage<Faction>, other: Entity) -> bool {
+    factions
+        .get(other)
+        .is_some_and(|theirs| raws().reaction(&theirs.name, "Player") != Reaction::Hostile)
+}
+
+/// Whether the player can see a monster that isn't on their side.
+fn monster_in_sight(ecs: &World) -> bool {
+    let player_entity = ecs.fetch::<Entity>();
+    let fovs = ecs.read_storage::<FieldOfView>();
+    let monsters = ecs.read_storage::<Monster>();
+    let factions = ecs.read_storage::<Faction>();
+    let map = ecs.fetch::<Map>();
+
+    fovs.get(*player_entity).is_some_and(|fov| {
+        fov.visible_tiles.iter().any(|tile| {
+            map.tile_content[map.xy_idx(tile.x, tile.y)]
+                .iter()
+                .any(|entity| {
+                    monsters.contains(*entity) && !is_ally(&factions, *player_entity, *entity)
+                })
+        })
+    })
+}
+
+/// Takes one step towards the nearest part of the map the player hasn't
+/// seen yet. Exploring stops once monsters show up, there's nowhere left to
+/// go, or the player gets stuck.
+fn explore_step(ecs: &mut World) -> RunState {
+    if monster_in_sight(ecs) {
+        stop_autopilot(ecs, "Monsters nearby — exploration stopped.");
+        return RunState::AwaitingInput;
+    }
+
+    let player_pos = *ecs.fetch::<Point>();
+    let (step, opens_door) = {
+        let map = ecs.fetch::<Map>();
+        let unexplored: Vec<usize> = (0..map.tiles.len())
+            .filter(|idx| !map.revealed_tiles[*idx] && !map.is_wall(*idx))
+            .collect();
+        let explore_map =
+            DijkstraMap::new(map.width, map.height, &unexplored, &*map, EXPLORE_DEPTH);
+        let step = lowest_exit(
+            &explore_map,
+            map.xy_idx(player_pos.x, player_pos.y),
+            &map,
+            true,
+        );
+        (step, step.is_some_and(|idx| map.is_closed_door(idx)))
+    };
+
+    let Some(step) = step else {
+        stop_autopilot(ecs, "There is nothing left to explore.");
+        return RunState::AwaitingInput;
+    };
+
+    let width = ecs.fetch::<Map>().width;
+    let (step_x, step_y) = (step as i32 % width, step as i32 / width);
+    try_move_player(step_x - player_pos.x, step_y - player_pos.y, ecs);
+
+    // Opening a door is progress, bumping into something isn't
+    if !opens_door && *ecs.fetch::<Point>() == player_pos {
+        stop_autopilot(ecs, "You can't find a way on.");
+        return RunState::AwaitingInput;
+    }
+
+    RunState::PlayerTurn
+}
+
+/// Plans a route to the tile under the mouse and takes the first step.
+fn start_travel(ecs: &mut World, (mouse_x, mouse_y): (i32, i32)) -> RunState {
+    if !camera::in_view(mouse_x, mouse_y) {
+        return RunState::AwaitingInput;
+    }
+
+    let origin = camera::origin(ecs);
+    let player_pos = *ecs.fetch::<Point>();
+    let (x, y) = (mouse_x + origin.x, mouse_y + origin.y);
+    if (x, y) == (player_pos.x, player_pos.y) {
+        return RunState::AwaitingInput;
+    }
 
-            VirtualKeyCode::Down | VirtualKeyCode::Numpad2 | VirtualKeyCode::J => {
-                try_move_player(0, 1, &mut gs.ecs)
+    let path = {
+        let map = ecs.fetch::<Map>();
+        if x >= map.width || y >= map.height {
+            return RunState::AwaitingInput;
+        }
+
+        let destination = map.xy_idx(x, y);
+        if !map.revealed_tiles[destination] || map.is_wall(destination) {
+            None
+        } else {
+            let route = a_star_search(map.xy_idx(player_pos.x, player_pos.y), destination, &*map);
+            // The first step is where the player already is
+            route.success.then(|| route.steps[1..].to_vec())
+        }
+    };
+
+    match path {
+        None => {
+            let mut log = ecs.fetch_mut::<GameLog>();
+            log.add(LogCategory::General, "Can't go there.");
+            RunState::AwaitingInput
+        }
+        Some(path) => travel_step(ecs, path, false),
+    }
+}
+
+/// Sets off for the nearest stairs down the player has found, to take them
+/// on arrival.
+fn start_stairs_travel(ecs: &mut World) -> RunState {
+    let player_pos = *ecs.fetch::<Point>();
+    let path = {
+        let map = ecs.fetch::<Map>();
+        let start = map.xy_idx(player_pos.x, player_pos.y);
+        let stairs = (0..map.tiles.len())
+            .filter(|idx| map.revealed_tiles[*idx] && map.tiles[*idx] == TileType::DownStairs)
+            .min_by_key(|idx| {
+                let (x, y) = (*idx as i32 % map.width, *idx as i32 / map.width);
+                (x - player_pos.x).abs() + (y - player_pos.y).abs()
+            });
+        match stairs {
+            None => Err("You haven't found the stairs."),
+            Some(stairs) if stairs == start => Ok(Vec::new()),
+            Some(stairs) => {
+                let route = a_star_search(start, stairs, &*map);
+                if route.success {
+                    Ok(route.steps[1..].to_vec())
+                } else {
+                    Err("You can't find a way to the stairs.")
+                }
             }
+        }
+    };
 
-            // Diagonals
-            VirtualKeyCode::Numpad9 | VirtualKeyCode::Y => try_move_player(1, -1, &mut gs.ecs),
+    match path {
+        Err(why) => {
+            let mut log = ecs.fetch_mut::<GameLog>();
+            log.add(LogCategory::General, why);
+            RunState::AwaitingInput
+        }
+        Ok(path) => travel_step(ecs, path, true),
+    }
+}
+
+/// Takes the next step along a travel route, until the player arrives, sees
+/// a monster, or finds the way blocked. Arriving at the stairs with
+/// `descend` set takes them.
+fn travel_step(ecs: &mut World, mut path: Vec<usize>, descend: bool) -> RunState {
+    if monster_in_sight(ecs) {
+        stop_autopilot(ecs, "Monsters nearby — travel stopped.");
+        return RunState::AwaitingInput;
+    }
+    if path.is_empty() {
+        *ecs.write_resource::<Autopilot>() = Autopilot::Off;
+        if descend && try_next_level(ecs) {
+            return RunState::NextLevel;
+        }
+        return RunState::AwaitingInput;
+    }
 
-            VirtualKeyCode::Numpad7 | VirtualKeyCode::U => try_move_player(-1, -1, &mut gs.ecs),
+    let step = path.remove(0);
+    let player_pos = *ecs.fetch::<Point>();
+    let (opens_door, blocked, width) = {
+        let map = ecs.fetch::<Map>();
+        let opens_door = map.is_closed_door(step);
+        (opens_door, map.blocked[step] && !opens_door, map.width)
+    };
+    if blocked {
+        *ecs.write_resource::<Autopilot>() = Autopilot::Off;
+        return RunState::AwaitingInput;
+    }
+
+    let (step_x, step_y) = (step as i32 % width, step as i32 / width);
+    try_move_player(step_x - player_pos.x, step_y - player_pos.y, ecs);
+    if opens_door {
+        // Still have to walk through it
+        path.insert(0, step);
+    }
 
-            VirtualKeyCode::Numpad3 | VirtualKeyCode::N => try_move_player(1, 1, &mut gs.ecs),
+    *ecs.write_resource::<Autopilot>() = if path.is_empty() && !descend {
+        Autopilot::Off
+    } else {
+        Autopilot::Travel { path, descend }
+    };
+    RunState::PlayerTurn
+}
 
-            VirtualKeyCode::Numpad1 | VirtualKeyCode::B => try_move_player(-1, 1, &mut gs.ecs),
+fn stop_autopilot(ecs: &mut World, reason: &str) {
+    *ecs.write_resource::<Autopilot>() = Autopilot::Off;
+    let mut log = ecs.fetch_mut::<GameLog>();
+    log.add(LogCategory::General, reason);
+}
 
-            _ => {
+/// Waits out one more turn of rest. Resting stops once the player is back to
+/// full health, a monster shows up or hunger sets in. The turns pass as
+/// usual, so hunger and whatever ails the player go on meanwhile.
+fn rest_step(ecs: &mut World) -> RunState {
+    if monster_in_sight(ecs) {
+        stop_autopilot(ecs, "A monster comes into view — you stop resting.");
+        return RunState::AwaitingInput;
+    }
+
+    let player_entity = *ecs.fetch::<Entity>();
+    let healed = ecs
+        .read_storage::<CombatStats>()
+        .get(player_entity)
+        .is_none_or(|stats| stats.hp >= stats.max_hp);
+    if healed {
+        stop_autopilot(ecs, "You feel rested.");
+        return RunState::AwaitingInput;
+    }
+    let hungry = ecs
+        .read_storage::<HungerClock>()
+        .get(player_entity)
+        .is_some_and(|clock| matches!(clock.state, HungerState::Hungry | HungerState::Starving));
+    if hungry {
+        stop_autopilot(ecs, "You're too hungry to rest.");
+        return RunState::AwaitingInput;
+    }
+
+    skip_turn(ecs);
+    RunState::PlayerTurn
+}
+
+/// Passes the turn. Resting with no monsters in sight recovers a hit point.
+fn skip_turn(ecs: &mut World) {
+    if monster_in_sight(ecs) {
+        return;
+    }
+
+    let player_entity = ecs.fetch::<Entity>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    if let Some(stats) = combat_stats.get_mut(*player_entity) {
+        stats.hp = i32::min(stats.hp + 1, stats.max_hp);
+    }
+}
+
+/// Checks whether the player is standing on the stairs down, logging why not
+/// if they aren't.
+pub fn try_next_level(ecs: &mut World) -> bool {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+    if map.tiles[player_idx] == TileType::DownStairs {
+        true
+    } else {
+        let mut log = ecs.fetch_mut::<GameLog>();
+        log.add(LogCategory::General, "There is no way down from here.");
+        false
+    }
+}
+
+/// Checks whether the player is standing on the stairs up, logging why not
+/// if they aren't.
+pub fn try_previous_level(ecs: &mut World) -> bool {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+    if map.tiles[player_idx] == TileType::UpStairs {
+        true
+    } else {
+        let mut log = ecs.fetch_mut::<GameLog>();
+        log.add(LogCategory::General, "There is no way up from here.");
+        false
+    }
+}
+
+pub fn player_input(gs: &mut State, ctx: &mut BTerm) -> RunState {
+    // Any key takes back control
+    let autopilot = (*gs.ecs.fetch::<Autopilot>()).clone();
+    if autopilot != Autopilot::Off {
+        if ctx.key.is_some() {
+            *gs.ecs.write_resource::<Autopilot>() = Autopilot::Off;
+            return RunState::AwaitingInput;
+        }
+        // Stumbling about would throw any route off
+        let confused = gs
+            .ecs
+            .read_storage::<Confusion>()
+            .contains(*gs.ecs.fetch::<Entity>());
+        if confused && autopilot != Autopilot::Rest {
+            stop_autopilot(&mut gs.ecs, "You're too confused to find your way.");
+            return RunState::AwaitingInput;
+        }
+        return match autopilot {
+            Autopilot::Off => RunState::AwaitingInput,
+            Autopilot::Explore => explore_step(&mut gs.ecs),
+            Autopilot::Rest => rest_step(&mut gs.ecs),
+            Autopilot::Travel { path, descend } => travel_step(&mut gs.ecs, path, descend),
+        };
+    }
+
+    if ctx.left_click && ctx.key.is_none() {
+        return start_travel(&mut gs.ecs, ctx.mouse_pos());
+    }
+
+    let Some(key) = ctx.key else {
+        return RunState::AwaitingInput; // Nothing happened
+    };
+    let action = gs.ecs.fetch::<Keybindings>().action(key, ctx.shift);
+    match action {
+        Some(Action::MoveWest) => return try_move_player(-1, 0, &mut gs.ecs),
+        Some(Action::MoveEast) => return try_move_player(1, 0, &mut gs.ecs),
+        Some(Action::MoveNorth) => return try_move_player(0, -1, &mut gs.ecs),
+        Some(Action::MoveSouth) => return try_move_player(0, 1, &mut gs.ecs),
+
+        // Diagonals
+        Some(Action::MoveNorthEast) => return try_move_player(1, -1, &mut gs.ecs),
+        Some(Action::MoveNorthWest) => return try_move_player(-1, -1, &mut gs.ecs),
+        Some(Action::MoveSouthEast) => return try_move_player(1, 1, &mut gs.ecs),
+        Some(Action::MoveSouthWest) => return try_move_player(-1, 1, &mut gs.ecs),
+
+        // Wait a turn
+        Some(Action::Wait) => skip_turn(&mut gs.ecs),
+
+        Some(Action::Explore) => {
+            *gs.ecs.write_resource::<Autopilot>() = Autopilot::Explore;
+            return explore_step(&mut gs.ecs);
+        }
+
+        Some(Action::Rest) => {
+            *gs.ecs.write_resource::<Autopilot>() = Autopilot::Rest;
+            return rest_step(&mut gs.ecs);
+        }
+
+        Some(Action::PickUp) => get_item(&mut gs.ecs),
+
+        Some(Action::Inventory) => return RunState::ShowInventory,
+
+        Some(Action::Throw) => return RunState::ShowThrowItem,
+
+        Some(Action::Drop) => return RunState::ShowDropItem,
+
+        Some(Action::Remove) => return RunState::ShowRemoveItem,
+
+        Some(Action::Log) => return RunState::ShowLog { offset: 0 },
+
+        Some(Action::CharacterSheet) => return RunState::ShowCharacter,
+
+        Some(Action::Quests) => return RunState::ShowQuests,
+
+        Some(Action::Cast) => return RunState::ShowSpells,
+
+        Some(Action::Fire) => return ready_to_fire(&gs.ecs),
+
+        Some(Action::Sneak) => {
+            toggle_sneaking(&mut gs.ecs);
+            return RunState::AwaitingInput;
+        }
+
+        Some(Action::Search) => search(&mut gs.ecs),
+
+        Some(Action::Examine) => {
+            let cursor = *gs.ecs.fetch::<Point>();
+            return RunState::Examine { cursor };
+        }
+
+        Some(Action::Quaff) => {
+            if !quaff_potion(&mut gs.ecs) {
                 return RunState::AwaitingInput;
             }
-        },
+        }
+
+        // Save and return to the main menu
+        Some(Action::SaveAndQuit) => return RunState::SaveGame,
+
+        // Level changes
+        Some(Action::Descend) => {
+            if try_next_level(&mut gs.ecs) {
+                return RunState::NextLevel;
+            }
+            return RunState::AwaitingInput;
+        }
+
+        Some(Action::Ascend) => {
+            if try_previous_level(&mut gs.ecs) {
+                return RunState::PreviousLevel;
+            }
+            return RunState::AwaitingInput;
+        }
+
+        Some(Action::GoToStairs) => return start_stairs_travel(&mut gs.ecs),
+
+        // Only changes what's shown, so no time passes
+        Some(Action::ToggleMinimap) => {
+            let mut ui = gs.ecs.write_resource::<UiState>();
+            ui.show_minimap = !ui.show_minimap;
+            return RunState::AwaitingInput;
+        }
+
+        Some(Action::ToggleWizardMode) => {
+            let mut ui = gs.ecs.write_resource::<UiState>();
+            ui.wizard_mode = !ui.wizard_mode;
+            let mut log = gs.ecs.fetch_mut::<GameLog>();
+            log.add(
+                LogCategory::General,
+                if ui.wizard_mode {
+                    "Wizard mode on: everything is shown."
+                } else {
+                    "Wizard mode off."
+                },
+            );
+            return RunState::AwaitingInput;
+        }
+
+        None => return RunState::AwaitingInput,
     }
 
     RunState::PlayerTurn
@@ -0,0 +1,105 @@
+use crate::event_log_system::{EventQueue, GameEvent};
+use crate::gamelog::{GameLog, LogCategory};
+use crate::map::{Depth, BOSS_DEPTH};
+use crate::Wallet;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+/// What has to happen for a quest to be done.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Objective {
+    /// Get down to this depth, or deeper.
+    ReachDepth(i32),
+    /// See someone by this name dead.
+    Slay(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Quest {
+    pub title: String,
+    pub objective: Objective,
+    /// Gold handed over when it's done.
+    pub reward: i32,
+    pub done: bool,
+}
+
+impl Quest {
+    fn new(title: &str, objective: Objective, reward: i32) -> Quest {
+        Quest {
+            title: title.to_string(),
+            objective,
+            reward,
+            done: false,
+        }
+    }
+}
+
+/// The player's objectives, done or not, main quest first.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Quests {
+    pub quests: Vec<Quest>,
+}
+
+impl Quests {
+    /// What every game starts with: getting down to the Orc Warlord's level,
+    /// and slaying him there.
+    pub fn new() -> Quests {
+        Quests {
+            quests: vec![
+                Quest::new(
+                    &format!("Reach level {}, where the Warlord waits", BOSS_DEPTH),
+                    Objective::ReachDepth(BOSS_DEPTH),
+                    100,
+                ),
+                Quest::new(
+                    "Slay the Orc Warlord",
+                    Objective::Slay("Orc Warlord".to_string()),
+                    250,
+                ),
+            ],
+        }
+    }
+}
+
+/// Marks off quests whose objective has been met, congratulating the player
+/// and paying out their reward. Kills are only seen in the event queue, so it
+/// runs after the dead are cleared away and before the events are reported.
+pub struct QuestSystem {}
+
+impl<'a> System<'a> for QuestSystem {
+    type SystemData = (
+        WriteExpect<'a, Quests>,
+        ReadExpect<'a, Depth>,
+        ReadExpect<'a, EventQueue>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Entity>,
+        WriteStorage<'a, Wallet>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut quests, depth, events, mut log, player_entity, mut wallets) = data;
+
+        for quest in quests.quests.iter_mut().filter(|quest| !quest.done) {
+            quest.done = match &quest.objective {
+                Objective::ReachDepth(target) => depth.0 >= *target,
+                Objective::Slay(name) => events.events.iter().any(|event| {
+                    matches!(event, GameEvent::Death { victim, .. } if victim.name == *name)
+                }),
+            };
+            if !quest.done {
+                continue;
+            }
+
+            if let Some(wallet) = wallets.get_mut(*player_entity) {
+                wallet.gold += quest.reward;
+            }
+            log.add(
+                LogCategory::General,
+                format!(
+                    "Quest complete: {}! You are rewarded {} gold.",
+                    quest.title, quest.reward
+                ),
+            );
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use bracket_lib::prelude::RandomNumberGenerator;
+
+/// Name rolled when nothing should be spawned at all.
+pub const NOTHING: &str = "None";
+
+struct RandomEntry {
+    name: String,
+    weight: i32,
+}
+
+/// A list of names to pick from at random, each one coming up in proportion
+/// to its weight compared to the others.
+#[derive(Default)]
+pub struct RandomTable {
+    entries: Vec<RandomEntry>,
+    total_weight: i32,
+}
+
+impl RandomTable {
+    pub fn new() -> RandomTable {
+        RandomTable::default()
+    }
+
+    /// Adds a name to the table. Entries with no weight are left out, so
+    /// depth-scaled weights can simply start at zero.
+    pub fn add<S: ToString>(mut self, name: S, weight: i32) -> RandomTable {
+        if weight > 0 {
+            self.total_weight += weight;
+            self.entries.push(RandomEntry {
+                name: name.to_string(),
+                weight,
+            });
+        }
+        self
+    }
+
+    /// Picks a name. An empty table always gives `NOTHING`.
+    pub fn roll(&self, rng: &mut RandomNumberGenerator) -> String {
+        if self.total_weight == 0 {
+            return NOTHING.to_string();
+        }
+
+        let mut roll = rng.roll_dice(1, self.total_weight) - 1;
+        for entry in self.entries.iter() {
+            if roll < entry.weight {
+                return entry.name.clone();
+            }
+            roll -= entry.weight;
+        }
+
+        NOTHING.to_string()
+    }
+}
@@ -0,0 +1,231 @@
+use super::{
+    Ammo, CombatStats, Confusion, DamageType, DefenseBonus, Equipped, InInventory, Map, Name,
+    Player, Position, Projectile, RangedAttacker, RangedWeapon, Resistances, Sneaking,
+    SufferDamage, WantsToShoot,
+};
+use crate::damage_system::after_resistance;
+use crate::gamelog::{refer_to, GameLog, LogCategory};
+use crate::particle_system::{line_path, ParticleBuilder};
+use bracket_lib::prelude::{to_cp437, Point, BLACK, ORANGE, RGB, RGBA, YELLOW};
+use specs::prelude::*;
+
+/// How many shots of the `kind` of ammo `owner` has in their pack.
+pub fn ammo_left(ecs: &World, owner: Entity, kind: &str) -> i32 {
+    let backpack = ecs.read_storage::<InInventory>();
+    let ammo = ecs.read_storage::<Ammo>();
+    (&backpack, &ammo)
+        .join()
+        .filter(|(pack, ammo)| pack.owner == owner && ammo.kind == kind)
+        .map(|(_pack, ammo)| ammo.count)
+        .sum()
+}
+
+/// The ranged weapon `owner` has equipped, if any.
+pub fn equipped_ranged_weapon(ecs: &World, owner: Entity) -> Option<(Entity, RangedWeapon)> {
+    let entities = ecs.entities();
+    let equipped = ecs.read_storage::<Equipped>();
+    let weapons = ecs.read_storage::<RangedWeapon>();
+    (&entities, &equipped, &weapons)
+        .join()
+        .find(|(_item, equipped_by, _weapon)| equipped_by.owner == owner)
+        .map(|(item, _equipped_by, weapon)| (item, weapon.clone()))
+}
+
+/// Resolves shots: monsters that shoot on their own, some of them leaving
+/// their target confused, and whoever has a bow equipped, which uses up one
+/// of their arrows per shot.
+pub struct RangedCombatSystem {}
+
+impl<'a> System<'a> for RangedCombatSystem {
+    type SystemData = (
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, WantsToShoot>,
+        ReadStorage<'a, RangedAttacker>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, CombatStats>,
+        WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, DefenseBonus>,
+        ReadStorage<'a, Position>,
+        WriteExpect<'a, ParticleBuilder>,
+        ReadStorage<'a, Player>,
+        ReadExpect<'a, Map>,
+        Entities<'a>,
+        ReadStorage<'a, Projectile>,
+        ReadStorage<'a, RangedWeapon>,
+        WriteStorage<'a, Ammo>,
+        ReadStorage<'a, InInventory>,
+        WriteStorage<'a, Sneaking>,
+        WriteStorage<'a, Confusion>,
+        ReadStorage<'a, Resistances>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut log,
+            mut wants_shoot,
+            ranged_attackers,
+            names,
+            combat_stats,
+            mut inflict_damage,
+            equipped,
+            defense_bonuses,
+            positions,
+            mut particle_builder,
+            players,
+            map,
+            entities,
+            projectiles,
+            ranged_weapons,
+            mut ammo,
+            backpack,
+            mut sneaking,
+            mut confused,
+            resistances,
+        ) = data;
+
+        for (entity, wants_shoot, name, stats, pos) in
+            (&entities, &wants_shoot, &names, &combat_stats, &positions).join()
+        {
+            if stats.hp <= 0 {
+                continue;
+            }
+            let shooter_is_player = players.contains(entity);
+
+            // Monsters shoot by nature, everyone else needs a weapon and
+            // something to put in it
+            let weapon = (&entities, &equipped, &ranged_weapons)
+                .join()
+                .find(|(_item, equipped_by, _weapon)| equipped_by.owner == entity)
+                .map(|(item, _equipped_by, weapon)| (item, weapon));
+            let (damage, projectile, confusion) = match (ranged_attackers.get(entity), weapon) {
+                (Some(attacker), _) => {
+                    (attacker.damage, projectiles.get(entity), attacker.confusion)
+                }
+                (None, Some((item, weapon))) => {
+                    let quiver =
+                        (&entities, &backpack, &mut ammo)
+                            .join()
+                            .find(|(_stack, pack, ammo)| {
+                                pack.owner == entity && ammo.kind == weapon.ammo && ammo.count > 0
+                            });
+                    let Some((stack, _pack, quiver)) = quiver else {
+                        if shooter_is_player {
+                            log.add(LogCategory::Combat, format!("Out of {}s!", weapon.ammo));
+                        }
+                        continue;
+                    };
+                    quiver.count -= 1;
+                    if quiver.count == 0 {
+                        entities.delete(stack).expect("Delete failed");
+                    }
+                    (weapon.damage, projectiles.get(item), None)
+                }
+                (None, None) => continue,
+            };
+            sneaking.remove(entity);
+
+            let Some(target_stats) = combat_stats.get(wants_shoot.target) else {
+                continue;
+            };
+            if target_stats.hp <= 0 {
+                continue;
+            }
+            let target_name = names.get(wants_shoot.target).unwrap();
+            let target_is_player = players.contains(wants_shoot.target);
+            // Shots the player can't see go unreported
+            let seen = shooter_is_player
+                || target_is_player
+                || map.visible_tiles[map.xy_idx(pos.x, pos.y)];
+
+            // Send the shot flying from the shooter to the target
+            if let Some(target_pos) = positions.get(wants_shoot.target) {
+                let path = line_path(
+                    Point::new(pos.x, pos.y),
+                    Point::new(target_pos.x, target_pos.y),
+                );
+                match projectile {
+                    Some(projectile) => {
+                        particle_builder.projectile(&path, projectile.fg, projectile.glyph)
+                    }
+                    None => particle_builder.projectile(&path, RGB::named(YELLOW), to_cp437('∙')),
+                }
+            }
+
+            let defensive_bonus: i32 = (&equipped, &defense_bonuses)
+                .join()
+                .filter(|(equipped_by, _bonus)| equipped_by.owner == wants_shoot.target)
+                .map(|(_equipped_by, bonus)| bonus.defense)
+                .sum();
+            let damage = i32::max(0, damage - (target_stats.defense + defensive_bonus));
+
+            if damage == 0 {
+                if seen {
+                    log.add(
+                        LogCategory::Combat,
+                        format!(
+                            "{} {} {}, but {} no harm.",
+                            refer_to(name, shooter_is_player, true),
+                            if shooter_is_player { "shoot" } else { "shoots" },
+                            refer_to(target_name, target_is_player, false),
+                            if shooter_is_player { "do" } else { "does" }
+                        ),
+                    );
+                }
+            } else {
+                if seen {
+                    log.add(
+                        LogCategory::Combat,
+                        format!(
+                            "{} {} {}, for {} hp.",
+                            refer_to(name, shooter_is_player, true),
+                            if shooter_is_player { "shoot" } else { "shoots" },
+                            refer_to(target_name, target_is_player, false),
+                            after_resistance(
+                                damage,
+                                DamageType::Physical,
+                                resistances.get(wants_shoot.target)
+                            )
+                        ),
+                    );
+                }
+                SufferDamage::new_damage(
+                    &mut inflict_damage,
+                    wants_shoot.target,
+                    damage,
+                    DamageType::Physical,
+                    shooter_is_player,
+                );
+                if let Some(target_pos) = positions.get(wants_shoot.target) {
+                    particle_builder.request(
+                        target_pos.x,
+                        target_pos.y,
+                        RGB::named(ORANGE),
+                        RGBA::named(BLACK),
+                        to_cp437('‼'),
+                        200.0,
+                    );
+                }
+            }
+
+            // Dazes even when armour takes the sting out of it
+            if let Some(turns) = confusion {
+                confused
+                    .insert(wants_shoot.target, Confusion { turns })
+                    .expect("Unable to insert status");
+                if seen {
+                    log.add(
+                        LogCategory::Combat,
+                        format!(
+                            "{} {} confused!",
+                            refer_to(target_name, target_is_player, true),
+                            if target_is_player { "are" } else { "is" }
+                        ),
+                    );
+                }
+            }
+        }
+
+        wants_shoot.clear();
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::{DamageType, Size};
+
+/// Monster and faction definitions, compiled into the binary so the game
+/// doesn't depend on where it's started from.
+const SPAWNS: &str = include_str!("../raws/spawns.json");
+
+static RAWS: OnceLock<Raws> = OnceLock::new();
+
+#[derive(Deserialize, Debug)]
+pub struct Raws {
+    pub monsters: Vec<RawMonster>,
+    pub factions: Vec<RawFaction>,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Deserialize)]
+pub enum Reaction {
+    Hostile,
+    Neutral,
+    Ally,
+}
+
+/// How one faction treats the others. A `"Default"` entry covers every faction
+/// not listed by name.
+#[derive(Deserialize, Debug)]
+pub struct RawFaction {
+    pub name: String,
+    pub reactions: HashMap<String, Reaction>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RawMonster {
+    pub name: String,
+    pub glyph: char,
+    /// Hex color, like `#FF0000`.
+    pub fg: String,
+    pub stats: RawStats,
+    pub blocks_tile: bool,
+    pub vision_range: i32,
+    pub faction: String,
+    /// Initiative gained per player turn. The player's own pace is 2.
+    pub speed: i32,
+    /// Fraction of its hit points below which the monster runs away.
+    pub flee_threshold: Option<f32>,
+    pub ranged: Option<RawRanged>,
+    /// Damage types it takes half of, and those it takes double of.
+    pub resists: Option<Vec<DamageType>>,
+    pub vulnerable: Option<Vec<DamageType>>,
+    pub regeneration: Option<RawRegeneration>,
+    /// How many tiles its blows throw the target back.
+    pub knockback: Option<i32>,
+    /// Tiles it takes up, for monsters too big for just the one.
+    pub size: Option<Size>,
+    pub boss: Option<RawBoss>,
+    /// How many turn up together, for monsters that run in packs.
+    pub pack: Option<RawPack>,
+    /// Whether it can open doors. Anything that doesn't say can; animals
+    /// and the like have to go around.
+    pub opens_doors: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RawStats {
+    pub max_hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    pub attack: i32,
+    pub dodge: i32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RawRanged {
+    pub range: i32,
+    pub damage: i32,
+    pub projectile: Option<RawProjectile>,
+    /// Turns a hit leaves the target confused for.
+    pub confusion: Option<i32>,
+}
+
+/// How a ranged attacker's shots look on their way.
+#[derive(Deserialize, Debug)]
+pub struct RawProjectile {
+    pub glyph: char,
+    /// Hex color, like `#FF0000`.
+    pub fg: String,
+}
+
+/// How fast a monster's wounds close: `amount` hp every `interval` turns.
+#[derive(Deserialize, Debug)]
+pub struct RawRegeneration {
+    pub amount: i32,
+    pub interval: i32,
+}
+
+/// The smallest and largest pack a monster turns up in.
+#[derive(Deserialize, Debug)]
+pub struct RawPack {
+    pub min: i32,
+    pub max: i32,
+}
+
+/// What makes a monster a boss: who it calls for help and what it always
+/// drops.
+#[derive(Deserialize, Debug)]
+pub struct RawBoss {
+    pub minion: String,
+    pub loot: String,
+}
+
+impl Raws {
+    pub fn monster(&self, name: &str) -> Option<&RawMonster> {
+        self.monsters.iter().find(|monster| monster.name == name)
+    }
+
+    /// How members of faction `mine` react to members of `theirs`. Factions
+    /// stick together, and anything the table doesn't mention is left alone.
+    pub fn reaction(&self, mine: &str, theirs: &str) -> Reaction {
+        if mine == theirs {
+            return Reaction::Ally;
+        }
+        let Some(faction) = self.factions.iter().find(|faction| faction.name == mine) else {
+            return Reaction::Neutral;
+        };
+        faction
+            .reactions
+            .get(theirs)
+            .or_else(|| faction.reactions.get("Default"))
+            .copied()
+            .unwrap_or(Reaction::Neutral)
+    }
+}
+
+/// The embedded raws, parsed the first time they're needed.
+pub fn raws() -> &'static Raws {
+    RAWS.get_or_init(|| serde_json::from_str(SPAWNS).expect("Unable to parse spawns.json"))
+}
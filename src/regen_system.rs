@@ -0,0 +1,42 @@
+use crate::{CombatStats, Mana, Regeneration, RunState, StatusEffect};
+use specs::prelude::*;
+
+/// Heals whoever regenerates, a little every few player turns, up to their
+/// max hp. Poison and fire keep wounds from closing for as long as they last.
+/// Mana comes back the same way, a point each turn.
+pub struct RegenSystem {}
+
+impl<'a> System<'a> for RegenSystem {
+    type SystemData = (
+        WriteStorage<'a, Regeneration>,
+        WriteStorage<'a, CombatStats>,
+        ReadStorage<'a, StatusEffect>,
+        ReadExpect<'a, RunState>,
+        WriteStorage<'a, Mana>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut regenerators, mut combat_stats, effects, runstate, mut manas) = data;
+
+        if *runstate != RunState::PlayerTurn {
+            return;
+        }
+
+        for (regen, stats, _effect) in (&mut regenerators, &mut combat_stats, !&effects).join() {
+            if stats.hp <= 0 || stats.hp >= stats.max_hp {
+                regen.counter = 0;
+                continue;
+            }
+
+            regen.counter += 1;
+            if regen.counter >= regen.interval {
+                regen.counter = 0;
+                stats.hp = i32::min(stats.max_hp, stats.hp + regen.amount);
+            }
+        }
+
+        for mana in (&mut manas).join() {
+            mana.current = i32::min(mana.max, mana.current + 1);
+        }
+    }
+}
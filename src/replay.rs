@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+use bracket_lib::prelude::BTerm;
+use serde::{Deserialize, Serialize};
+
+use crate::difficulty::DifficultyLevel;
+use crate::keybindings::key_named;
+use crate::map::MapGenerator;
+
+/// Where every new game's input is written, ready to be replayed with
+/// `--replay inputs.jsonl`. Each game starts it afresh.
+pub const RECORDING_FILE: &str = "inputs.jsonl";
+
+/// The first line of a recording: what the game was started with.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct RecordingHeader {
+    pub seed: u64,
+    pub generator: MapGenerator,
+    pub difficulty: DifficultyLevel,
+}
+
+/// A key press or mouse click, and the player turn it came in on.
+#[derive(Serialize, Deserialize, Clone)]
+struct InputEvent {
+    turn: u32,
+    /// The `VirtualKeyCode` name of the key, if one was pressed.
+    key: Option<String>,
+    shift: bool,
+    /// Where the mouse was clicked, in pixels, if it was.
+    click: Option<(i32, i32)>,
+}
+
+/// Writes down the player's input as they play, one line per key press or
+/// click, so the game can be played back exactly.
+pub struct InputLog {
+    /// Player turns taken so far.
+    pub turn: u32,
+    file: Option<File>,
+}
+
+impl InputLog {
+    /// Starts a new recording for a game begun with `header`. If the file
+    /// can't be written the game is played without one.
+    pub fn start(header: RecordingHeader) -> InputLog {
+        let file = File::create(RECORDING_FILE).ok().and_then(|mut file| {
+            let line = serde_json::to_string(&header).ok()?;
+            writeln!(file, "{}", line).ok()?;
+            Some(file)
+        });
+        InputLog { turn: 0, file }
+    }
+
+    /// Counts turns without writing anything, for games that can't be
+    /// replayed, like those continued from a save.
+    pub fn off() -> InputLog {
+        InputLog {
+            turn: 0,
+            file: None,
+        }
+    }
+
+    /// Appends this tick's key press or click, if there was one.
+    pub fn record(&mut self, ctx: &BTerm) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        if ctx.key.is_none() && !ctx.left_click {
+            return;
+        }
+        let event = InputEvent {
+            turn: self.turn,
+            key: ctx.key.map(|key| format!("{:?}", key)),
+            shift: ctx.shift,
+            click: ctx.left_click.then_some(ctx.mouse_pos),
+        };
+        if let Ok(line) = serde_json::to_string(&event) {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+}
+
+/// A recording being played back in place of the player.
+pub struct Replay {
+    pub header: RecordingHeader,
+    events: VecDeque<InputEvent>,
+}
+
+impl Replay {
+    /// Reads the recording at `path`, or gives `None` if there isn't a
+    /// readable one there.
+    pub fn load(path: &str) -> Option<Replay> {
+        let mut lines = BufReader::new(File::open(path).ok()?).lines();
+        let header = serde_json::from_str(&lines.next()?.ok()?).ok()?;
+        let events = lines
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect();
+        Some(Replay { header, events })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Swaps whatever input came in this tick for the next recorded one, or
+    /// for none at all if that isn't due until a later turn.
+    pub fn feed(&mut self, turn: u32, ctx: &mut BTerm) {
+        ctx.key = None;
+        ctx.shift = false;
+        ctx.left_click = false;
+
+        if self.events.front().is_none_or(|event| event.turn > turn) {
+            return;
+        }
+        let Some(event) = self.events.pop_front() else {
+            return;
+        };
+        ctx.key = event
+            .key
+            .as_deref()
+            .and_then(key_named)
+            .map(|(key, _shift)| key);
+        ctx.shift = event.shift;
+        if let Some(pos) = event.click {
+            ctx.left_click = true;
+            ctx.mouse_pos = pos;
+        }
+    }
+}
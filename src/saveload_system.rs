@@ -0,0 +1,318 @@
+use std::convert::Infallible;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+
+use specs::prelude::*;
+use specs::saveload::{
+    DeserializeComponents, MarkedBuilder, SerializeComponents, SimpleMarker, SimpleMarkerAllocator,
+};
+
+use crate::components::*;
+use crate::difficulty::Difficulty;
+use crate::gamelog::GameLog;
+use crate::inventory_system::KnownItems;
+use crate::map::{Depth, LevelStore, Map};
+use crate::quest_system::Quests;
+use crate::Point;
+
+const SAVE_FILE: &str = "./savegame.json";
+
+macro_rules! serialize_individually {
+    ($ecs:expr, $ser:expr, $data:expr, $( $type:ty),*) => {
+        $(
+        SerializeComponents::<Infallible, SimpleMarker<SerializeMe>>::serialize(
+            &( $ecs.read_storage::<$type>(), ),
+            &$data.0,
+            &$data.1,
+            &mut $ser,
+        )
+        .unwrap();
+        )*
+    };
+}
+
+macro_rules! deserialize_individually {
+    ($ecs:expr, $de:expr, $data:expr, $( $type:ty),*) => {
+        $(
+        DeserializeComponents::<Infallible, _>::deserialize(
+            &mut ( &mut $ecs.write_storage::<$type>(), ),
+            &$data.0, // entities
+            &mut $data.1, // marker
+            &mut $data.2, // allocater
+            &mut $de,
+        )
+        .unwrap();
+        )*
+    };
+}
+
+/// Writes every marked entity, plus the map, depth, log, stored levels, known
+/// items, quests and difficulty, to the save file.
+pub fn save_game(ecs: &mut World) {
+    // The resources ride along on a temporary entity
+    let map_copy = ecs.get_mut::<Map>().unwrap().clone();
+    let depth = ecs.fetch::<Depth>().0;
+    let log_copy = (*ecs.fetch::<GameLog>()).clone();
+    let levels_copy = (*ecs.fetch::<LevelStore>()).clone();
+    let known_copy = (*ecs.fetch::<KnownItems>()).clone();
+    let quests_copy = (*ecs.fetch::<Quests>()).clone();
+    let difficulty = ecs.fetch::<Difficulty>().level;
+    let save_helper = ecs
+        .create_entity()
+        .with(SerializationHelper {
+            map: map_copy,
+            depth,
+            log: log_copy,
+            levels: levels_copy,
+            known_items: known_copy,
+            quests: quests_copy,
+            difficulty,
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build();
+
+    {
+        let data = (
+            ecs.entities(),
+            ecs.read_storage::<SimpleMarker<SerializeMe>>(),
+        );
+
+        let writer = File::create(SAVE_FILE).unwrap();
+        let mut serializer = serde_json::Serializer::new(writer);
+        serialize_individually!(
+            ecs,
+            serializer,
+            data,
+            Position,
+            Renderable,
+            Player,
+            Monster,
+            Name,
+            FieldOfView,
+            BlocksTile,
+            CombatStats,
+            WantsToMelee,
+            SufferDamage,
+            KilledByPlayer,
+            Item,
+            InInventory,
+            WantsToPickupItem,
+            ProvidesHealing,
+            WantsToUseItem,
+            Consumable,
+            Equippable,
+            Equipped,
+            MeleePowerBonus,
+            DefenseBonus,
+            Ranged,
+            InflictsDamage,
+            AreaOfEffect,
+            Confusion,
+            Experience,
+            HungerClock,
+            ProvidesFood,
+            DoorUser,
+            Wading,
+            StatusEffect,
+            InflictsStatus,
+            CanFlee,
+            RangedAttacker,
+            WantsToShoot,
+            Hidden,
+            EntryTrigger,
+            SingleActivation,
+            EntityMoved,
+            Initiative,
+            MyTurn,
+            LightSource,
+            AiState,
+            Faction,
+            Gold,
+            Wallet,
+            Price,
+            Vendor,
+            Key,
+            ProvidesMapping,
+            ProvidesTeleport,
+            Boss,
+            ProvidesSummon,
+            Temporary,
+            Projectile,
+            Corpse,
+            WantsToThrowItem,
+            Weight,
+            WantsToDropItem,
+            WantsToRemoveItem,
+            Cursed,
+            Unidentified,
+            ProvidesIdentify,
+            CritBonus,
+            Knockback,
+            Resistances,
+            Regeneration,
+            Mana,
+            Spell,
+            Cooldowns,
+            RangedWeapon,
+            Ammo,
+            Stackable,
+            Sneaking,
+            Size,
+            OtherLevelPosition,
+            PackMember,
+            Potion,
+            Dialogue,
+            SerializationHelper
+        );
+    }
+
+    ecs.delete_entity(save_helper).expect("Crash on cleanup");
+}
+
+pub fn does_save_exist() -> bool {
+    Path::new(SAVE_FILE).exists()
+}
+
+/// Replaces the whole world with the contents of the save file. The `Map`,
+/// `Depth`, `GameLog`, `LevelStore`, `KnownItems`, `Quests`, `Difficulty`,
+/// `Point` and player `Entity` resources must already exist.
+pub fn load_game(ecs: &mut World) {
+    {
+        let to_delete: Vec<Entity> = ecs.entities().join().collect();
+        for del in to_delete.iter() {
+            ecs.delete_entity(*del).expect("Deletion failed");
+        }
+    }
+
+    let data = fs::read_to_string(SAVE_FILE).unwrap();
+    let mut de = serde_json::Deserializer::from_str(&data);
+
+    {
+        let mut d = (
+            &mut ecs.entities(),
+            &mut ecs.write_storage::<SimpleMarker<SerializeMe>>(),
+            &mut ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>(),
+        );
+
+        deserialize_individually!(
+            ecs,
+            de,
+            d,
+            Position,
+            Renderable,
+            Player,
+            Monster,
+            Name,
+            FieldOfView,
+            BlocksTile,
+            CombatStats,
+            WantsToMelee,
+            SufferDamage,
+            KilledByPlayer,
+            Item,
+            InInventory,
+            WantsToPickupItem,
+            ProvidesHealing,
+            WantsToUseItem,
+            Consumable,
+            Equippable,
+            Equipped,
+            MeleePowerBonus,
+            DefenseBonus,
+            Ranged,
+            InflictsDamage,
+            AreaOfEffect,
+            Confusion,
+            Experience,
+            HungerClock,
+            ProvidesFood,
+            DoorUser,
+            Wading,
+            StatusEffect,
+            InflictsStatus,
+            CanFlee,
+            RangedAttacker,
+            WantsToShoot,
+            Hidden,
+            EntryTrigger,
+            SingleActivation,
+            EntityMoved,
+            Initiative,
+            MyTurn,
+            LightSource,
+            AiState,
+            Faction,
+            Gold,
+            Wallet,
+            Price,
+            Vendor,
+            Key,
+            ProvidesMapping,
+            ProvidesTeleport,
+            Boss,
+            ProvidesSummon,
+            Temporary,
+            Projectile,
+            Corpse,
+            WantsToThrowItem,
+            Weight,
+            WantsToDropItem,
+            WantsToRemoveItem,
+            Cursed,
+            Unidentified,
+            ProvidesIdentify,
+            CritBonus,
+            Knockback,
+            Resistances,
+            Regeneration,
+            Mana,
+            Spell,
+            Cooldowns,
+            RangedWeapon,
+            Ammo,
+            Stackable,
+            Sneaking,
+            Size,
+            OtherLevelPosition,
+            PackMember,
+            Potion,
+            Dialogue,
+            SerializationHelper
+        );
+    }
+
+    let mut delete_me: Option<Entity> = None;
+    {
+        let entities = ecs.entities();
+        let helper = ecs.read_storage::<SerializationHelper>();
+        for (entity, h) in (&entities, &helper).join() {
+            let mut worldmap = ecs.write_resource::<Map>();
+            *worldmap = h.map.clone();
+            worldmap.tile_content = vec![Vec::new(); (worldmap.width * worldmap.height) as usize];
+            *ecs.write_resource::<Depth>() = Depth(h.depth);
+            *ecs.write_resource::<GameLog>() = h.log.clone();
+            *ecs.write_resource::<LevelStore>() = h.levels.clone();
+            *ecs.write_resource::<KnownItems>() = h.known_items.clone();
+            *ecs.write_resource::<Quests>() = h.quests.clone();
+            *ecs.write_resource::<Difficulty>() = Difficulty::new(h.difficulty);
+            delete_me = Some(entity);
+        }
+
+        let players = ecs.read_storage::<Player>();
+        let positions = ecs.read_storage::<Position>();
+        for (entity, _player, pos) in (&entities, &players, &positions).join() {
+            *ecs.write_resource::<Point>() = Point::new(pos.x, pos.y);
+            *ecs.write_resource::<Entity>() = entity;
+        }
+    }
+    ecs.delete_entity(delete_me.unwrap())
+        .expect("Unable to delete helper");
+}
+
+/// Saves are single-use: the file goes away once it has been loaded.
+pub fn delete_save() {
+    if does_save_exist() {
+        fs::remove_file(SAVE_FILE).expect("Unable to delete file");
+    }
+}
@@ -0,0 +1,86 @@
+use bracket_lib::prelude::{to_cp437, BTerm, RandomNumberGenerator, RGBA};
+use specs::prelude::*;
+
+use crate::camera::{VIEW_HEIGHT, VIEW_WIDTH};
+
+/// How long the screen shakes after the player is hit.
+const SHAKE_MS: f32 = 250.0;
+
+/// Furthest the screen is thrown about, in tiles, right after a hit.
+const SHAKE_STRENGTH: f32 = 0.5;
+
+/// How quickly the red flash fades, in intensity per millisecond.
+const FLASH_FADE_PER_MS: f32 = 0.004;
+
+/// The screen shaking and flashing red when the player gets hurt. It only
+/// ever changes what's drawn: it winds down with the frame time rather than
+/// with turns, and has its own random numbers so the dungeon's stay the same.
+pub struct ScreenEffects {
+    pub shake_ms: f32,
+    pub flash: f32,
+    rng: RandomNumberGenerator,
+}
+
+impl ScreenEffects {
+    pub fn new() -> ScreenEffects {
+        ScreenEffects {
+            shake_ms: 0.0,
+            flash: 0.0,
+            rng: RandomNumberGenerator::new(),
+        }
+    }
+
+    /// Starts the shake over and makes the flash brighter the harder the hit.
+    pub fn player_hurt(&mut self, damage: i32) {
+        self.shake_ms = SHAKE_MS;
+        self.flash = f32::min(1.0, self.flash + 0.3 + damage as f32 / 20.0);
+    }
+
+    fn wind_down(&mut self, frame_time_ms: f32) {
+        self.shake_ms = f32::max(0.0, self.shake_ms - frame_time_ms);
+        self.flash = f32::max(0.0, self.flash - frame_time_ms * FLASH_FADE_PER_MS);
+    }
+
+    /// How far to move the screen this frame, settling down as the shake
+    /// runs out.
+    fn shake_offset(&mut self) -> (f32, f32) {
+        if self.shake_ms <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let strength = SHAKE_STRENGTH * self.shake_ms / SHAKE_MS;
+        (
+            (self.rng.rand::<f32>() * 2.0 - 1.0) * strength,
+            (self.rng.rand::<f32>() * 2.0 - 1.0) * strength,
+        )
+    }
+}
+
+/// Shakes the map and sprite layers and tints the edge of the view red while
+/// the effects last. Leaves console 1 active.
+pub fn draw_screen_effects(ecs: &World, ctx: &mut BTerm) {
+    let mut effects = ecs.write_resource::<ScreenEffects>();
+    effects.wind_down(ctx.frame_time_ms);
+
+    let (offset_x, offset_y) = effects.shake_offset();
+    ctx.set_active_console(0);
+    ctx.set_offset(offset_x, offset_y);
+    ctx.set_active_console(1);
+    ctx.set_offset(offset_x, offset_y);
+
+    ctx.set_active_console(2);
+    ctx.cls();
+    if effects.flash > 0.0 {
+        let tint = RGBA::from_f32(1.0, 0.0, 0.0, effects.flash * 0.6);
+        for x in 0..VIEW_WIDTH {
+            for y in [0, VIEW_HEIGHT - 1] {
+                ctx.set(x, y, tint, RGBA::new(), to_cp437('▒'));
+            }
+        }
+        for y in 1..VIEW_HEIGHT - 1 {
+            for x in [0, VIEW_WIDTH - 1] {
+                ctx.set(x, y, tint, RGBA::new(), to_cp437('▒'));
+            }
+        }
+    }
+    ctx.set_active_console(1);
+}
@@ -1,22 +1,58 @@
+use std::collections::HashMap;
+
+use crate::difficulty::Difficulty;
+use crate::hunger_system::HUNGER_STATE_DURATION;
+use crate::inventory_system::put_in_pack;
+use crate::map::{footprint, Depth, Map, TileType};
+use crate::monster_ai_system::HUNT_TURNS;
+use crate::random_table::{RandomTable, NOTHING};
+use crate::raws::{raws, Raws};
+use crate::rect::Rect;
 use crate::{
-    BlocksTile, CombatStats, FieldOfView, Monster, Name, Player, Position, Renderable, FLOOR_COLOR,
-    TRANSPARENT_COLOR,
+    AiMode, AiState, Ammo, AreaOfEffect, BlocksTile, Boss, CanFlee, CombatStats, Confusion,
+    Consumable, Cooldowns, Corpse, CritBonus, Cursed, DamageType, DefenseBonus, Dialogue, DoorUser,
+    EffectKind, EntryTrigger, EquipmentSlot, Equippable, Experience, Faction, FieldOfView, Gold,
+    Hidden, HungerClock, HungerState, InflictsDamage, InflictsStatus, Initiative, Item, Key,
+    Knockback, LightSource, Mana, MeleePowerBonus, Monster, Name, PackMember, Player, Position,
+    Potion, Price, Projectile, ProvidesFood, ProvidesHealing, ProvidesIdentify, ProvidesMapping,
+    ProvidesSummon, ProvidesTeleport, Ranged, RangedAttacker, RangedWeapon, Regeneration,
+    Renderable, Resistances, SerializeMe, SingleActivation, Spell, Stackable, StatusEffect,
+    Temporary, Unidentified, Vendor, Wallet, Weight, FLOOR_COLOR, TRANSPARENT_COLOR,
+};
+use bracket_lib::prelude::{
+    to_cp437, Point, RandomNumberGenerator, BROWN1, BURLYWOOD, CYAN, GOLD, GREEN, LIGHT_BLUE,
+    LIGHT_GRAY, MAGENTA, ORANGE, PINK, RED, RGB, WHITE, YELLOW,
 };
-use bracket_lib::prelude::{to_cp437, FontCharType, RandomNumberGenerator, RED, RGB, YELLOW};
 use specs::prelude::*;
+use specs::saveload::{MarkedBuilder, SimpleMarker};
+
+/// What potions and scrolls go by until the player knows what they are
+const UNIDENTIFIED_POTION: &str = "unidentified potion";
+const UNIDENTIFIED_SCROLL: &str = "unidentified scroll";
 
 /// Spawns the player and returns his/her entity object.
 pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
-    ecs.create_entity()
+    let player = ecs
+        .create_entity()
         .with(Player {})
         .with(Name {
             name: "Player".to_string(),
         })
+        .with(Faction {
+            name: "Player".to_string(),
+        })
         .with(CombatStats {
             max_hp: 30,
             hp: 30,
             defense: 2,
             power: 5,
+            attack: 3,
+            dodge: 2,
+        })
+        .with(Regeneration {
+            amount: 1,
+            interval: 10,
+            counter: 0,
         })
         .with(Position {
             x: player_x,
@@ -26,59 +62,1216 @@ pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
             glyph: to_cp437('@'),
             fg: RGB::named(YELLOW),
             bg: TRANSPARENT_COLOR,
+            render_order: 0,
         })
         .with(FieldOfView {
             visible_tiles: vec![],
             range: 8,
             dirty: true,
         })
+        .with(Experience { level: 1, xp: 0 })
+        .with(LightSource {
+            range: 6,
+            color: RGB::from_f32(0.6, 0.55, 0.4),
+        })
+        .with(HungerClock {
+            state: HungerState::WellFed,
+            duration: HUNGER_STATE_DURATION,
+        })
+        .with(Wallet { gold: 0 })
+        .with(Mana {
+            current: 12,
+            max: 12,
+        })
+        .with(Cooldowns {
+            remaining: HashMap::new(),
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build();
+
+    frost_bolt_spell(ecs, player);
+    daze_spell(ecs, player);
+    player
+}
+
+/// A bolt of cold at something up to six tiles away.
+pub fn frost_bolt_spell(ecs: &mut World, owner: Entity) -> Entity {
+    ecs.create_entity()
+        .with(Name {
+            name: "Frost Bolt".to_string(),
+        })
+        .with(Spell {
+            owner,
+            mana_cost: 6,
+            cooldown: 2,
+        })
+        .with(Ranged { range: 6 })
+        .with(InflictsDamage {
+            damage: 7,
+            kind: DamageType::Cold,
+        })
+        .with(Projectile {
+            glyph: to_cp437('*'),
+            fg: RGB::named(LIGHT_BLUE),
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+/// Leaves something nearby confused for a few turns.
+pub fn daze_spell(ecs: &mut World, owner: Entity) -> Entity {
+    ecs.create_entity()
+        .with(Name {
+            name: "Daze".to_string(),
+        })
+        .with(Spell {
+            owner,
+            mana_cost: 4,
+            cooldown: 8,
+        })
+        .with(Ranged { range: 5 })
+        .with(Confusion { turns: 3 })
+        .marked::<SimpleMarker<SerializeMe>>()
         .build()
 }
 
-pub fn random_monster(ecs: &mut World, x: i32, y: i32) {
-    let roll: i32;
+/// At most this many spawn points per room on the first level; deeper levels
+/// get one more each.
+const MAX_SPAWNS: i32 = 4;
+
+/// The monsters that live at a depth. Goblins thin out the deeper you go
+/// while orcs and archers become more common.
+fn add_monsters(table: RandomTable, depth: i32) -> RandomTable {
+    table
+        .add("Goblin", 10 - depth)
+        .add("Orc", 1 + depth)
+        .add("Kobold Archer", depth)
+        .add("Goblin Shaman", depth - 1)
+        .add("Fire Imp", depth - 2)
+        .add("Ice Wraith", depth - 3)
+        .add("Troll", depth - 3)
+        .add("Young Dragon", depth - 5)
+}
+
+/// What can turn up on a level, and how often. The better gear becomes more
+/// common the deeper you go, as do the monsters guarding it.
+fn room_table(depth: i32) -> RandomTable {
+    add_monsters(RandomTable::new(), depth)
+        .add("Health Potion", 7)
+        .add("Rations", 3)
+        .add("Acid Flask", 2)
+        .add("Fire Potion", depth)
+        .add("Dagger", 3)
+        .add("Shield", 3)
+        .add("Poisoned Dagger", depth - 1)
+        .add("Cursed Dagger", 1)
+        .add("Warhammer", depth - 2)
+        .add("Shortbow", 2)
+        .add("Arrows", 3)
+        .add("Magic Missile Scroll", 4)
+        .add("Fireball Scroll", depth)
+        .add("Confusion Scroll", 2 + depth)
+        .add("Magic Mapping Scroll", 2)
+        .add("Teleport Scroll", 2)
+        .add("Summon Wolf Scroll", 1 + depth / 2)
+        .add("Identify Scroll", 3)
+        .add("Bear Trap", 2)
+        .add("Torch", 2)
+        .add("Gold", 6)
+        .add("Shopkeeper", 1)
+        .add(NOTHING, 5)
+}
+
+/// What wanders into a level that's been lingered on: its monsters, without
+/// the loot.
+pub fn wandering_table(depth: i32) -> RandomTable {
+    add_monsters(RandomTable::new(), depth)
+}
+
+/// Rolls a few things from the depth's spawn table and puts each on its own
+/// floor tile inside the room. A room holds at most one pack, and its members
+/// count against what else the room gets.
+pub fn spawn_room(ecs: &mut World, room: &Rect, depth: i32) {
+    let spawn_table = room_table(depth);
+    let mut spawn_points: Vec<(i32, i32, String)> = vec![];
+    let mut pack: Option<(String, i32)> = None;
     {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
-        roll = rng.roll_dice(1, 2);
+        let map = ecs.fetch::<Map>();
+        let mut num_spawns = rng.roll_dice(1, MAX_SPAWNS + 3) + (depth - 1) - 3;
+
+        while num_spawns > 0 {
+            num_spawns -= 1;
+            let mut attempts = 0;
+            while attempts < 20 {
+                attempts += 1;
+                let x = room.x1 + rng.roll_dice(1, i32::abs(room.x2 - room.x1));
+                let y = room.y1 + rng.roll_dice(1, i32::abs(room.y2 - room.y1));
+                let taken = spawn_points.iter().any(|(sx, sy, _)| *sx == x && *sy == y);
+                // Nothing lands where it couldn't be reached
+                if map.tiles[map.xy_idx(x, y)] == TileType::Floor && !taken {
+                    let name = spawn_table.roll(&mut rng);
+                    // Pack animals bring the rest of the pack, if the room
+                    // hasn't got one already
+                    match raws().monster(&name).and_then(|raw| raw.pack.as_ref()) {
+                        Some(size) if pack.is_none() => {
+                            let count = rng.range(size.min, size.max + 1);
+                            num_spawns -= count - 1;
+                            pack = Some((name, count));
+                        }
+                        Some(_) => {}
+                        None => spawn_points.push((x, y, name)),
+                    }
+                    break;
+                }
+            }
+        }
     }
 
-    match roll {
-        1 => orc(ecs, x, y),
-        _ => goblin(ecs, x, y),
+    for (x, y, name) in spawn_points.iter() {
+        spawn_entity(ecs, name, *x, *y);
+    }
+    if let Some((species, count)) = pack {
+        spawn_pack(ecs, room, &species, count);
     }
 }
 
-fn orc(ecs: &mut World, x: i32, y: i32) {
-    monster(ecs, x, y, to_cp437('o'), "Orc");
+/// Puts `count` of `species` together on free floor tiles in `room`, giving
+/// back those there was space for. They're all of one kind, so they share a
+/// faction, and the first of them leads the rest.
+pub fn spawn_pack(ecs: &mut World, room: &Rect, species: &str, count: i32) -> Vec<Entity> {
+    let mut spots: Vec<(i32, i32)> = Vec::new();
+    {
+        let map = ecs.fetch::<Map>();
+        let positions = ecs.read_storage::<Position>();
+        let blockers = ecs.read_storage::<BlocksTile>();
+        let mut taken: Vec<(i32, i32)> = (&positions, &blockers)
+            .join()
+            .map(|(pos, _blocks)| (pos.x, pos.y))
+            .collect();
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+
+        let mut attempts = 0;
+        while (spots.len() as i32) < count && attempts < 20 * count {
+            attempts += 1;
+            let x = room.x1 + rng.roll_dice(1, i32::abs(room.x2 - room.x1));
+            let y = room.y1 + rng.roll_dice(1, i32::abs(room.y2 - room.y1));
+            if map.tiles[map.xy_idx(x, y)] == TileType::Floor && !taken.contains(&(x, y)) {
+                taken.push((x, y));
+                spots.push((x, y));
+            }
+        }
+    }
+
+    let pack: Vec<Entity> = spots
+        .into_iter()
+        .filter_map(|(x, y)| spawn_entity(ecs, species, x, y))
+        .collect();
+    if let Some((leader, members)) = pack.split_first() {
+        let mut pack_members = ecs.write_storage::<PackMember>();
+        for member in members {
+            pack_members
+                .insert(*member, PackMember { leader: *leader })
+                .expect("Unable to insert pack member");
+        }
+    }
+    pack
 }
 
-fn goblin(ecs: &mut World, x: i32, y: i32) {
-    monster(ecs, x, y, to_cp437('g'), "Goblin");
+/// Places monsters and items on random floor tiles, for levels that have no
+/// rooms to put them in. Keeps clear of the player's starting spot.
+pub fn scatter_monsters(ecs: &mut World, map: &Map, player_x: i32, player_y: i32) {
+    const SPAWN_COUNT: usize = 20;
+    const MIN_PLAYER_DISTANCE: i32 = 10;
+
+    let spawn_table = room_table(ecs.fetch::<Depth>().0);
+    let mut spawn_points: Vec<(i32, i32, String)> = vec![];
+    {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        let mut attempts = 0;
+        while spawn_points.len() < SPAWN_COUNT && attempts < 1000 {
+            attempts += 1;
+            let x = rng.range(1, map.width - 1);
+            let y = rng.range(1, map.height - 1);
+            let idx = map.xy_idx(x, y);
+            let too_close = (x - player_x).abs() + (y - player_y).abs() < MIN_PLAYER_DISTANCE;
+            let taken = spawn_points.iter().any(|(sx, sy, _)| *sx == x && *sy == y);
+            if map.tiles[idx] == TileType::Floor && !too_close && !taken {
+                spawn_points.push((x, y, spawn_table.roll(&mut rng)));
+            }
+        }
+    }
+
+    for (x, y, name) in spawn_points.iter() {
+        spawn_entity(ecs, name, *x, *y);
+    }
 }
 
-fn monster<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: FontCharType, name: S) {
-    ecs.create_entity()
+/// Creates whatever a spawn table entry stands for. Anything that isn't an
+/// item is looked up in the raws; `NOTHING`, or a name nobody knows, leaves
+/// the spot empty and gives `None`.
+pub fn spawn_entity(ecs: &mut World, name: &str, x: i32, y: i32) -> Option<Entity> {
+    match name {
+        "Health Potion" => Some(health_potion(ecs, x, y)),
+        "Rations" => Some(rations(ecs, x, y)),
+        "Acid Flask" => Some(acid_flask(ecs, x, y)),
+        "Fire Potion" => Some(fire_potion(ecs, x, y)),
+        "Fire Puddle" => Some(fire_puddle(ecs, x, y)),
+        "Dagger" => Some(dagger(ecs, x, y)),
+        "Shield" => Some(shield(ecs, x, y)),
+        "Poisoned Dagger" => Some(poisoned_dagger(ecs, x, y)),
+        "Cursed Dagger" => Some(cursed_dagger(ecs, x, y)),
+        "Warhammer" => Some(warhammer(ecs, x, y)),
+        "Shortbow" => Some(shortbow(ecs, x, y)),
+        "Arrows" => Some(arrows(ecs, x, y)),
+        "Magic Missile Scroll" => Some(magic_missile_scroll(ecs, x, y)),
+        "Fireball Scroll" => Some(fireball_scroll(ecs, x, y)),
+        "Confusion Scroll" => Some(confusion_scroll(ecs, x, y)),
+        "Magic Mapping Scroll" => Some(magic_mapping_scroll(ecs, x, y)),
+        "Teleport Scroll" => Some(teleport_scroll(ecs, x, y)),
+        "Summon Wolf Scroll" => Some(summon_wolf_scroll(ecs, x, y)),
+        "Identify Scroll" => Some(identify_scroll(ecs, x, y)),
+        "Bear Trap" => Some(bear_trap(ecs, x, y)),
+        "Torch" => Some(torch(ecs, x, y)),
+        "Gold" => Some(gold_pile(ecs, x, y)),
+        "Shopkeeper" => Some(shopkeeper(ecs, x, y)),
+        "Elder" => Some(elder(ecs, x, y)),
+        _ => spawn_named_entity(raws(), ecs, name, x, y),
+    }
+}
+
+struct SpawnRequest {
+    name: String,
+    x: i32,
+    y: i32,
+    /// How long it fights for the player, if it's on their side at all.
+    ally_turns: Option<i32>,
+}
+
+/// Queue of monsters, and the like, that systems want brought in mid-turn.
+/// Systems can't create entities with all their components themselves, so
+/// `spawn_queued` does it once they're done.
+pub struct SpawnQueue {
+    requests: Vec<SpawnRequest>,
+}
+
+impl SpawnQueue {
+    pub fn new() -> SpawnQueue {
+        SpawnQueue {
+            requests: Vec::new(),
+        }
+    }
+
+    pub fn request(&mut self, name: &str, x: i32, y: i32) {
+        self.requests.push(SpawnRequest {
+            name: name.to_string(),
+            x,
+            y,
+            ally_turns: None,
+        });
+    }
+
+    /// Asks for a monster that joins the player's faction and leaves again
+    /// after `turns` turns.
+    pub fn request_ally(&mut self, name: &str, x: i32, y: i32, turns: i32) {
+        self.requests.push(SpawnRequest {
+            name: name.to_string(),
+            x,
+            y,
+            ally_turns: Some(turns),
+        });
+    }
+}
+
+/// Spawns everything in the `SpawnQueue`. Whatever turns up this way was
+/// called for, so it arrives wide awake and makes for the player, foes to
+/// attack them and allies to tag along.
+pub fn spawn_queued(ecs: &mut World) {
+    let requests = std::mem::take(&mut ecs.write_resource::<SpawnQueue>().requests);
+    let player_entity = *ecs.fetch::<Entity>();
+    let player_pos = *ecs.fetch::<Point>();
+
+    for request in requests {
+        let Some(entity) = spawn_entity(ecs, &request.name, request.x, request.y) else {
+            continue;
+        };
+        if let Some(ai) = ecs.write_storage::<AiState>().get_mut(entity) {
+            ai.mode = AiMode::Hunting {
+                last_known: player_pos,
+                turns: HUNT_TURNS,
+            };
+        }
+
+        if let Some(turns) = request.ally_turns {
+            let player_faction = ecs.read_storage::<Faction>().get(player_entity).cloned();
+            if let Some(faction) = player_faction {
+                ecs.write_storage::<Faction>()
+                    .insert(entity, faction)
+                    .expect("Unable to insert faction");
+            }
+            ecs.write_storage::<Temporary>()
+                .insert(entity, Temporary { turns })
+                .expect("Unable to insert lifetime");
+        }
+    }
+}
+
+/// Gives a monster the next free number among those of its kind, so the log
+/// can tell "Goblin #1" from "Goblin #2".
+fn numbered_name(ecs: &World, base: &str) -> String {
+    let prefix = format!("{} #", base);
+    let highest = ecs
+        .read_storage::<Name>()
+        .join()
+        .filter_map(|name| name.name.strip_prefix(&prefix)?.parse::<i32>().ok())
+        .max()
+        .unwrap_or(0);
+    format!("{}{}", prefix, highest + 1)
+}
+
+/// Builds a monster from its raw definition. Returns `None` if the raws don't
+/// know the name, or if the monster is too big to fit there.
+pub fn spawn_named_entity(
+    raws: &Raws,
+    ecs: &mut World,
+    name: &str,
+    x: i32,
+    y: i32,
+) -> Option<Entity> {
+    let raw = raws.monster(name)?;
+    if let Some(size) = &raw.size {
+        let map = ecs.fetch::<Map>();
+        if !footprint(x, y, Some(size))
+            .iter()
+            .all(|tile| map.is_open_tile(tile.x, tile.y))
+        {
+            return None;
+        }
+    }
+    // There's only ever the one of each boss
+    let unique_name = if raw.boss.is_some() {
+        raw.name.clone()
+    } else {
+        numbered_name(ecs, &raw.name)
+    };
+    // A third of the dungeon is napping, and bosses wait in their lairs
+    // until disturbed
+    let mode = if raw.boss.is_some()
+        || ecs
+            .write_resource::<RandomNumberGenerator>()
+            .roll_dice(1, 3)
+            == 1
+    {
+        AiMode::Sleeping
+    } else {
+        AiMode::Wandering
+    };
+    // Tougher the deeper down, and the harder the game was set to
+    let (max_hp, power) = {
+        let difficulty = ecs.fetch::<Difficulty>();
+        let depth = ecs.fetch::<Depth>().0;
+        (
+            difficulty.scaled(raw.stats.max_hp, depth),
+            difficulty.scaled(raw.stats.power, depth),
+        )
+    };
+
+    let mut builder = ecs
+        .create_entity()
         .with(Position { x, y })
         .with(Renderable {
-            glyph,
-            fg: RGB::named(RED),
+            glyph: to_cp437(raw.glyph),
+            fg: RGB::from_hex(&raw.fg).expect("Invalid monster color"),
             bg: FLOOR_COLOR,
+            render_order: 1,
         })
         .with(FieldOfView {
             visible_tiles: vec![],
-            range: 8,
+            range: raw.vision_range,
             dirty: true,
         })
         .with(Monster {})
-        .with(Name {
-            name: name.to_string(),
+        .with(AiState { mode })
+        .with(Name { name: unique_name })
+        .with(Faction {
+            name: raw.faction.clone(),
         })
-        .with(BlocksTile {})
         .with(CombatStats {
-            max_hp: 16,
-            hp: 16,
-            defense: 1,
-            power: 4,
+            max_hp,
+            hp: max_hp,
+            defense: raw.stats.defense,
+            power,
+            attack: raw.stats.attack,
+            dodge: raw.stats.dodge,
+        })
+        .with(Experience { level: 1, xp: 0 })
+        .with(Initiative {
+            current: 0,
+            speed: raw.speed,
+        });
+
+    if raw.blocks_tile {
+        builder = builder.with(BlocksTile {});
+    }
+    if raw.opens_doors != Some(false) {
+        builder = builder.with(DoorUser {});
+    }
+    if let Some(threshold) = raw.flee_threshold {
+        builder = builder.with(CanFlee {
+            threshold,
+            fleeing: false,
+        });
+    }
+    if let Some(ranged) = &raw.ranged {
+        builder = builder.with(RangedAttacker {
+            range: ranged.range,
+            damage: ranged.damage,
+            confusion: ranged.confusion,
+        });
+        if let Some(projectile) = &ranged.projectile {
+            builder = builder.with(Projectile {
+                glyph: to_cp437(projectile.glyph),
+                fg: RGB::from_hex(&projectile.fg).expect("Invalid projectile color"),
+            });
+        }
+    }
+    if raw.resists.is_some() || raw.vulnerable.is_some() {
+        builder = builder.with(Resistances {
+            resists: raw.resists.clone().unwrap_or_default(),
+            vulnerable: raw.vulnerable.clone().unwrap_or_default(),
+        });
+    }
+    if let Some(regen) = &raw.regeneration {
+        builder = builder.with(Regeneration {
+            amount: regen.amount,
+            interval: regen.interval,
+            counter: 0,
+        });
+    }
+    if let Some(distance) = raw.knockback {
+        builder = builder.with(Knockback { distance });
+    }
+    if let Some(size) = &raw.size {
+        builder = builder.with(size.clone());
+    }
+    if let Some(boss) = &raw.boss {
+        builder = builder.with(Boss {
+            minion: boss.minion.clone(),
+            loot: boss.loot.clone(),
+            cooldown: 0,
+        });
+    }
+
+    Some(builder.marked::<SimpleMarker<SerializeMe>>().build())
+}
+
+pub fn health_potion(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('¡'),
+            fg: RGB::named(MAGENTA),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Health Potion".to_string(),
+        })
+        .with(Item {})
+        .with(Unidentified {
+            name: UNIDENTIFIED_POTION.to_string(),
+        })
+        .with(Weight { value: 0.5 })
+        .with(Price { gold: 15 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(Potion { puddle: None })
+        .with(ProvidesHealing { heal_amount: 8 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn rations(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('%'),
+            fg: RGB::named(GREEN),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Rations".to_string(),
+        })
+        .with(Item {})
+        .with(Weight { value: 0.5 })
+        .with(Price { gold: 5 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(ProvidesFood {})
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+/// Burns whoever drinks it, or whoever is splashed when it's thrown, and
+/// leaves flames where it breaks.
+pub fn fire_potion(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('¡'),
+            fg: RGB::named(ORANGE),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Fire Potion".to_string(),
+        })
+        .with(Item {})
+        .with(Unidentified {
+            name: UNIDENTIFIED_POTION.to_string(),
+        })
+        .with(Weight { value: 0.5 })
+        .with(Price { gold: 20 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(Potion {
+            puddle: Some("Fire Puddle".to_string()),
+        })
+        .with(InflictsDamage {
+            damage: 6,
+            kind: DamageType::Fire,
+        })
+        .with(InflictsStatus {
+            effect: StatusEffect {
+                kind: EffectKind::Burning,
+                turns: 3,
+                magnitude: 2,
+            },
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+/// Flames left where a fire potion broke, setting alight whoever walks in
+/// before they die down.
+pub fn fire_puddle(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('░'),
+            fg: RGB::named(ORANGE),
+            bg: FLOOR_COLOR,
+            render_order: 3,
+        })
+        .with(Name {
+            name: "Fire Puddle".to_string(),
+        })
+        .with(EntryTrigger {})
+        .with(Temporary { turns: 3 })
+        .with(InflictsStatus {
+            effect: StatusEffect {
+                kind: EffectKind::Burning,
+                turns: 3,
+                magnitude: 2,
+            },
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+/// Shatters wherever it's thrown, burning whoever is there.
+pub fn acid_flask(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('!'),
+            fg: RGB::named(GREEN),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Acid Flask".to_string(),
+        })
+        .with(Item {})
+        .with(Weight { value: 0.5 })
+        .with(Price { gold: 15 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(InflictsDamage {
+            damage: 10,
+            kind: DamageType::Physical,
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437(')'),
+            fg: RGB::named(CYAN),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Magic Missile Scroll".to_string(),
+        })
+        .with(Item {})
+        .with(Unidentified {
+            name: UNIDENTIFIED_SCROLL.to_string(),
+        })
+        .with(Weight { value: 0.2 })
+        .with(Price { gold: 20 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(Ranged { range: 6 })
+        .with(Projectile {
+            glyph: to_cp437('*'),
+            fg: RGB::named(CYAN),
+        })
+        .with(InflictsDamage {
+            damage: 8,
+            kind: DamageType::Physical,
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn fireball_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437(')'),
+            fg: RGB::named(ORANGE),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Fireball Scroll".to_string(),
+        })
+        .with(Item {})
+        .with(Unidentified {
+            name: UNIDENTIFIED_SCROLL.to_string(),
+        })
+        .with(Weight { value: 0.2 })
+        .with(Price { gold: 40 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(Ranged { range: 6 })
+        .with(Projectile {
+            glyph: to_cp437('*'),
+            fg: RGB::named(ORANGE),
+        })
+        .with(InflictsDamage {
+            damage: 20,
+            kind: DamageType::Fire,
+        })
+        .with(AreaOfEffect { radius: 3 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn confusion_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437(')'),
+            fg: RGB::named(PINK),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Confusion Scroll".to_string(),
+        })
+        .with(Item {})
+        .with(Unidentified {
+            name: UNIDENTIFIED_SCROLL.to_string(),
+        })
+        .with(Weight { value: 0.2 })
+        .with(Price { gold: 30 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(Ranged { range: 6 })
+        .with(Confusion { turns: 4 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn magic_mapping_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437(')'),
+            fg: RGB::named(LIGHT_BLUE),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Magic Mapping Scroll".to_string(),
+        })
+        .with(Item {})
+        .with(Unidentified {
+            name: UNIDENTIFIED_SCROLL.to_string(),
+        })
+        .with(Weight { value: 0.2 })
+        .with(Price { gold: 30 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(ProvidesMapping {})
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn teleport_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437(')'),
+            fg: RGB::named(WHITE),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Teleport Scroll".to_string(),
+        })
+        .with(Item {})
+        .with(Unidentified {
+            name: UNIDENTIFIED_SCROLL.to_string(),
+        })
+        .with(Weight { value: 0.2 })
+        .with(Price { gold: 25 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(ProvidesTeleport { random: true })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn summon_wolf_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437(')'),
+            fg: RGB::named(GREEN),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Summon Wolf Scroll".to_string(),
+        })
+        .with(Item {})
+        .with(Unidentified {
+            name: UNIDENTIFIED_SCROLL.to_string(),
+        })
+        .with(Weight { value: 0.2 })
+        .with(Price { gold: 35 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(ProvidesSummon {
+            creature: "Spirit Wolf".to_string(),
+            turns: 30,
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn identify_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437(')'),
+            fg: RGB::named(YELLOW),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Identify Scroll".to_string(),
+        })
+        .with(Item {})
+        .with(Unidentified {
+            name: UNIDENTIFIED_SCROLL.to_string(),
+        })
+        .with(Weight { value: 0.2 })
+        .with(Price { gold: 20 })
+        .with(Consumable {})
+        .with(Stackable { count: 1 })
+        .with(ProvidesIdentify {})
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn dagger(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('/'),
+            fg: RGB::named(CYAN),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Dagger".to_string(),
+        })
+        .with(Item {})
+        .with(Weight { value: 1.0 })
+        .with(Price { gold: 20 })
+        .with(Equippable {
+            slot: EquipmentSlot::Melee,
+        })
+        .with(MeleePowerBonus { power: 2 })
+        .with(CritBonus { chance: 5 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+/// Passes for a plain dagger until someone takes it in hand, and then won't
+/// let go.
+pub fn cursed_dagger(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('/'),
+            fg: RGB::named(CYAN),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Dagger".to_string(),
+        })
+        .with(Item {})
+        .with(Weight { value: 1.0 })
+        .with(Price { gold: 20 })
+        .with(Equippable {
+            slot: EquipmentSlot::Melee,
+        })
+        .with(MeleePowerBonus { power: -1 })
+        .with(Cursed {})
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn poisoned_dagger(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('/'),
+            fg: RGB::named(GREEN),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Poisoned Dagger".to_string(),
+        })
+        .with(Item {})
+        .with(Weight { value: 1.0 })
+        .with(Price { gold: 35 })
+        .with(Equippable {
+            slot: EquipmentSlot::Melee,
+        })
+        .with(MeleePowerBonus { power: 1 })
+        .with(InflictsStatus {
+            effect: StatusEffect {
+                kind: EffectKind::Poison,
+                turns: 3,
+                magnitude: 2,
+            },
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn warhammer(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('/'),
+            fg: RGB::named(LIGHT_GRAY),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Warhammer".to_string(),
+        })
+        .with(Item {})
+        .with(Weight { value: 5.0 })
+        .with(Price { gold: 40 })
+        .with(Equippable {
+            slot: EquipmentSlot::Melee,
+        })
+        .with(MeleePowerBonus { power: 3 })
+        .with(Knockback { distance: 1 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn shortbow(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('}'),
+            fg: RGB::named(BROWN1),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Shortbow".to_string(),
+        })
+        .with(Item {})
+        .with(Weight { value: 2.0 })
+        .with(Price { gold: 30 })
+        .with(Equippable {
+            slot: EquipmentSlot::Ranged,
+        })
+        .with(RangedWeapon {
+            range: 7,
+            damage: 5,
+            ammo: "arrow".to_string(),
+        })
+        .with(Projectile {
+            glyph: to_cp437('-'),
+            fg: RGB::named(BURLYWOOD),
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn arrows(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('|'),
+            fg: RGB::named(BURLYWOOD),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Arrows".to_string(),
         })
+        .with(Item {})
+        .with(Weight { value: 0.5 })
+        .with(Price { gold: 10 })
+        .with(Ammo {
+            kind: "arrow".to_string(),
+            count: 10,
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn shield(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('('),
+            fg: RGB::named(CYAN),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Shield".to_string(),
+        })
+        .with(Item {})
+        .with(Weight { value: 6.0 })
+        .with(Price { gold: 25 })
+        .with(Equippable {
+            slot: EquipmentSlot::Shield,
+        })
+        .with(DefenseBonus { defense: 1 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn torch(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('¥'),
+            fg: RGB::named(ORANGE),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Torch".to_string(),
+        })
+        .with(Item {})
+        .with(Weight { value: 1.0 })
+        .with(Price { gold: 10 })
+        .with(LightSource {
+            range: 4,
+            color: RGB::from_f32(0.8, 0.5, 0.1),
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+/// What shopkeepers stock their shelves from
+fn stock_table() -> RandomTable {
+    RandomTable::new()
+        .add("Health Potion", 4)
+        .add("Rations", 3)
+        .add("Acid Flask", 1)
+        .add("Dagger", 2)
+        .add("Shield", 2)
+        .add("Warhammer", 1)
+        .add("Shortbow", 1)
+        .add("Arrows", 2)
+        .add("Magic Missile Scroll", 2)
+        .add("Fireball Scroll", 1)
+        .add("Confusion Scroll", 1)
+        .add("Magic Mapping Scroll", 1)
+        .add("Teleport Scroll", 1)
+        .add("Summon Wolf Scroll", 1)
+        .add("Identify Scroll", 2)
+        .add("Torch", 2)
+}
+
+/// Someone happy to trade instead of fight. Bumping into them opens their
+/// shop; their wares are in their inventory until bought.
+pub fn shopkeeper(ecs: &mut World, x: i32, y: i32) -> Entity {
+    const STOCK_SIZE: i32 = 6;
+
+    let shopkeeper = ecs
+        .create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('@'),
+            fg: RGB::named(GOLD),
+            bg: FLOOR_COLOR,
+            render_order: 1,
+        })
+        .with(Name {
+            name: "Shopkeeper".to_string(),
+        })
+        .with(BlocksTile {})
+        .with(Vendor {})
+        .marked::<SimpleMarker<SerializeMe>>()
         .build();
+
+    let table = stock_table();
+    for _ in 0..STOCK_SIZE {
+        let name = table.roll(&mut ecs.write_resource::<RandomNumberGenerator>());
+        if let Some(item) = spawn_entity(ecs, &name, x, y) {
+            ecs.write_storage::<Position>().remove(item);
+            put_in_pack(ecs, shopkeeper, item);
+        }
+    }
+
+    shopkeeper
+}
+
+/// The town's elder, who has a word for anyone passing by.
+pub fn elder(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let lines = [
+        "Another one headed for the stairs, then.",
+        "Nobody who went down has come back up to tell of the bottom.",
+        "Buy what you can from the shopkeeper. You'll want it.",
+    ];
+
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('@'),
+            fg: RGB::named(LIGHT_BLUE),
+            bg: FLOOR_COLOR,
+            render_order: 1,
+        })
+        .with(Name {
+            name: "Elder".to_string(),
+        })
+        .with(Faction {
+            name: "Townsfolk".to_string(),
+        })
+        .with(BlocksTile {})
+        .with(Dialogue {
+            lines: lines.iter().map(|line| line.to_string()).collect(),
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+/// A handful of coins. Deeper levels have bigger piles.
+pub fn gold_pile(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let amount = {
+        let depth = ecs.fetch::<Depth>().0;
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        rng.roll_dice(depth + 1, 6)
+    };
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('$'),
+            fg: RGB::named(GOLD),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Gold".to_string(),
+        })
+        .with(Item {})
+        .with(Gold { amount })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn key(ecs: &mut World, x: i32, y: i32, id: u32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('⌐'),
+            fg: RGB::named(YELLOW),
+            bg: FLOOR_COLOR,
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Key".to_string(),
+        })
+        .with(Item {})
+        .with(Weight { value: 0.1 })
+        .with(Key { id })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+/// The remains of `name`, left where they fell. Nothing stops anyone from
+/// walking over them.
+pub fn corpse(ecs: &mut World, x: i32, y: i32, name: &str, fg: RGB) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('%'),
+            fg,
+            bg: FLOOR_COLOR,
+            render_order: 3,
+        })
+        .with(Name {
+            name: format!("{} corpse", name),
+        })
+        .with(Corpse {})
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+pub fn bear_trap(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('^'),
+            fg: RGB::named(RED),
+            bg: FLOOR_COLOR,
+            render_order: 3,
+        })
+        .with(Name {
+            name: "Bear Trap".to_string(),
+        })
+        .with(Hidden {})
+        .with(EntryTrigger {})
+        .with(SingleActivation {})
+        .with(InflictsDamage {
+            damage: 6,
+            kind: DamageType::Physical,
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
 }
@@ -0,0 +1,70 @@
+use bracket_lib::prelude::Point;
+use specs::prelude::*;
+
+use crate::gamelog::{GameLog, LogCategory};
+use crate::{Cooldowns, Mana, Name, Spell, WantsToUseItem};
+
+/// Whether the spell's caster can cast it right now: it has to be done
+/// recharging and they need the mana. Logs why not if they can't.
+pub fn can_cast(ecs: &World, spell: Entity) -> bool {
+    let spells = ecs.read_storage::<Spell>();
+    let names = ecs.read_storage::<Name>();
+    let manas = ecs.read_storage::<Mana>();
+    let cooldowns = ecs.read_storage::<Cooldowns>();
+    let mut log = ecs.fetch_mut::<GameLog>();
+    let (Some(spell), Some(name)) = (spells.get(spell), names.get(spell)) else {
+        return false;
+    };
+
+    let recharging = cooldowns
+        .get(spell.owner)
+        .and_then(|cooldowns| cooldowns.remaining.get(&name.name));
+    if let Some(turns) = recharging {
+        log.add(
+            LogCategory::Item,
+            format!("That ability is recharging ({} turns).", turns),
+        );
+        return false;
+    }
+
+    let affordable = manas
+        .get(spell.owner)
+        .is_some_and(|mana| mana.current >= spell.mana_cost);
+    if !affordable {
+        log.add(LogCategory::Item, "Not enough mana.");
+    }
+    affordable
+}
+
+/// Spends the mana for a spell and has it go off this turn, aimed at
+/// `target` if it needs aiming. Spells work like items that never get used
+/// up, so `ItemUseSystem` takes care of their effects. Gives `false`, and
+/// does nothing, if the caster can't cast it.
+pub fn cast_spell(ecs: &mut World, spell: Entity, target: Option<Point>) -> bool {
+    if !can_cast(ecs, spell) {
+        return false;
+    }
+
+    let (caster, cost, cooldown) = {
+        let spells = ecs.read_storage::<Spell>();
+        let spell = spells.get(spell).unwrap();
+        (spell.owner, spell.mana_cost, spell.cooldown)
+    };
+    ecs.write_storage::<Mana>().get_mut(caster).unwrap().current -= cost;
+    if cooldown > 0 {
+        let name = ecs.read_storage::<Name>().get(spell).unwrap().name.clone();
+        if let Some(cooldowns) = ecs.write_storage::<Cooldowns>().get_mut(caster) {
+            cooldowns.remaining.insert(name, cooldown);
+        }
+    }
+    ecs.write_storage::<WantsToUseItem>()
+        .insert(
+            caster,
+            WantsToUseItem {
+                item: spell,
+                target,
+            },
+        )
+        .expect("Unable to insert intent");
+    true
+}
@@ -0,0 +1,126 @@
+use crate::damage_system::after_resistance;
+use crate::gamelog::{GameLog, LogCategory};
+use crate::particle_system::ParticleBuilder;
+use crate::{
+    Confusion, EffectKind, MyTurn, Player, Position, Resistances, RunState, StatusEffect,
+    SufferDamage,
+};
+use bracket_lib::prelude::{to_cp437, BLACK, GREEN, ORANGE, RGB, RGBA};
+use specs::prelude::*;
+
+/// Deals the damage of every lingering status effect, one tick per turn of
+/// whoever suffers it, and removes the ones that have run out. Like hazards,
+/// the player's effects tick on `PlayerTurn` and everyone else's on
+/// `MonsterTurn`. The player's confusion wears off here too; monsters shake
+/// off theirs as they try to act.
+pub struct StatusEffectSystem {}
+
+impl<'a> System<'a> for StatusEffectSystem {
+    type SystemData = (
+        ReadExpect<'a, RunState>,
+        ReadStorage<'a, MyTurn>,
+        Entities<'a>,
+        WriteStorage<'a, StatusEffect>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, SufferDamage>,
+        WriteExpect<'a, ParticleBuilder>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, Confusion>,
+        ReadStorage<'a, Resistances>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            runstate,
+            my_turn,
+            entities,
+            mut effects,
+            players,
+            positions,
+            mut inflict_damage,
+            mut particle_builder,
+            mut log,
+            mut confused,
+            resistances,
+        ) = data;
+
+        let players_turn_ended = match *runstate {
+            RunState::PlayerTurn => true,
+            RunState::MonsterTurn => false,
+            _ => return,
+        };
+
+        let mut worn_off = Vec::new();
+        for (entity, effect) in (&entities, &mut effects).join() {
+            // Monsters only end a turn if they got to take one
+            let is_player = players.contains(entity);
+            if is_player != players_turn_ended || !(is_player || my_turn.contains(entity)) {
+                continue;
+            }
+
+            SufferDamage::new_damage(
+                &mut inflict_damage,
+                entity,
+                effect.magnitude,
+                effect.kind.damage_type(),
+                false,
+            );
+            if is_player {
+                log.add(
+                    LogCategory::Combat,
+                    format!(
+                        "You are {}, losing {} hp.",
+                        effect.kind.adjective(),
+                        after_resistance(
+                            effect.magnitude,
+                            effect.kind.damage_type(),
+                            resistances.get(entity)
+                        )
+                    ),
+                );
+            }
+
+            if let Some(pos) = positions.get(entity) {
+                let fg = match effect.kind {
+                    EffectKind::Poison => RGB::named(GREEN),
+                    EffectKind::Burning => RGB::named(ORANGE),
+                };
+                particle_builder.request(
+                    pos.x,
+                    pos.y,
+                    fg,
+                    RGBA::named(BLACK),
+                    to_cp437('*'),
+                    200.0,
+                );
+            }
+
+            effect.turns -= 1;
+            if effect.turns < 1 {
+                worn_off.push(entity);
+            }
+        }
+
+        for entity in worn_off {
+            effects.remove(entity);
+            if players.contains(entity) {
+                log.add(LogCategory::General, "You feel better.");
+            }
+        }
+
+        if players_turn_ended {
+            let clear_headed: Vec<Entity> = (&entities, &mut confused, &players)
+                .join()
+                .filter_map(|(entity, confusion, _player)| {
+                    confusion.turns -= 1;
+                    (confusion.turns < 1).then_some(entity)
+                })
+                .collect();
+            for entity in clear_headed {
+                confused.remove(entity);
+                log.add(LogCategory::General, "Your head clears.");
+            }
+        }
+    }
+}
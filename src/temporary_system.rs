@@ -0,0 +1,42 @@
+use crate::gamelog::{GameLog, LogCategory};
+use crate::{Map, Name, Position, RunState, Temporary};
+use specs::prelude::*;
+
+/// Counts down whatever is only around for a while, once per player turn,
+/// and sends it away when its time is up.
+pub struct TemporarySystem {}
+
+impl<'a> System<'a> for TemporarySystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Temporary>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Name>,
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, GameLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut temporaries, positions, names, map, runstate, mut log) = data;
+
+        if *runstate != RunState::PlayerTurn {
+            return;
+        }
+
+        for (entity, temporary) in (&entities, &mut temporaries).join() {
+            temporary.turns -= 1;
+            if temporary.turns > 0 {
+                continue;
+            }
+
+            let seen = positions
+                .get(entity)
+                .is_some_and(|pos| map.visible_tiles[map.xy_idx(pos.x, pos.y)]);
+            if let (true, Some(name)) = (seen, names.get(entity)) {
+                log.add(LogCategory::General, format!("{} fades away.", name.name));
+            }
+            entities.delete(entity).expect("Unable to delete");
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::gamelog::{GameLog, LogCategory};
+use crate::{EntityMoved, Hidden, Position};
+use bracket_lib::prelude::RandomNumberGenerator;
+use specs::prelude::*;
+
+/// Odds, one in this many, of noticing each hidden thing next to the player
+/// after a step.
+const DETECTION_CHANCE: i32 = 4;
+
+/// Gives the player a chance to spot hidden things next to them whenever they
+/// move.
+pub struct TrapDetectionSystem {}
+
+impl<'a> System<'a> for TrapDetectionSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        ReadStorage<'a, EntityMoved>,
+        ReadStorage<'a, Position>,
+        Entities<'a>,
+        WriteStorage<'a, Hidden>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        WriteExpect<'a, GameLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (player_entity, entity_moved, positions, entities, mut hidden, mut rng, mut log) = data;
+
+        if !entity_moved.contains(*player_entity) {
+            return;
+        }
+        let Some(player_pos) = positions.get(*player_entity) else {
+            return;
+        };
+
+        let mut spotted: Vec<Entity> = vec![];
+        for (entity, _hidden, pos) in (&entities, &hidden, &positions).join() {
+            let adjacent = (pos.x - player_pos.x).abs() <= 1 && (pos.y - player_pos.y).abs() <= 1;
+            if adjacent && rng.roll_dice(1, DETECTION_CHANCE) == 1 {
+                spotted.push(entity);
+            }
+        }
+
+        for entity in spotted.iter() {
+            hidden.remove(*entity);
+            log.add(LogCategory::General, "You spotted a trap!");
+        }
+    }
+}
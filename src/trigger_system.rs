@@ -0,0 +1,120 @@
+use crate::gamelog::{refer_to, GameLog, LogCategory};
+use crate::particle_system::ParticleBuilder;
+use crate::{
+    EntityMoved, EntryTrigger, Hidden, InflictsDamage, InflictsStatus, Map, Name, Player, Position,
+    SingleActivation, StatusEffect, SufferDamage,
+};
+use bracket_lib::prelude::{to_cp437, BLACK, ORANGE, RGB, RGBA};
+use specs::prelude::*;
+
+/// Sets off whatever is waiting on the tile of anyone who moved this turn,
+/// like traps or burning puddles. A trap that goes off is no longer hidden,
+/// and single-use ones are removed.
+pub struct TriggerSystem {}
+
+impl<'a> System<'a> for TriggerSystem {
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        Entities<'a>,
+        WriteStorage<'a, EntityMoved>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, EntryTrigger>,
+        WriteStorage<'a, Hidden>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, InflictsDamage>,
+        ReadStorage<'a, SingleActivation>,
+        WriteStorage<'a, SufferDamage>,
+        WriteExpect<'a, ParticleBuilder>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, InflictsStatus>,
+        WriteStorage<'a, StatusEffect>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            map,
+            entities,
+            mut entity_moved,
+            positions,
+            entry_triggers,
+            mut hidden,
+            names,
+            players,
+            inflicts_damage,
+            single_activations,
+            mut inflict_damage,
+            mut particle_builder,
+            mut log,
+            inflicts_status,
+            mut status_effects,
+        ) = data;
+
+        let mut used_up: Vec<Entity> = vec![];
+        for (entity, _moved, pos) in (&entities, &entity_moved, &positions).join() {
+            let idx = map.xy_idx(pos.x, pos.y);
+            for trap in map.tile_content[idx].iter() {
+                if *trap == entity || !entry_triggers.contains(*trap) {
+                    continue;
+                }
+
+                // Only what was hidden is set off; anything else is walked
+                // into with eyes open
+                let was_hidden = hidden.remove(*trap).is_some();
+                let is_player = players.contains(entity);
+                if let (Some(name), Some(trap_name)) = (names.get(entity), names.get(*trap)) {
+                    if is_player || map.visible_tiles[idx] {
+                        let verb = match (was_hidden, is_player) {
+                            (true, true) => "set off",
+                            (true, false) => "sets off",
+                            (false, true) => "step into",
+                            (false, false) => "steps into",
+                        };
+                        log.add(
+                            LogCategory::Combat,
+                            format!(
+                                "{} {} the {}!",
+                                refer_to(name, is_player, true),
+                                verb,
+                                trap_name.name
+                            ),
+                        );
+                    }
+                }
+
+                if let Some(damage) = inflicts_damage.get(*trap) {
+                    SufferDamage::new_damage(
+                        &mut inflict_damage,
+                        entity,
+                        damage.damage,
+                        damage.kind,
+                        false,
+                    );
+                    particle_builder.request(
+                        pos.x,
+                        pos.y,
+                        RGB::named(ORANGE),
+                        RGBA::named(BLACK),
+                        to_cp437('‼'),
+                        200.0,
+                    );
+                }
+
+                if let Some(inflicts) = inflicts_status.get(*trap) {
+                    status_effects
+                        .insert(entity, inflicts.effect.clone())
+                        .expect("Unable to insert status");
+                }
+
+                if single_activations.contains(*trap) {
+                    used_up.push(*trap);
+                }
+            }
+        }
+
+        for trap in used_up.iter() {
+            entities.delete(*trap).expect("Unable to delete trap");
+        }
+        entity_moved.clear();
+    }
+}
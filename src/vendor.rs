@@ -0,0 +1,112 @@
+use specs::prelude::*;
+
+use crate::gamelog::{GameLog, LogCategory};
+use crate::inventory_system::{
+    carried_weight, carry_capacity, item_name, put_in_pack, split_stack, KnownItems,
+};
+use crate::{
+    CombatStats, Equipped, InInventory, Name, Price, Stackable, Unidentified, Wallet, Weight,
+};
+
+/// What a shopkeeper pays for something they'd sell for `price`.
+pub fn sell_price(price: i32) -> i32 {
+    i32::max(1, price / 2)
+}
+
+/// Moves one of an item from the shopkeeper's shelves into the player's pack,
+/// as long as the player can pay for it and carry it.
+pub fn buy_item(ecs: &mut World, vendor: Entity, item: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let Some(cost) = buyable(ecs, vendor, player_entity, item) else {
+        return;
+    };
+
+    let item = split_stack(ecs, item);
+    let name = item_name(
+        &ecs.read_storage::<Name>(),
+        &ecs.read_storage::<Unidentified>(),
+        &ecs.fetch::<KnownItems>(),
+        item,
+    );
+    if let Some(wallet) = ecs.write_storage::<Wallet>().get_mut(player_entity) {
+        wallet.gold -= cost;
+    }
+    put_in_pack(ecs, player_entity, item);
+    ecs.fetch_mut::<GameLog>().add(
+        LogCategory::Item,
+        format!("You buy the {} for {} gold.", name, cost),
+    );
+}
+
+/// What the player would pay for one of `item`, if it's on the shopkeeper's
+/// shelves and the player can afford it and carry it. Says why not otherwise.
+fn buyable(ecs: &World, vendor: Entity, player_entity: Entity, item: Entity) -> Option<i32> {
+    let backpack = ecs.read_storage::<InInventory>();
+    let wallets = ecs.read_storage::<Wallet>();
+    let prices = ecs.read_storage::<Price>();
+    let weights = ecs.read_storage::<Weight>();
+    let stacks = ecs.read_storage::<Stackable>();
+    let mut log = ecs.fetch_mut::<GameLog>();
+
+    if backpack.get(item).is_none_or(|pack| pack.owner != vendor) {
+        return None;
+    }
+    let (Some(price), Some(wallet)) = (prices.get(item), wallets.get(player_entity)) else {
+        return None;
+    };
+    if wallet.gold < price.gold {
+        log.add(LogCategory::Item, "You can't afford that.");
+        return None;
+    }
+    let carried = carried_weight(
+        player_entity,
+        &ecs.entities(),
+        &backpack,
+        &ecs.read_storage::<Equipped>(),
+        &weights,
+        &stacks,
+    );
+    let capacity = ecs
+        .read_storage::<CombatStats>()
+        .get(player_entity)
+        .map_or(0.0, carry_capacity);
+    if carried + weights.get(item).map_or(0.0, |weight| weight.value) > capacity {
+        log.add(LogCategory::Item, "It's too heavy to carry.");
+        return None;
+    }
+    Some(price.gold)
+}
+
+/// Hands one of an item from the player's pack over to the shopkeeper for
+/// half what they'd charge for it.
+pub fn sell_item(ecs: &mut World, vendor: Entity, item: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let owned = ecs
+        .read_storage::<InInventory>()
+        .get(item)
+        .is_some_and(|pack| pack.owner == player_entity);
+    let price = ecs
+        .read_storage::<Price>()
+        .get(item)
+        .map(|price| price.gold);
+    let (true, Some(price)) = (owned, price) else {
+        return;
+    };
+
+    let item = split_stack(ecs, item);
+    let name = item_name(
+        &ecs.read_storage::<Name>(),
+        &ecs.read_storage::<Unidentified>(),
+        &ecs.fetch::<KnownItems>(),
+        item,
+    );
+    let paid = sell_price(price);
+    if let Some(wallet) = ecs.write_storage::<Wallet>().get_mut(player_entity) {
+        wallet.gold += paid;
+    }
+    put_in_pack(ecs, vendor, item);
+    ecs.fetch_mut::<GameLog>().add(
+        LogCategory::Item,
+        format!("You sell the {} for {} gold.", name, paid),
+    );
+}
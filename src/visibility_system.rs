@@ -1,7 +1,12 @@
 use super::{FieldOfView, Position};
-use crate::{field_of_view, Map, Player, Point};
+use crate::{field_of_view, BaseMap, DistanceAlg, Map, Player, Point};
+use num::rational::Ratio;
 use specs::prelude::*;
 
+/// Whether to use `compute_fov_symmetric` instead of bracket-lib's field of
+/// view, which can let one creature see another without being seen back.
+pub const SYMMETRIC_FOV: bool = true;
+
 pub struct VisibilitySystem;
 
 impl<'a> System<'a> for VisibilitySystem {
@@ -22,7 +27,11 @@ impl<'a> System<'a> for VisibilitySystem {
             }
 
             fov.visible_tiles.clear();
-            fov.visible_tiles = field_of_view(Point::new(pos.x, pos.y), fov.range, &*map);
+            fov.visible_tiles = if SYMMETRIC_FOV {
+                compute_fov_symmetric(Point::new(pos.x, pos.y), fov.range, &map)
+            } else {
+                field_of_view(Point::new(pos.x, pos.y), fov.range, &*map)
+            };
             fov.visible_tiles
                 .retain(|p| p.x >= 0 && p.x < map.width && p.y >= 0 && p.y < map.height);
 
@@ -42,3 +51,156 @@ impl<'a> System<'a> for VisibilitySystem {
         }
     }
 }
+
+/// Symmetric shadowcasting, after Albert Ford: a floor tile is only visible if
+/// the line from its center back to the origin is unobstructed too, so if A
+/// can see B, B can see A. Walls are visible whenever any part of them is lit.
+/// Everything returned is within `range`, but may lie off the map.
+pub fn compute_fov_symmetric(origin: Point, range: i32, map: &Map) -> Vec<Point> {
+    let mut visible = vec![origin];
+    for quadrant in Quadrant::ALL {
+        let mut scan = ShadowScan {
+            origin,
+            quadrant,
+            range,
+            map,
+            visible: &mut visible,
+        };
+        scan.scan(Row {
+            depth: 1,
+            start_slope: Ratio::from_integer(-1),
+            end_slope: Ratio::from_integer(1),
+        });
+    }
+
+    visible.sort_by_key(|p| (p.y, p.x));
+    visible.dedup();
+    visible
+}
+
+/// One of the four 90 degree wedges around the origin, named for the direction
+/// it faces.
+#[derive(Copy, Clone)]
+enum Quadrant {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Quadrant {
+    const ALL: [Quadrant; 4] = [
+        Quadrant::North,
+        Quadrant::East,
+        Quadrant::South,
+        Quadrant::West,
+    ];
+
+    /// Turns a (row, column) position inside the quadrant into map
+    /// coordinates.
+    fn transform(self, origin: Point, depth: i32, col: i32) -> Point {
+        match self {
+            Quadrant::North => Point::new(origin.x + col, origin.y - depth),
+            Quadrant::South => Point::new(origin.x + col, origin.y + depth),
+            Quadrant::East => Point::new(origin.x + depth, origin.y + col),
+            Quadrant::West => Point::new(origin.x - depth, origin.y + col),
+        }
+    }
+}
+
+/// The tiles at `depth` steps out from the origin that fall between the two
+/// slopes.
+#[derive(Copy, Clone)]
+struct Row {
+    depth: i32,
+    start_slope: Ratio<i32>,
+    end_slope: Ratio<i32>,
+}
+
+impl Row {
+    fn min_col(&self) -> i32 {
+        // Round ties up
+        (Ratio::from_integer(self.depth) * self.start_slope + Ratio::new(1, 2))
+            .floor()
+            .to_integer()
+    }
+
+    fn max_col(&self) -> i32 {
+        // Round ties down
+        (Ratio::from_integer(self.depth) * self.end_slope - Ratio::new(1, 2))
+            .ceil()
+            .to_integer()
+    }
+
+    fn next(&self) -> Row {
+        Row {
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+
+    /// Whether the center of the tile in column `col` lies inside the row's
+    /// slopes, which is what makes floor visibility symmetric.
+    fn is_symmetric(&self, col: i32) -> bool {
+        let col = Ratio::from_integer(col);
+        let depth = Ratio::from_integer(self.depth);
+        col >= depth * self.start_slope && col <= depth * self.end_slope
+    }
+}
+
+struct ShadowScan<'a> {
+    origin: Point,
+    quadrant: Quadrant,
+    range: i32,
+    map: &'a Map,
+    visible: &'a mut Vec<Point>,
+}
+
+impl ShadowScan<'_> {
+    fn scan(&mut self, mut row: Row) {
+        if row.depth > self.range {
+            return;
+        }
+
+        let mut prev_is_wall: Option<bool> = None;
+        for col in row.min_col()..=row.max_col() {
+            let point = self.quadrant.transform(self.origin, row.depth, col);
+            let is_wall = self.is_blocking(point);
+
+            if (is_wall || row.is_symmetric(col)) && self.in_range(point) {
+                self.visible.push(point);
+            }
+            if prev_is_wall == Some(true) && !is_wall {
+                row.start_slope = slope(row.depth, col);
+            }
+            if prev_is_wall == Some(false) && is_wall {
+                let mut next_row = row.next();
+                next_row.end_slope = slope(row.depth, col);
+                self.scan(next_row);
+            }
+            prev_is_wall = Some(is_wall);
+        }
+
+        if prev_is_wall == Some(false) {
+            self.scan(row.next());
+        }
+    }
+
+    /// The edge of the map blocks sight like a wall would.
+    fn is_blocking(&self, point: Point) -> bool {
+        if point.x < 0 || point.x >= self.map.width || point.y < 0 || point.y >= self.map.height {
+            return true;
+        }
+        self.map.is_opaque(self.map.xy_idx(point.x, point.y))
+    }
+
+    fn in_range(&self, point: Point) -> bool {
+        DistanceAlg::Pythagoras.distance2d(self.origin, point) <= self.range as f32
+    }
+}
+
+/// Slope of the line from the origin to the near edge of the tile in column
+/// `col`.
+fn slope(depth: i32, col: i32) -> Ratio<i32> {
+    Ratio::new(2 * col - 1, 2 * depth)
+}
@@ -0,0 +1,81 @@
+use crate::map::{Depth, TileType, TOWN_DEPTH};
+use crate::random_table::NOTHING;
+use crate::spawner::{wandering_table, SpawnQueue};
+use crate::{Map, Monster, Position, RunState};
+use bracket_lib::prelude::RandomNumberGenerator;
+use specs::prelude::*;
+
+/// Player turns between chances for something new to wander in.
+const WANDERING_SPAWN_INTERVAL: i32 = 50;
+
+/// One in this many of those chances brings a monster.
+const WANDERING_SPAWN_ODDS: i32 = 2;
+
+/// No more wanderers turn up while a level has this many monsters.
+const MAX_MONSTERS_PER_LEVEL: usize = 30;
+
+/// Player turns since something last had the chance to wander in.
+#[derive(Default)]
+pub struct WanderingSpawns {
+    pub turns: i32,
+}
+
+impl WanderingSpawns {
+    pub fn new() -> WanderingSpawns {
+        WanderingSpawns::default()
+    }
+}
+
+/// Every so often, brings a monster from the level's spawn table in
+/// somewhere the player can't see, so a cleared level doesn't stay safe for
+/// long.
+pub struct WanderingSpawnSystem {}
+
+impl<'a> System<'a> for WanderingSpawnSystem {
+    type SystemData = (
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, WanderingSpawns>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, Depth>,
+        WriteExpect<'a, SpawnQueue>,
+        ReadStorage<'a, Monster>,
+        ReadStorage<'a, Position>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (runstate, mut wandering, mut rng, map, depth, mut spawns, monsters, positions) = data;
+
+        // Nothing wanders into the town
+        if *runstate != RunState::PlayerTurn || depth.0 == TOWN_DEPTH {
+            return;
+        }
+
+        wandering.turns += 1;
+        if wandering.turns < WANDERING_SPAWN_INTERVAL {
+            return;
+        }
+        wandering.turns = 0;
+
+        if rng.roll_dice(1, WANDERING_SPAWN_ODDS) != 1
+            || (&monsters, &positions).join().count() >= MAX_MONSTERS_PER_LEVEL
+        {
+            return;
+        }
+
+        let name = wandering_table(depth.0).roll(&mut rng);
+        if name == NOTHING {
+            return;
+        }
+
+        for _ in 0..20 {
+            let x = rng.roll_dice(1, map.width - 2);
+            let y = rng.roll_dice(1, map.height - 2);
+            let idx = map.xy_idx(x, y);
+            if map.tiles[idx] == TileType::Floor && !map.visible_tiles[idx] && !map.blocked[idx] {
+                spawns.request(&name, x, y);
+                return;
+            }
+        }
+    }
+}